@@ -1,5 +0,0 @@
-#include <errno.h>
-
-int get_errno() {
-    return errno;
-}
@@ -18,3 +18,47 @@ Foo baz(int a, float b) {
 
     return f;
 }
+
+typedef struct {
+    char a;
+    int b;
+    short c;
+    double d;
+    unsigned char e;
+} Mixed;
+
+void show_mixed(Mixed m) {
+    printf("sizeof(Mixed) = %zu\n", sizeof(Mixed));
+    printf("%d %d %d %f %u\n", m.a, m.b, m.c, m.d, m.e);
+}
+
+Mixed make_mixed(char a, int b, short c, double d, unsigned char e) {
+    Mixed m;
+    m.a = a;
+    m.b = b;
+    m.c = c;
+    m.d = d;
+    m.e = e;
+
+    return m;
+}
+
+typedef struct __attribute__((aligned(16))) {
+    int x;
+} Aligned;
+
+void show_aligned(Aligned a) {
+    printf("sizeof(Aligned) = %zu\n", sizeof(Aligned));
+    printf("%d\n", a.x);
+}
+
+typedef struct __attribute__((packed)) {
+    char a;
+    int b;
+} Packed;
+
+int packed_sum(Packed* p) {
+    printf("sizeof(Packed) = %zu\n", sizeof(Packed));
+    printf("%d %d\n", p->a, p->b);
+    return p->a + p->b;
+}
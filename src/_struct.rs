@@ -6,9 +6,42 @@ use crate::_type::Type;
 pub struct Struct {
     pub name: String,
 
+    // size and alignment in bytes when stored in memory, laid out the same way C would
     pub size: usize,
+    pub align: usize,
+    // packed structs have no padding between their variables
+    pub packed: bool,
+    // the alignment asked for in the struct definition, if any
+    pub min_align: usize,
 
     pub var_names: Vec<String>,
     pub var_types: Vec<Type>,
+    // slot offsets of each variable within a frame
     pub var_offsets: IndexMap<String, usize>,
+    // byte offsets of each variable within memory
+    pub byte_offsets: IndexMap<String, usize>,
+}
+
+impl Struct {
+    // lays the variables out the way C would, given the size and alignment of each of them
+    pub fn layout(&mut self, fields: &[(usize, usize)]) {
+        self.size = 0;
+        self.align = 1;
+        self.byte_offsets.clear();
+
+        for (i, (size, align)) in fields.iter().enumerate() {
+            // each variable starts at a multiple of its alignment, unless the struct is packed
+            let align = if self.packed { 1 } else { *align };
+
+            self.size = self.size.next_multiple_of(align);
+            self.align = self.align.max(align);
+
+            self.byte_offsets.insert(self.var_names[i].clone(), self.size);
+            self.size += *size;
+        }
+
+        // the size is padded so that every element of an array of the struct is aligned
+        self.align = self.align.max(self.min_align);
+        self.size = self.size.next_multiple_of(self.align);
+    }
 }
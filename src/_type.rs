@@ -20,6 +20,7 @@ pub enum Types {
     TYPE           = 0x0D,
     STRUCT(String) = 0x0E,
     NAME           = 0x0F,
+    HANDLE(String) = 0x10, // destructor
 }
 
 impl Types {
@@ -41,10 +42,33 @@ impl Types {
             0x0D => Types::TYPE,
             0x0E => Types::STRUCT(String::new()),
             0x0F => Types::NAME,
+            0x10 => Types::HANDLE(String::new()),
             _ => panic!("unknown type {:#04x}", typ)
         }
     }
 
+    pub fn to_u8(&self) -> u8 {
+        match self {
+            Types::VOID => 0x00,
+            Types::I8 => 0x01,
+            Types::I16 => 0x02,
+            Types::I32 => 0x03,
+            Types::I64 => 0x04,
+            Types::U8 => 0x05,
+            Types::U16 => 0x06,
+            Types::U32 => 0x07,
+            Types::U64 => 0x08,
+            Types::F16 => 0x09,
+            Types::F32 => 0x0A,
+            Types::F64 => 0x0B,
+            Types::POINTER => 0x0C,
+            Types::TYPE => 0x0D,
+            Types::STRUCT(_) => 0x0E,
+            Types::NAME => 0x0F,
+            Types::HANDLE(_) => 0x10,
+        }
+    }
+
     pub fn get_size(&self) -> usize {
         match self {
             Types::VOID => 0,
@@ -63,6 +87,33 @@ impl Types {
             Types::TYPE => 1,
             Types::STRUCT(_) => 0, // struct does not have a known size
             Types::NAME => 0, // name does not have a known size
+            Types::HANDLE(_) => std::mem::size_of::<usize>(),
+        }
+    }
+
+    // alignment in bytes when laid out in memory, following the platform's C ABI
+    pub fn get_align(&self) -> usize {
+        match self {
+            Types::I64 | Types::U64 => std::mem::align_of::<i64>(),
+            Types::F64 => std::mem::align_of::<f64>(),
+            Types::POINTER | Types::HANDLE(_) => std::mem::align_of::<usize>(),
+            Types::VOID | Types::STRUCT(_) | Types::NAME => 1,
+            _ => self.get_size(),
+        }
+    }
+
+    // returns the bit width and signedness of integer types
+    pub fn get_int_info(&self) -> Option<(u32, bool)> {
+        match self {
+            Types::I8 => Some((8, true)),
+            Types::I16 => Some((16, true)),
+            Types::I32 => Some((32, true)),
+            Types::I64 => Some((64, true)),
+            Types::U8 => Some((8, false)),
+            Types::U16 => Some((16, false)),
+            Types::U32 => Some((32, false)),
+            Types::U64 => Some((64, false)),
+            _ => None,
         }
     }
 }
@@ -97,6 +148,8 @@ impl fmt::Display for Type {
                 Types::TYPE => "type".to_string(),
                 Types::STRUCT(t) => format!("struct({})", t),
                 Types::NAME => "name".to_string(),
+                Types::HANDLE(d) if d.is_empty() => "handle".to_string(),
+                Types::HANDLE(d) => format!("handle({})", d),
             }.as_str();
         }
 
@@ -125,6 +178,8 @@ impl fmt::Display for Types {
             Types::TYPE => "type".to_string(),
             Types::STRUCT(t) => format!("struct({})", t),
             Types::NAME => "name".to_string(),
+            Types::HANDLE(d) if d.is_empty() => "handle".to_string(),
+            Types::HANDLE(d) => format!("handle({})", d),
         }.as_str();
 
         f.write_str(&str)
@@ -139,4 +194,8 @@ impl Type {
     pub fn get_size(&self) -> usize {
         return self.typ[0].get_size();
     }
+
+    pub fn get_align(&self) -> usize {
+        return self.typ[0].get_align();
+    }
 }
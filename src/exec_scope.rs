@@ -1,6 +1,6 @@
-use indexmap::IndexMap;
+use std::sync::atomic::{AtomicBool, Ordering};
 
-use crate::{_type::Types, block::Block, ffi::call_ffi, frame::Frame, func_exists, function::Function, get_extern, get_func, get_struct, get_var, instruction::{Instruction, Opcode}, scope::Scope, set_var, value::{Value, Values}, module_exists};
+use crate::{_type::{Type, Types}, block::Block, ffi::{call_ffi, call_pointer, find_symbol, load_library}, frame::Frame, func_exists, gc, function::Function, get_callee, get_extern, get_struct, get_type_size, get_var, get_var_slot, instruction::{Instruction, Opcode}, intrinsics::get_intrinsic, scope::Scope, set_var, value::{Overflow, Value, Values}};
 
 // instruction macros
 macro_rules! peek {
@@ -22,34 +22,24 @@ macro_rules! peek {
 macro_rules! call {
     ($func:expr, $scope:expr, $global_scope:expr, $stack:expr, $cur_frame:expr, $module_frame:expr, $global_frame:expr, $module:expr) => {
         if func_exists($func, $scope, $global_scope) {
-            let frame_func = get_func($func, $scope, $global_scope, $module_frame, $global_frame, $module);
-            let module = frame_func.0;
-            let frame = frame_func.1;
-            let func = frame_func.2;
-
-            let module_name;
-            if frame != $module_frame {
-                if $module.clone().len() > 0 {
-                    if module_exists(&($module.clone() + "." + &module), $scope, $global_scope) {
-                        module_name = $module.clone() + "." + &module;
-                    } else {
-                        module_name = module;
-                    }
-                } else {
-                    module_name = module;
-                }
-            } else {
-                module_name = module;
-            }
+            let (module_name, frame, func) = get_callee($func, $scope, $global_scope, $module_frame, $global_frame, &$module);
 
             exec_func(&func, $global_scope, $scope, $stack, frame, $global_frame, &module_name);
+        } else if let Some(intrinsic) = get_intrinsic($func) {
+            intrinsic($stack, $cur_frame, $global_frame, $scope, $global_scope);
         } else {
             let func = get_extern($func, $scope, $global_scope);
-            call_ffi(func, $stack, $cur_frame, $global_frame, $scope, $global_scope);
+            call_ffi(func, $stack, $cur_frame, $module_frame, $global_frame, $scope, $global_scope, &$module);
         }
     }
 }
 
+macro_rules! callc {
+    ($addr:expr, $typ:expr, $arg_count:expr, $scope:expr, $global_scope:expr, $stack:expr, $cur_frame:expr, $module_frame:expr, $global_frame:expr, $module:expr) => {
+        call_pointer(&$addr, $typ, &$arg_count, $stack, $cur_frame, $module_frame, $global_frame, $scope, $global_scope, $module);
+    }
+}
+
 macro_rules! add {
     ($a:expr, $b:expr, $out:expr, $scope:expr, $global_scope:expr, $stack:expr, $cur_frame:expr, $module_frame:expr, $global_frame:expr) => {
         let val = $a.val.add(&$b.val);
@@ -82,23 +72,10 @@ macro_rules! modulo {
 }
 
 macro_rules! compare {
-    ($a_val:expr, $b:expr, $op:tt, $cast:tt, $pc:expr, $new_pc:expr, $skip_inc:expr) => {
-        
+    ($a:expr, $b:expr, $op:tt, $pc:expr, $new_pc:expr, $skip_inc:expr) => {
         match $b {
-            Values::SIGNED(b_val) => {
-                if $a_val $op (b_val as $cast) {
-                    $skip_inc = true;
-                    $pc = $new_pc as usize;
-                }
-            }
-            Values::UNSIGNED(b_val) => {
-                if $a_val $op (b_val as $cast) {
-                    $skip_inc = true;
-                    $pc = $new_pc as usize;
-                }
-            }
-            Values::DECIMAL(b_val) => {
-                if $a_val $op (b_val as $cast) {
+            Values::SIGNED(_) | Values::UNSIGNED(_) | Values::DECIMAL(_) | Values::POINTER(_, _) | Values::REFERENCE(_, _, _) | Values::HANDLE(_, _) => {
+                if $a $op $b {
                     $skip_inc = true;
                     $pc = $new_pc as usize;
                 }
@@ -129,9 +106,7 @@ macro_rules! jne {
         get_pc!($c.val, new_pc);
 
         match $a.val {
-            Values::SIGNED(a_val) => compare!(a_val, $b.val, !=, i64, $pc, new_pc, $skip_inc),
-            Values::UNSIGNED(a_val) => compare!(a_val, $b.val, !=, u64, $pc, new_pc, $skip_inc),
-            Values::DECIMAL(a_val) => compare!(a_val, $b.val, !=, f64, $pc, new_pc, $skip_inc),
+            Values::SIGNED(_) | Values::UNSIGNED(_) | Values::DECIMAL(_) | Values::POINTER(_, _) | Values::REFERENCE(_, _, _) | Values::HANDLE(_, _) => compare!($a.val, $b.val, !=, $pc, new_pc, $skip_inc),
             _ => panic!("expected a number for comparison, got `{:?}`", $a.val)
         }
     }
@@ -142,9 +117,7 @@ macro_rules! je {
         get_pc!($c.val, new_pc);
 
         match $a.val {
-            Values::SIGNED(a_val) => compare!(a_val, $b.val, ==, i64, $pc, new_pc, $skip_inc),
-            Values::UNSIGNED(a_val) => compare!(a_val, $b.val, ==, u64, $pc, new_pc, $skip_inc),
-            Values::DECIMAL(a_val) => compare!(a_val, $b.val, ==, f64, $pc, new_pc, $skip_inc),
+            Values::SIGNED(_) | Values::UNSIGNED(_) | Values::DECIMAL(_) | Values::POINTER(_, _) | Values::REFERENCE(_, _, _) | Values::HANDLE(_, _) => compare!($a.val, $b.val, ==, $pc, new_pc, $skip_inc),
             _ => panic!("expected a number for comparison, got `{:?}`", $a.val)
         }
     }
@@ -156,9 +129,7 @@ macro_rules! jge {
         get_pc!($c.val, new_pc);
 
         match $a.val {
-            Values::SIGNED(a_val) => compare!(a_val, $b.val, >=, i64, $pc, new_pc, $skip_inc),
-            Values::UNSIGNED(a_val) => compare!(a_val, $b.val, >=, u64, $pc, new_pc, $skip_inc),
-            Values::DECIMAL(a_val) => compare!(a_val, $b.val, >=, f64, $pc, new_pc, $skip_inc),
+            Values::SIGNED(_) | Values::UNSIGNED(_) | Values::DECIMAL(_) | Values::POINTER(_, _) | Values::REFERENCE(_, _, _) => compare!($a.val, $b.val, >=, $pc, new_pc, $skip_inc),
             _ => panic!("expected a number for comparison, got `{:?}`", $a.val)
         }
     }
@@ -169,9 +140,7 @@ macro_rules! jg {
         get_pc!($c.val, new_pc);
 
         match $a.val {
-            Values::SIGNED(a_val) => compare!(a_val, $b.val, >, i64, $pc, new_pc, $skip_inc),
-            Values::UNSIGNED(a_val) => compare!(a_val, $b.val, >, u64, $pc, new_pc, $skip_inc),
-            Values::DECIMAL(a_val) => compare!(a_val, $b.val, >, f64, $pc, new_pc, $skip_inc),
+            Values::SIGNED(_) | Values::UNSIGNED(_) | Values::DECIMAL(_) | Values::POINTER(_, _) | Values::REFERENCE(_, _, _) => compare!($a.val, $b.val, >, $pc, new_pc, $skip_inc),
             _ => panic!("expected a number for comparison, got `{:?}`", $a.val)
         }
     }
@@ -182,9 +151,7 @@ macro_rules! jle {
         get_pc!($c.val, new_pc);
 
         match $a.val {
-            Values::SIGNED(a_val) => compare!(a_val, $b.val, <=, i64, $pc, new_pc, $skip_inc),
-            Values::UNSIGNED(a_val) => compare!(a_val, $b.val, <=, u64, $pc, new_pc, $skip_inc),
-            Values::DECIMAL(a_val) => compare!(a_val, $b.val, <=, f64, $pc, new_pc, $skip_inc),
+            Values::SIGNED(_) | Values::UNSIGNED(_) | Values::DECIMAL(_) | Values::POINTER(_, _) | Values::REFERENCE(_, _, _) => compare!($a.val, $b.val, <=, $pc, new_pc, $skip_inc),
             _ => panic!("expected a number for comparison, got `{:?}`", $a.val)
         }
     }
@@ -195,9 +162,7 @@ macro_rules! jl {
         get_pc!($c.val, new_pc);
 
         match $a.val {
-            Values::SIGNED(a_val) => compare!(a_val, $b.val, <, i64, $pc, new_pc, $skip_inc),
-            Values::UNSIGNED(a_val) => compare!(a_val, $b.val, <, u64, $pc, new_pc, $skip_inc),
-            Values::DECIMAL(a_val) => compare!(a_val, $b.val, <, f64, $pc, new_pc, $skip_inc),
+            Values::SIGNED(_) | Values::UNSIGNED(_) | Values::DECIMAL(_) | Values::POINTER(_, _) | Values::REFERENCE(_, _, _) => compare!($a.val, $b.val, <, $pc, new_pc, $skip_inc),
             _ => panic!("expected a number for comparison, got `{:?}`", $a.val)
         }
     }
@@ -319,21 +284,47 @@ macro_rules! ret {
                 Values::UNSIGNED(n) => return n as i32,
                 Values::DECIMAL(n) => return n as i32,
                 Values::POINTER(n, _) => return n as i32,
+                Values::REFERENCE(_, _, _) => return 0,
                 Values::STRUCT(_, _, _) => return 0,
                 Values::TYPE(_) => return 0,
                 Values::NAME(_) => return 0,
+                Values::HANDLE(_, _) => return 0,
             }
         }
     };
 }
 
+// stores a value into memory, structs are stored field by field from the frame they live in
+macro_rules! store {
+    ($addr:expr, $typ:expr, $val:expr, $scope:expr, $global_scope:expr, $stack:expr, $cur_frame:expr, $global_frame:expr) => {
+        match &$typ.typ[0] {
+            Types::STRUCT(_) => store_struct($addr, &$val.val, $scope, $global_scope, $stack, $cur_frame, $global_frame),
+            _ => $stack[$global_frame].heap.store($addr, $typ, &$val.val),
+        }
+    }
+}
+
+// loads a value from memory into a variable, structs are loaded field by field into an existing struct
+macro_rules! load {
+    ($addr:expr, $typ:expr, $out:expr, $scope:expr, $global_scope:expr, $stack:expr, $cur_frame:expr, $module_frame:expr, $global_frame:expr) => {
+        match &$typ.typ[0] {
+            Types::STRUCT(name) => load_struct($addr, name, $out, $scope, $global_scope, $stack, $cur_frame, $module_frame, $global_frame),
+            _ => {
+                let val = $stack[$global_frame].heap.load($addr, $typ);
+                set_var($out, &val, $scope, $global_scope, $stack, $cur_frame, $module_frame, $global_frame);
+            }
+        }
+    }
+}
+
 macro_rules! ref_ {
-    ($index:expr, $out_var:expr, $scope:expr, $global_scope:expr, $stack:expr, $cur_frame:expr, $module_frame:expr, $global_frame:expr) => {
+    ($frame:expr, $gen:expr, $slot:expr, $out_var:expr, $scope:expr, $global_scope:expr, $stack:expr, $cur_frame:expr, $module_frame:expr, $global_frame:expr) => {
         // ugly line
         let out_var_type = get_var($out_var, $scope, $global_scope, $stack, $cur_frame, $module_frame, $global_frame).typ.typ[0].clone();
         match out_var_type {
             Types::POINTER => {
-                set_var($out_var, &Values::POINTER($index, 1), $scope, $global_scope, $stack, $cur_frame, $module_frame, $global_frame);
+                let reference = Values::REFERENCE($frame, $gen, $slot);
+                set_var($out_var, &reference, $scope, $global_scope, $stack, $cur_frame, $module_frame, $global_frame);
             }
             _ => panic!("attempted set a variable with type `{:?}` to a reference", out_var_type)
         }
@@ -342,17 +333,24 @@ macro_rules! ref_ {
 
 macro_rules! deref {
     ($ptr:expr, $out:expr, $scope:expr, $global_scope:expr, $stack:expr, $cur_frame:expr, $module_frame:expr, $global_frame:expr) => {
-        let index;
-        match $ptr.val {
-            Values::SIGNED(p) => index = p as usize,
-            Values::UNSIGNED(p) => index = p as usize,
-            Values::DECIMAL(p) => index = p as usize,
-            Values::POINTER(p, _) => index = p,
-            _ => panic!("attempted to deref non-pointer value")
+        match &$ptr.val {
+            Values::REFERENCE(frame, gen, slot) => {
+                let val = Frame::get_ref($stack, *frame, *gen, *slot, 0).val.clone();
+                set_var($out, &val, $scope, $global_scope, $stack, $cur_frame, $module_frame, $global_frame);
+            }
+            _ => {
+                let (addr, typ) = get_pointee(&$ptr, "deref");
+
+                // untyped addresses are read as the type of the output variable
+                let typ = match typ {
+                    Some(t) => t,
+                    None => get_var($out, $scope, $global_scope, $stack, $cur_frame, $module_frame, $global_frame).typ.clone(),
+                };
+
+                $stack[$global_frame].heap.check_provenance(&$ptr.val, addr, get_type_size(&typ, $scope, $global_scope), "read from");
+                load!(addr, &typ, $out, $scope, $global_scope, $stack, $cur_frame, $module_frame, $global_frame);
+            }
         }
-        
-        let val = $stack[$global_frame].stack[index].val.clone();
-        set_var($out, &val, $scope, $global_scope, $stack, $cur_frame, $module_frame, $global_frame);
     }
 }
 
@@ -370,28 +368,34 @@ macro_rules! get_usize {
 
 macro_rules! pmov {
     ($val:expr, $ptr:expr, $offset:expr, $scope:expr, $global_scope:expr, $stack:expr, $cur_frame:expr, $module_frame:expr, $global_frame:expr) => {
-        let ptr = get_var($ptr, $scope, $global_scope, $stack, $cur_frame, $module_frame, $global_frame);
-        let ptr = match(ptr.val) {
-            Values::SIGNED(p) => p as usize,
-            Values::UNSIGNED(p) => p as usize,
-            Values::DECIMAL(p) => p as usize,
-            Values::POINTER(p, _) => p,
-            _ => panic!("cannot PMOV into a non-pointer variable")
-        };
+        let ptr = get_var($ptr, $scope, $global_scope, $stack, $cur_frame, $module_frame, $global_frame).clone();
 
         let offset_index;
         get_usize!(offset_index, $offset, "PMOV", "offset");
 
-        if ptr + offset_index >= $stack[$global_frame].stack.len() {
-            panic!("`PMOV` index out of bounds: {} > {}", ptr + offset_index, $stack[$global_frame].stack.len() - 1);
-        }
+        match ptr.val {
+            // offsets from a reference are counted in stack slots
+            Values::REFERENCE(frame, gen, slot) => Frame::get_ref($stack, frame, gen, slot, offset_index).set(&$val.val),
+            _ => {
+                let (addr, typ) = get_pointee(&ptr, "PMOV into");
+
+                // untyped addresses are written with the type of the value
+                let typ = match typ {
+                    Some(t) => t,
+                    None => $val.typ.clone(),
+                };
 
-        $stack[$global_frame].stack[ptr + offset_index].set(&$val.val);
+                let addr = addr + offset_index * get_type_size(&typ, $scope, $global_scope);
+
+                $stack[$global_frame].heap.check_provenance(&ptr.val, addr, get_type_size(&typ, $scope, $global_scope), "write to");
+                store!(addr, &typ, $val, $scope, $global_scope, $stack, $cur_frame, $global_frame);
+            }
+        }
     }
 }
 
 macro_rules! alloc {
-    ($typ:expr, $amnt:expr, $out:expr, $scope:expr, $global_scope:expr, $stack:expr, $cur_frame:expr, $module_frame:expr, $global_frame:expr) => {
+    ($typ:expr, $amnt:expr, $out:expr, $instr:expr, $module:expr, $scope:expr, $global_scope:expr, $stack:expr, $cur_frame:expr, $module_frame:expr, $global_frame:expr) => {
         let amnt = match($amnt.val) {
             Values::SIGNED(n) => n as u64,
             Values::UNSIGNED(n) => n,
@@ -399,33 +403,45 @@ macro_rules! alloc {
             _ => panic!("cannot allocate with non-number value as count"),
         };
 
-        let index = $stack[$global_frame].stack.len();
+        let size = get_type_size($typ, $scope, $global_scope) * amnt as usize;
 
-        $stack[$cur_frame].set_var($out, &Values::POINTER(index, amnt as usize));
+        gc::maybe_collect($stack, $global_frame);
+        let addr = $stack[$global_frame].heap.alloc(size);
 
-        for _ in 0..amnt {
-            $stack[$global_frame].push_alloc($typ, $out.clone());
+        // numbers can't point anywhere, so the collector doesn't need to look inside them
+        if gc::is_gc() && $typ.typ.len() == 1 && !matches!($typ.typ[0], Types::STRUCT(_) | Types::VOID) {
+            $stack[$global_frame].heap.set_no_scan(addr);
+        }
+        if $stack[$global_frame].heap.is_memcheck() {
+            let site = match $module.is_empty() {
+                true => format!("`{}` at {:#06x}", $instr.to_string(), $instr.index),
+                false => format!("`{}` at {:#06x} in module `{}`", $instr.to_string(), $instr.index, $module),
+            };
+            $stack[$global_frame].heap.set_site(addr, site);
         }
+
+        set_var($out, &Values::POINTER(addr, addr), $scope, $global_scope, $stack, $cur_frame, $module_frame, $global_frame);
     }
 }
 
 macro_rules! free_ {
-    ($ptr:expr, $amnt:expr, $stack:expr, $global_frame:expr) => {
-        let mut index;
-        get_usize!(index, $ptr, "free", "pointer");
-
-        let size;
-        get_usize!(size, $amnt, "free", "size");
-
-        let start = index;
+    ($ptr:expr, $amnt:expr, $scope:expr, $global_scope:expr, $stack:expr, $global_frame:expr) => {
+        let (addr, typ) = get_pointee(&$ptr, "free");
+
+        // the size is a count of elements for typed pointers, and of bytes for plain addresses
+        let count;
+        get_usize!(count, $amnt, "free", "size");
+        let size = match typ {
+            Some(t) => count * get_type_size(&t, $scope, $global_scope),
+            None => count,
+        };
 
-        // TODO: this loop will get extremely slow with large allocs
-        //       replace this with full heap reconstruction, or somehow allow the heap to get fragmented
-        for _ in 0..size {
-            $stack[$global_frame].allocs.remove(start);
-            $stack[$global_frame].stack.remove(start);
-            index += 1;
+        match $stack[$global_frame].heap.alloc_size(addr) {
+            Some(alloc_size) if alloc_size != size => panic!("attempted to free {} bytes at address {}, but the allocation there is {} bytes", size, addr, alloc_size),
+            _ => (),
         }
+
+        $stack[$global_frame].heap.free(addr);
     }
 }
 
@@ -489,6 +505,110 @@ macro_rules! cmp {
     };
 }
 
+macro_rules! arith {
+    ($mode:expr, $op:expr, $a:expr, $b:expr, $out:expr, $scope:expr, $global_scope:expr, $stack:expr, $cur_frame:expr, $module_frame:expr, $global_frame:expr) => {
+        {
+            let op;
+            match $op.val {
+                Values::SIGNED(n) => op = n as u64,
+                Values::UNSIGNED(n) => op = n,
+                Values::DECIMAL(n) => op = n as u64,
+                _ => panic!("invalid operation `{:?}` passed to arithmetic instruction", $op.val)
+            }
+
+            // the width of the operation is taken from the output variable
+            let typ = if $out == "_" {
+                $a.typ.clone()
+            } else {
+                get_var($out, $scope, $global_scope, $stack, $cur_frame, $module_frame, $global_frame).typ.clone()
+            };
+
+            let (val, overflowed) = $a.val.arith(&$b.val, op, &typ, $mode);
+            set_var($out, &val, $scope, $global_scope, $stack, $cur_frame, $module_frame, $global_frame);
+
+            overflowed
+        }
+    };
+}
+
+macro_rules! cast {
+    ($mode:expr, $val:expr, $typ:expr, $out:expr, $scope:expr, $global_scope:expr, $stack:expr, $cur_frame:expr, $module_frame:expr, $global_frame:expr) => {
+        let mode;
+        match $mode.val {
+            Values::SIGNED(n) => mode = n as u64,
+            Values::UNSIGNED(n) => mode = n,
+            Values::DECIMAL(n) => mode = n as u64,
+            _ => panic!("invalid mode `{:?}` passed to `CAST` instruction", $mode.val)
+        }
+
+        let val = $val.val.cast(&$val.typ, $typ, mode);
+        set_var($out, &val, $scope, $global_scope, $stack, $cur_frame, $module_frame, $global_frame);
+    };
+}
+
+// stores a struct into memory field by field from the frame it lives in, nested structs are stored in place
+fn store_struct(addr: usize, val: &Values, scope: &Scope, global_scope: &Scope, stack: &mut Vec<Frame>, cur_frame: usize, global_frame: usize) {
+    let (module, name, index) = match val {
+        Values::STRUCT(module, name, index) => (module.clone(), name.clone(), *index),
+        _ => panic!("cannot store value `{}` in memory as a struct", val),
+    };
+    if index == usize::MAX {
+        panic!("cannot store struct `{name}` in memory before it has been created with `INST`");
+    }
+
+    let struct_type = get_struct(&module, &name, scope, global_scope);
+
+    for i in 0..struct_type.var_names.len() {
+        let field = &struct_type.var_names[i];
+        let slot = struct_type.var_offsets.get(field).unwrap();
+        let offset = struct_type.byte_offsets.get(field).unwrap();
+
+        let val = stack[cur_frame].get(index + slot).val.clone();
+        match struct_type.var_types[i].typ[0] {
+            Types::STRUCT(_) => store_struct(addr + offset, &val, scope, global_scope, stack, cur_frame, global_frame),
+            _ => stack[global_frame].heap.store(addr + offset, &struct_type.var_types[i], &val),
+        }
+    }
+}
+
+// loads a struct from memory field by field into an existing struct, nested structs are loaded into its nested structs
+fn load_struct(addr: usize, name: &String, out: &String, scope: &Scope, global_scope: &Scope, stack: &mut Vec<Frame>, cur_frame: usize, module_frame: usize, global_frame: usize) {
+    let struct_type = get_struct(&String::new(), name, scope, global_scope);
+
+    for i in 0..struct_type.var_names.len() {
+        let field = &struct_type.var_names[i];
+        let offset = struct_type.byte_offsets.get(field).unwrap();
+        let out_field = format!("{}.{}", out, field);
+
+        match &struct_type.var_types[i].typ[0] {
+            Types::STRUCT(nested) => load_struct(addr + offset, nested, &out_field, scope, global_scope, stack, cur_frame, module_frame, global_frame),
+            _ => {
+                let val = stack[global_frame].heap.load(addr + offset, &struct_type.var_types[i]);
+                set_var(&out_field, &val, scope, global_scope, stack, cur_frame, module_frame, global_frame);
+            }
+        }
+    }
+}
+
+// gets the address a value points to, and the type it points to if the value is a typed pointer
+fn get_pointee(ptr: &Value, action: &str) -> (usize, Option<Type>) {
+    let addr = match ptr.val {
+        Values::SIGNED(p) => p as usize,
+        Values::UNSIGNED(p) => p as usize,
+        Values::DECIMAL(p) => p as usize,
+        Values::POINTER(p, _) => p,
+        Values::REFERENCE(_, _, _) => panic!("cannot {} a reference to a stack value", action),
+        Values::HANDLE(_, _) => panic!("cannot {} a handle, handles can only be passed to externs", action),
+        _ => panic!("cannot {} a non-pointer value", action),
+    };
+
+    if ptr.typ.typ.len() > 1 && matches!(ptr.typ.typ[0], Types::POINTER) && !matches!(ptr.typ.typ[1], Types::VOID) {
+        return (addr, Some(ptr.typ.clone().pop()));
+    }
+
+    return (addr, None);
+}
+
 pub fn exec_block(scope: &Scope, block: &Vec<Instruction>, global_scope: &Scope, stack: &mut Vec<Frame>, cur_frame: usize, pc: &mut usize, block_start: usize, module_frame: usize, global_frame: usize, module: &String) -> i32 {
     // i want to make per-instruction timing toggleable
     // but i also want to do it in a way that doesnt have any performance impact
@@ -555,6 +675,53 @@ pub fn exec_block(scope: &Scope, block: &Vec<Instruction>, global_scope: &Scope,
 
                 call!(func, scope, global_scope, stack, cur_frame, module_frame, global_frame, module.to_string());
             }
+            Opcode::CALLC_I_T_I(addr, typ, arg_count) => { // CALLC [imm] [type] [imm]
+                callc!(addr, typ, arg_count, scope, global_scope, stack, cur_frame, module_frame, global_frame, module);
+            }
+            Opcode::CALLC_V_T_I(addr_var, typ, arg_count) => { // CALLC [var] [type] [imm]
+                let addr = get_var(addr_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+
+                callc!(addr, typ, arg_count, scope, global_scope, stack, cur_frame, module_frame, global_frame, module);
+            }
+            Opcode::CALLC_I_V_I(addr, typ_var, arg_count) => { // CALLC [imm] [var] [imm]
+                let typ;
+                get_type!(typ, typ_var, scope, global_scope, stack, cur_frame, "call a function pointer", module_frame, global_frame);
+
+                callc!(addr, &typ, arg_count, scope, global_scope, stack, cur_frame, module_frame, global_frame, module);
+            }
+            Opcode::CALLC_V_V_I(addr_var, typ_var, arg_count) => { // CALLC [var] [var] [imm]
+                let addr = get_var(addr_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+                let typ;
+                get_type!(typ, typ_var, scope, global_scope, stack, cur_frame, "call a function pointer", module_frame, global_frame);
+
+                callc!(addr, &typ, arg_count, scope, global_scope, stack, cur_frame, module_frame, global_frame, module);
+            }
+            Opcode::CALLC_I_T_V(addr, typ, arg_count_var) => { // CALLC [imm] [type] [var]
+                let arg_count = get_var(arg_count_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+
+                callc!(addr, typ, arg_count, scope, global_scope, stack, cur_frame, module_frame, global_frame, module);
+            }
+            Opcode::CALLC_V_T_V(addr_var, typ, arg_count_var) => { // CALLC [var] [type] [var]
+                let addr = get_var(addr_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+                let arg_count = get_var(arg_count_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+
+                callc!(addr, typ, arg_count, scope, global_scope, stack, cur_frame, module_frame, global_frame, module);
+            }
+            Opcode::CALLC_I_V_V(addr, typ_var, arg_count_var) => { // CALLC [imm] [var] [var]
+                let typ;
+                get_type!(typ, typ_var, scope, global_scope, stack, cur_frame, "call a function pointer", module_frame, global_frame);
+                let arg_count = get_var(arg_count_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+
+                callc!(addr, &typ, arg_count, scope, global_scope, stack, cur_frame, module_frame, global_frame, module);
+            }
+            Opcode::CALLC_V_V_V(addr_var, typ_var, arg_count_var) => { // CALLC [var] [var] [var]
+                let addr = get_var(addr_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+                let typ;
+                get_type!(typ, typ_var, scope, global_scope, stack, cur_frame, "call a function pointer", module_frame, global_frame);
+                let arg_count = get_var(arg_count_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+
+                callc!(addr, &typ, arg_count, scope, global_scope, stack, cur_frame, module_frame, global_frame, module);
+            }
 
             Opcode::ADD_I_I(a, b, out) => { // ADD [imm] [imm] [var]
                 add!(a, b, out, scope, global_scope, stack, cur_frame, module_frame, global_frame);
@@ -1107,39 +1274,23 @@ pub fn exec_block(scope: &Scope, block: &Vec<Instruction>, global_scope: &Scope,
             }
 
             Opcode::REF_IMM(val, out_var) => {
-                let index = stack[global_frame].stack.len();
-
-                stack[global_frame].push(val.clone());
+                // the value lives beside the current frame's stack until the scope it was created in ends
+                let (gen, slot) = stack[cur_frame].push_temp(val.clone());
 
-                ref_!(index, out_var, scope, global_scope, stack, cur_frame, module_frame, global_frame);
+                ref_!(cur_frame, gen, slot, out_var, scope, global_scope, stack, cur_frame, module_frame, global_frame);
             }
             Opcode::REF_VAR(var, out_var) => {
-                let index = stack[global_frame].stack.len();
-
-                // we only need to move the variable to the heap if it isnt already on the heap
-                // TODO: figure out a way to change the name of the created variable on the heap
-                //       to remove the possibility of name collisions
-                //       
-                //       if you have a variable with a certain name and you REF it, if there
-                //       is a global variable with the same name it wil overwrite it
-                if !stack[global_frame].vars.contains_key(var) {
-                    if stack[cur_frame].vars.contains_key(var) {
-                        let orig_var = stack[cur_frame].get_var(var).clone();
-
-                        stack[global_frame].push_var(var, orig_var.typ, orig_var.val);
-                    } else {
-                        panic!("attempted to create a reference to a variable that doesnt exist");
-                    }
-                }
-                
-                ref_!(index, out_var, scope, global_scope, stack, cur_frame, module_frame, global_frame);
+                let (frame, slot) = get_var_slot(var, scope, global_scope, stack, cur_frame, module_frame, global_frame);
+                let gen = stack[frame].slot_gen(slot);
+
+                ref_!(frame, gen, slot, out_var, scope, global_scope, stack, cur_frame, module_frame, global_frame);
             }
 
             Opcode::DEREF_IMM(ptr, out) => {
                 deref!(ptr, out, scope, global_scope, stack, cur_frame, module_frame, global_frame);
             }
             Opcode::DEREF_VAR(ptr_var, out) => {
-                let ptr = get_var(ptr_var, scope, global_scope, stack, cur_frame, module_frame, global_frame);
+                let ptr = get_var(ptr_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
 
                 deref!(ptr, out, scope, global_scope, stack, cur_frame, module_frame, global_frame);
             }
@@ -1200,18 +1351,18 @@ pub fn exec_block(scope: &Scope, block: &Vec<Instruction>, global_scope: &Scope,
             }
 
             Opcode::ALLOC_TYPE_IMM(typ, amnt, out) => {
-                alloc!(typ, amnt, out, scope, global_scope, stack, cur_frame, module_frame, global_frame);
+                alloc!(typ, amnt, out, instr, module, scope, global_scope, stack, cur_frame, module_frame, global_frame);
             }
             Opcode::ALLOC_VAR_IMM(type_var, amnt, out) => {
                 let typ;
                 get_type!(typ, type_var, scope, global_scope, stack, cur_frame, "allocate", module_frame, global_frame);
 
-                alloc!(&typ, amnt, out, scope, global_scope, stack, cur_frame, module_frame, global_frame);
+                alloc!(&typ, amnt, out, instr, module, scope, global_scope, stack, cur_frame, module_frame, global_frame);
             }
             Opcode::ALLOC_TYPE_VAR(typ, amnt_var, out) => {
                 let amnt = get_var(amnt_var, scope, global_scope, stack, cur_frame, module_frame, global_frame);
 
-                alloc!(typ, amnt, out, scope, global_scope, stack, cur_frame, module_frame, global_frame);
+                alloc!(typ, amnt, out, instr, module, scope, global_scope, stack, cur_frame, module_frame, global_frame);
             }
             Opcode::ALLOC_VAR_VAR(type_var, amnt_var, out) => {
                 let typ;
@@ -1219,39 +1370,55 @@ pub fn exec_block(scope: &Scope, block: &Vec<Instruction>, global_scope: &Scope,
 
                 let amnt = get_var(amnt_var, scope, global_scope, stack, cur_frame, module_frame, global_frame);
 
-                alloc!(&typ, amnt, out, scope, global_scope, stack, cur_frame, module_frame, global_frame);
+                alloc!(&typ, amnt, out, instr, module, scope, global_scope, stack, cur_frame, module_frame, global_frame);
             }
 
-            Opcode::FREE_VAR(ptr) => {
-                let mut index = *stack[global_frame].vars.get(ptr).unwrap_or_else(|| panic!("attempted to free non-existent pointer `{}`", ptr));
-                let start = index;
+            Opcode::FREE_VAR(ptr_var) => {
+                let ptr = get_var(ptr_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+
+                match &ptr.val {
+                    // freeing a handle calls its destructor with it
+                    Values::HANDLE(handle, dtor) => {
+                        if *handle != 0 && !dtor.is_empty() {
+                            let depth = stack[cur_frame].len();
+
+                            stack[cur_frame].push(ptr.clone());
+                            call!(dtor, scope, global_scope, stack, cur_frame, module_frame, global_frame, module.to_string());
+
+                            while stack[cur_frame].len() > depth {
+                                stack[cur_frame].pop();
+                            }
+                        }
+
+                        set_var(ptr_var, &Values::HANDLE(0, String::new()), scope, global_scope, stack, cur_frame, module_frame, global_frame);
+                    }
+                    _ => {
+                        let (addr, _) = get_pointee(&ptr, "free");
 
-                stack[global_frame].vars.swap_remove(ptr);
+                        stack[global_frame].heap.free(addr);
 
-                while &stack[global_frame].allocs[index] == ptr {
-                    stack[global_frame].allocs.swap_remove(start);
-                    stack[global_frame].stack.swap_remove(start);
-                    index += 1;
+                        set_var(ptr_var, &Values::POINTER(0, 0), scope, global_scope, stack, cur_frame, module_frame, global_frame);
+                    }
                 }
             }
             Opcode::FREE_IMM_IMM(ptr, amnt) => {
-                free_!(ptr, amnt, stack, global_frame);
+                free_!(ptr, amnt, scope, global_scope, stack, global_frame);
             }
             Opcode::FREE_VAR_IMM(ptr_var, amnt) => {
                 let ptr = get_var(ptr_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
 
-                free_!(ptr, amnt, stack, global_frame);
+                free_!(ptr, amnt, scope, global_scope, stack, global_frame);
             }
             Opcode::FREE_IMM_VAR(ptr, amnt_var) => {
                 let amnt = get_var(amnt_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
 
-                free_!(ptr, amnt, stack, global_frame);
+                free_!(ptr, amnt, scope, global_scope, stack, global_frame);
             }
             Opcode::FREE_VAR_VAR(ptr_var, amnt_var) => {
                 let ptr = get_var(ptr_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
                 let amnt = get_var(amnt_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
 
-                free_!(ptr, amnt, stack, global_frame);
+                free_!(ptr, amnt, scope, global_scope, stack, global_frame);
             }
 
             Opcode::CMP_I_I_I(cond, a, b, out) => {
@@ -1298,6 +1465,262 @@ pub fn exec_block(scope: &Scope, block: &Vec<Instruction>, global_scope: &Scope,
                 cmp!(cond, a, b, out, scope, global_scope, stack, cur_frame, module_frame, global_frame);
             }
 
+            Opcode::CHK_I_I_I(op, a, b, out) => { // CHK [imm] [imm] [imm] [var]
+                arith!(Overflow::CHECKED, op, a, b, out, scope, global_scope, stack, cur_frame, module_frame, global_frame);
+            }
+            Opcode::CHK_V_I_I(op_var, a, b, out) => { // CHK [var] [imm] [imm] [var]
+                let op = get_var(op_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+
+                arith!(Overflow::CHECKED, op, a, b, out, scope, global_scope, stack, cur_frame, module_frame, global_frame);
+            }
+            Opcode::CHK_I_V_I(op, a_var, b, out) => { // CHK [imm] [var] [imm] [var]
+                let a = get_var(a_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+
+                arith!(Overflow::CHECKED, op, a, b, out, scope, global_scope, stack, cur_frame, module_frame, global_frame);
+            }
+            Opcode::CHK_V_V_I(op_var, a_var, b, out) => { // CHK [var] [var] [imm] [var]
+                let op = get_var(op_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+                let a = get_var(a_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+
+                arith!(Overflow::CHECKED, op, a, b, out, scope, global_scope, stack, cur_frame, module_frame, global_frame);
+            }
+            Opcode::CHK_I_I_V(op, a, b_var, out) => { // CHK [imm] [imm] [var] [var]
+                let b = get_var(b_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+
+                arith!(Overflow::CHECKED, op, a, b, out, scope, global_scope, stack, cur_frame, module_frame, global_frame);
+            }
+            Opcode::CHK_V_I_V(op_var, a, b_var, out) => { // CHK [var] [imm] [var] [var]
+                let op = get_var(op_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+                let b = get_var(b_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+
+                arith!(Overflow::CHECKED, op, a, b, out, scope, global_scope, stack, cur_frame, module_frame, global_frame);
+            }
+            Opcode::CHK_I_V_V(op, a_var, b_var, out) => { // CHK [imm] [var] [var] [var]
+                let a = get_var(a_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+                let b = get_var(b_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+
+                arith!(Overflow::CHECKED, op, a, b, out, scope, global_scope, stack, cur_frame, module_frame, global_frame);
+            }
+            Opcode::CHK_V_V_V(op_var, a_var, b_var, out) => { // CHK [var] [var] [var] [var]
+                let op = get_var(op_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+                let a = get_var(a_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+                let b = get_var(b_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+
+                arith!(Overflow::CHECKED, op, a, b, out, scope, global_scope, stack, cur_frame, module_frame, global_frame);
+            }
+
+            Opcode::WRAP_I_I_I(op, a, b, out) => { // WRAP [imm] [imm] [imm] [var]
+                arith!(Overflow::WRAPPING, op, a, b, out, scope, global_scope, stack, cur_frame, module_frame, global_frame);
+            }
+            Opcode::WRAP_V_I_I(op_var, a, b, out) => { // WRAP [var] [imm] [imm] [var]
+                let op = get_var(op_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+
+                arith!(Overflow::WRAPPING, op, a, b, out, scope, global_scope, stack, cur_frame, module_frame, global_frame);
+            }
+            Opcode::WRAP_I_V_I(op, a_var, b, out) => { // WRAP [imm] [var] [imm] [var]
+                let a = get_var(a_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+
+                arith!(Overflow::WRAPPING, op, a, b, out, scope, global_scope, stack, cur_frame, module_frame, global_frame);
+            }
+            Opcode::WRAP_V_V_I(op_var, a_var, b, out) => { // WRAP [var] [var] [imm] [var]
+                let op = get_var(op_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+                let a = get_var(a_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+
+                arith!(Overflow::WRAPPING, op, a, b, out, scope, global_scope, stack, cur_frame, module_frame, global_frame);
+            }
+            Opcode::WRAP_I_I_V(op, a, b_var, out) => { // WRAP [imm] [imm] [var] [var]
+                let b = get_var(b_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+
+                arith!(Overflow::WRAPPING, op, a, b, out, scope, global_scope, stack, cur_frame, module_frame, global_frame);
+            }
+            Opcode::WRAP_V_I_V(op_var, a, b_var, out) => { // WRAP [var] [imm] [var] [var]
+                let op = get_var(op_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+                let b = get_var(b_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+
+                arith!(Overflow::WRAPPING, op, a, b, out, scope, global_scope, stack, cur_frame, module_frame, global_frame);
+            }
+            Opcode::WRAP_I_V_V(op, a_var, b_var, out) => { // WRAP [imm] [var] [var] [var]
+                let a = get_var(a_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+                let b = get_var(b_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+
+                arith!(Overflow::WRAPPING, op, a, b, out, scope, global_scope, stack, cur_frame, module_frame, global_frame);
+            }
+            Opcode::WRAP_V_V_V(op_var, a_var, b_var, out) => { // WRAP [var] [var] [var] [var]
+                let op = get_var(op_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+                let a = get_var(a_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+                let b = get_var(b_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+
+                arith!(Overflow::WRAPPING, op, a, b, out, scope, global_scope, stack, cur_frame, module_frame, global_frame);
+            }
+
+            Opcode::SAT_I_I_I(op, a, b, out) => { // SAT [imm] [imm] [imm] [var]
+                arith!(Overflow::SATURATING, op, a, b, out, scope, global_scope, stack, cur_frame, module_frame, global_frame);
+            }
+            Opcode::SAT_V_I_I(op_var, a, b, out) => { // SAT [var] [imm] [imm] [var]
+                let op = get_var(op_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+
+                arith!(Overflow::SATURATING, op, a, b, out, scope, global_scope, stack, cur_frame, module_frame, global_frame);
+            }
+            Opcode::SAT_I_V_I(op, a_var, b, out) => { // SAT [imm] [var] [imm] [var]
+                let a = get_var(a_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+
+                arith!(Overflow::SATURATING, op, a, b, out, scope, global_scope, stack, cur_frame, module_frame, global_frame);
+            }
+            Opcode::SAT_V_V_I(op_var, a_var, b, out) => { // SAT [var] [var] [imm] [var]
+                let op = get_var(op_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+                let a = get_var(a_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+
+                arith!(Overflow::SATURATING, op, a, b, out, scope, global_scope, stack, cur_frame, module_frame, global_frame);
+            }
+            Opcode::SAT_I_I_V(op, a, b_var, out) => { // SAT [imm] [imm] [var] [var]
+                let b = get_var(b_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+
+                arith!(Overflow::SATURATING, op, a, b, out, scope, global_scope, stack, cur_frame, module_frame, global_frame);
+            }
+            Opcode::SAT_V_I_V(op_var, a, b_var, out) => { // SAT [var] [imm] [var] [var]
+                let op = get_var(op_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+                let b = get_var(b_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+
+                arith!(Overflow::SATURATING, op, a, b, out, scope, global_scope, stack, cur_frame, module_frame, global_frame);
+            }
+            Opcode::SAT_I_V_V(op, a_var, b_var, out) => { // SAT [imm] [var] [var] [var]
+                let a = get_var(a_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+                let b = get_var(b_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+
+                arith!(Overflow::SATURATING, op, a, b, out, scope, global_scope, stack, cur_frame, module_frame, global_frame);
+            }
+            Opcode::SAT_V_V_V(op_var, a_var, b_var, out) => { // SAT [var] [var] [var] [var]
+                let op = get_var(op_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+                let a = get_var(a_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+                let b = get_var(b_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+
+                arith!(Overflow::SATURATING, op, a, b, out, scope, global_scope, stack, cur_frame, module_frame, global_frame);
+            }
+
+            Opcode::OVF_I_I_I(op, a, b, out, flag) => { // OVF [imm] [imm] [imm] [var] [var]
+                let overflowed = arith!(Overflow::FLAG, op, a, b, out, scope, global_scope, stack, cur_frame, module_frame, global_frame);
+                set_var(flag, &Values::UNSIGNED(overflowed as u64), scope, global_scope, stack, cur_frame, module_frame, global_frame);
+            }
+            Opcode::OVF_V_I_I(op_var, a, b, out, flag) => { // OVF [var] [imm] [imm] [var] [var]
+                let op = get_var(op_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+
+                let overflowed = arith!(Overflow::FLAG, op, a, b, out, scope, global_scope, stack, cur_frame, module_frame, global_frame);
+                set_var(flag, &Values::UNSIGNED(overflowed as u64), scope, global_scope, stack, cur_frame, module_frame, global_frame);
+            }
+            Opcode::OVF_I_V_I(op, a_var, b, out, flag) => { // OVF [imm] [var] [imm] [var] [var]
+                let a = get_var(a_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+
+                let overflowed = arith!(Overflow::FLAG, op, a, b, out, scope, global_scope, stack, cur_frame, module_frame, global_frame);
+                set_var(flag, &Values::UNSIGNED(overflowed as u64), scope, global_scope, stack, cur_frame, module_frame, global_frame);
+            }
+            Opcode::OVF_V_V_I(op_var, a_var, b, out, flag) => { // OVF [var] [var] [imm] [var] [var]
+                let op = get_var(op_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+                let a = get_var(a_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+
+                let overflowed = arith!(Overflow::FLAG, op, a, b, out, scope, global_scope, stack, cur_frame, module_frame, global_frame);
+                set_var(flag, &Values::UNSIGNED(overflowed as u64), scope, global_scope, stack, cur_frame, module_frame, global_frame);
+            }
+            Opcode::OVF_I_I_V(op, a, b_var, out, flag) => { // OVF [imm] [imm] [var] [var] [var]
+                let b = get_var(b_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+
+                let overflowed = arith!(Overflow::FLAG, op, a, b, out, scope, global_scope, stack, cur_frame, module_frame, global_frame);
+                set_var(flag, &Values::UNSIGNED(overflowed as u64), scope, global_scope, stack, cur_frame, module_frame, global_frame);
+            }
+            Opcode::OVF_V_I_V(op_var, a, b_var, out, flag) => { // OVF [var] [imm] [var] [var] [var]
+                let op = get_var(op_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+                let b = get_var(b_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+
+                let overflowed = arith!(Overflow::FLAG, op, a, b, out, scope, global_scope, stack, cur_frame, module_frame, global_frame);
+                set_var(flag, &Values::UNSIGNED(overflowed as u64), scope, global_scope, stack, cur_frame, module_frame, global_frame);
+            }
+            Opcode::OVF_I_V_V(op, a_var, b_var, out, flag) => { // OVF [imm] [var] [var] [var] [var]
+                let a = get_var(a_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+                let b = get_var(b_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+
+                let overflowed = arith!(Overflow::FLAG, op, a, b, out, scope, global_scope, stack, cur_frame, module_frame, global_frame);
+                set_var(flag, &Values::UNSIGNED(overflowed as u64), scope, global_scope, stack, cur_frame, module_frame, global_frame);
+            }
+            Opcode::OVF_V_V_V(op_var, a_var, b_var, out, flag) => { // OVF [var] [var] [var] [var] [var]
+                let op = get_var(op_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+                let a = get_var(a_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+                let b = get_var(b_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+
+                let overflowed = arith!(Overflow::FLAG, op, a, b, out, scope, global_scope, stack, cur_frame, module_frame, global_frame);
+                set_var(flag, &Values::UNSIGNED(overflowed as u64), scope, global_scope, stack, cur_frame, module_frame, global_frame);
+            }
+
+            Opcode::CAST_I_I_T(mode, val, typ, out) => { // CAST [imm] [imm] [type] [var]
+                cast!(mode, val, typ, out, scope, global_scope, stack, cur_frame, module_frame, global_frame);
+            }
+            Opcode::CAST_V_I_T(mode_var, val, typ, out) => { // CAST [var] [imm] [type] [var]
+                let mode = get_var(mode_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+
+                cast!(mode, val, typ, out, scope, global_scope, stack, cur_frame, module_frame, global_frame);
+            }
+            Opcode::CAST_I_V_T(mode, val_var, typ, out) => { // CAST [imm] [var] [type] [var]
+                let val = get_var(val_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+
+                cast!(mode, val, typ, out, scope, global_scope, stack, cur_frame, module_frame, global_frame);
+            }
+            Opcode::CAST_V_V_T(mode_var, val_var, typ, out) => { // CAST [var] [var] [type] [var]
+                let mode = get_var(mode_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+                let val = get_var(val_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+
+                cast!(mode, val, typ, out, scope, global_scope, stack, cur_frame, module_frame, global_frame);
+            }
+            Opcode::CAST_I_I_V(mode, val, typ_var, out) => { // CAST [imm] [imm] [var] [var]
+                let typ;
+                get_type!(typ, typ_var, scope, global_scope, stack, cur_frame, "cast", module_frame, global_frame);
+
+                cast!(mode, val, &typ, out, scope, global_scope, stack, cur_frame, module_frame, global_frame);
+            }
+            Opcode::CAST_V_I_V(mode_var, val, typ_var, out) => { // CAST [var] [imm] [var] [var]
+                let mode = get_var(mode_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+                let typ;
+                get_type!(typ, typ_var, scope, global_scope, stack, cur_frame, "cast", module_frame, global_frame);
+
+                cast!(mode, val, &typ, out, scope, global_scope, stack, cur_frame, module_frame, global_frame);
+            }
+            Opcode::CAST_I_V_V(mode, val_var, typ_var, out) => { // CAST [imm] [var] [var] [var]
+                let val = get_var(val_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+                let typ;
+                get_type!(typ, typ_var, scope, global_scope, stack, cur_frame, "cast", module_frame, global_frame);
+
+                cast!(mode, val, &typ, out, scope, global_scope, stack, cur_frame, module_frame, global_frame);
+            }
+            Opcode::CAST_V_V_V(mode_var, val_var, typ_var, out) => { // CAST [var] [var] [var] [var]
+                let mode = get_var(mode_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+                let val = get_var(val_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+                let typ;
+                get_type!(typ, typ_var, scope, global_scope, stack, cur_frame, "cast", module_frame, global_frame);
+
+                cast!(mode, val, &typ, out, scope, global_scope, stack, cur_frame, module_frame, global_frame);
+            }
+
+            Opcode::LOADLIB_IMM(name, out) => { // LOADLIB [imm] [var]
+                let lib = load_library(name, &stack[global_frame].heap);
+                set_var(out, &lib, scope, global_scope, stack, cur_frame, module_frame, global_frame);
+            }
+            Opcode::LOADLIB_VAR(name_var, out) => { // LOADLIB [var] [var]
+                let name = get_var(name_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+
+                let lib = load_library(&name, &stack[global_frame].heap);
+                set_var(out, &lib, scope, global_scope, stack, cur_frame, module_frame, global_frame);
+            }
+            Opcode::DLSYM_V_I(lib_var, name, out) => { // DLSYM [var] [imm] [var]
+                let lib = get_var(lib_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+
+                let symbol = find_symbol(&lib, name, &stack[global_frame].heap);
+                set_var(out, &symbol, scope, global_scope, stack, cur_frame, module_frame, global_frame);
+            }
+            Opcode::DLSYM_V_V(lib_var, name_var, out) => { // DLSYM [var] [var] [var]
+                let lib = get_var(lib_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+                let name = get_var(name_var, scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+
+                let symbol = find_symbol(&lib, &name, &stack[global_frame].heap);
+                set_var(out, &symbol, scope, global_scope, stack, cur_frame, module_frame, global_frame);
+            }
+
             _ => panic!("unknown instruction {:#04x} at {:#06x}", instr.opcode.to_u8(), instr.index)
         }
         
@@ -1326,6 +1749,7 @@ pub fn exec_block(scope: &Scope, block: &Vec<Instruction>, global_scope: &Scope,
 
 pub fn exec_scope(scope: &Scope, global_scope: &Scope, stack: &mut Vec<Frame>, cur_frame: usize, pop_stack: bool, pc: &mut usize, module_frame: usize, global_frame: usize, module_name: &String) -> i32 {
     let scope_stack_start = stack[cur_frame].stack.len();
+    let scope_temps_start = stack[cur_frame].temps_len();
 
     for (_, module) in &scope.modules {
         let retval = exec_scope(&module.scope, &global_scope, stack, module.frame, pop_stack, &mut 0, module.frame, global_frame, &(module_name.clone() + &module.name));
@@ -1394,13 +1818,27 @@ pub fn exec_scope(scope: &Scope, global_scope: &Scope, stack: &mut Vec<Frame>, c
         }
     }
 
+    // temporaries made by `REF` go away with their scope even where the scope's variables are kept
+    stack[cur_frame].truncate_temps(scope_temps_start);
+
     return 0;
 }
 
+// set once the program's `main` starts running, however it was called
+static MAIN_ENTERED: AtomicBool = AtomicBool::new(false);
+
+pub fn is_main_entered() -> bool {
+    return MAIN_ENTERED.load(Ordering::Relaxed);
+}
+
 pub fn exec_func(func: &Function, global_scope: &Scope, scope: &Scope, stack: &mut Vec<Frame>, module_frame: usize, global_frame: usize, module: &String) -> i32 {
     let len = stack.len();
 
-    stack.push(Frame { vars: IndexMap::new(), stack: Vec::new(), allocs: Vec::new() });
+    if func.name == "main" && module.is_empty() {
+        MAIN_ENTERED.store(true, Ordering::Relaxed);
+    }
+
+    stack.push(Frame::new());
 
     for i in 0..func.arg_names.len() {
         // TODO: argument type checking
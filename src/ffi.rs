@@ -1,7 +1,216 @@
 use libloading::{Library, Symbol};
+#[cfg(unix)]
+use libloading::os::unix::Library as NativeLibrary;
+#[cfg(windows)]
+use libloading::os::windows::Library as NativeLibrary;
 use libffi::{low::*, raw::{ffi_call, FFI_TYPE_STRUCT}};
-use std::{ffi::c_void, ptr::{addr_of_mut, null_mut}};
-use crate::{_struct::Struct, _type::{Type, Types}, frame::Frame, function::Extern, get_struct, value::{Value, Values}, scope::Scope};
+use half::f16;
+use std::{cell::{Cell, RefCell}, collections::{HashMap, HashSet}, ffi::c_void, panic::{self, AssertUnwindSafe}, process, ptr::{addr_of_mut, null_mut}, slice, sync::atomic::{AtomicBool, Ordering}};
+use crate::{_struct::Struct, _type::{Type, Types}, block::Block, exec_scope::exec_func, frame::Frame, func_exists, function::{Extern, Function}, gc, get_callee, get_struct, get_type_size, heap::Heap, value::{Value, Values}, scope::Scope};
+
+// the interpreter an extern was called from, callbacks from native code run on top of it
+#[derive(Clone, Copy)]
+struct Interpreter {
+    stack: *mut Vec<Frame>,
+    scope: *const Scope,
+    global_scope: *const Scope,
+    global_frame: usize,
+    swizzled: *const Vec<(usize, Type)>, // allocations the extern was given, with their pointers swapped for native ones
+}
+
+// a rainbow function that native code can call through a function pointer
+struct Callback {
+    func: Function,
+    module: String,
+    module_frame: usize,
+
+    cif: ffi_cif,
+    // boxed so the pointers in `arg_types` stay put as more types are added
+    #[allow(clippy::vec_box)]
+    types: Vec<Box<ffi_type>>,
+    arg_types: Vec<*mut ffi_type>,
+    var_type_storage: Vec<Vec<*mut ffi_type>>,
+
+    code: CodePtr,
+}
+
+thread_local! {
+    static INTERPRETER: Cell<Option<Interpreter>> = const { Cell::new(None) };
+
+    // native code can hold on to a function pointer for as long as it wants (`atexit`, event loops), so callbacks are never freed
+    static CALLBACKS: RefCell<HashMap<String, Box<Callback>>> = RefCell::new(HashMap::new());
+
+    // errno (`GetLastError` on windows) as the last native call left it
+    static ERRNO: Cell<i32> = const { Cell::new(0) };
+
+    // libraries stay loaded until the program exits, so their statics keep their values between calls
+    // each one is kept with the handle the system gave it, which is what `LOADLIB` gives the program
+    static LIBRARIES: RefCell<HashMap<String, (Library, usize)>> = RefCell::new(HashMap::new());
+
+    // names externs can use instead of a library file, so the same bytecode works on every platform
+    static LIB_ALIASES: RefCell<HashMap<String, String>> = RefCell::new(default_lib_aliases());
+}
+
+fn default_lib_aliases() -> HashMap<String, String> {
+    let aliases: &[(&str, &str)] = match std::env::consts::OS {
+        "linux"   => &[("c", "libc.so.6"), ("m", "libm.so.6"), ("dl", "libdl.so.2"), ("pthread", "libpthread.so.0")],
+        "windows" => &[("c", "msvcrt.dll"), ("m", "msvcrt.dll"), ("kernel32", "Kernel32.dll")],
+        "macos"   => &[("c", "libSystem.B.dylib"), ("m", "libSystem.B.dylib"), ("dl", "libSystem.B.dylib"), ("pthread", "libSystem.B.dylib")],
+        _ => &[],
+    };
+
+    return aliases.iter().map(|(name, file)| (name.to_string(), file.to_string())).collect();
+}
+
+pub fn add_lib_alias(name: String, file: String) {
+    LIB_ALIASES.with_borrow_mut(|aliases| aliases.insert(name, file));
+}
+
+// the library file an extern's library name refers to
+pub fn get_lib_alias(dll: &String) -> String {
+    return LIB_ALIASES.with_borrow(|aliases| aliases.get(dll).unwrap_or(dll).clone());
+}
+
+fn open_library(dll: &String) -> std::result::Result<(Library, usize), libloading::Error> {
+    let lib = match dll.is_empty() {
+        #[cfg(unix)]
+        true => NativeLibrary::this(),
+        #[cfg(windows)]
+        true => NativeLibrary::this()?,
+        false => unsafe { NativeLibrary::new(dll)? },
+    };
+
+    let handle = lib.into_raw();
+    return Ok((unsafe { NativeLibrary::from_raw(handle) }.into(), handle as usize));
+}
+
+fn get_library<'a>(libraries: &'a mut HashMap<String, (Library, usize)>, dll: &String) -> std::result::Result<&'a (Library, usize), String> {
+    if !libraries.contains_key(dll) {
+        let lib = open_library(dll).map_err(|e| e.to_string())?;
+        libraries.insert(dll.clone(), lib);
+    }
+
+    return Ok(&libraries[dll]);
+}
+
+// unloads every library, once the program is done with them
+pub fn close_libraries() {
+    LIBRARIES.with_borrow_mut(|libraries| libraries.clear());
+}
+
+// when set, externs are only looked up when they are first called instead of when the program is loaded
+static LAZY_EXTERNS: AtomicBool = AtomicBool::new(false);
+
+pub fn set_lazy_externs(lazy: bool) {
+    LAZY_EXTERNS.store(lazy, Ordering::Relaxed);
+}
+
+pub fn is_lazy_externs() -> bool {
+    return LAZY_EXTERNS.load(Ordering::Relaxed);
+}
+
+pub fn get_symbol(dll: &String, name: &String) -> std::result::Result<*mut c_void, String> {
+    return LIBRARIES.with_borrow_mut(|libraries| {
+        let (lib, _) = get_library(libraries, dll)?;
+
+        let symbol: Symbol<*mut c_void> = unsafe { lib.get(name.as_bytes()) }.map_err(|e| e.to_string())?;
+        return Ok(*symbol);
+    });
+}
+
+// `LOADLIB`, gives a null handle if the library couldn't be loaded
+pub fn load_library(name: &Value, heap: &Heap) -> Values {
+    let dll = get_lib_alias(&get_name_arg(name, heap));
+
+    return LIBRARIES.with_borrow_mut(|libraries| {
+        return match get_library(libraries, &dll) {
+            Ok((_, handle)) => Values::HANDLE(*handle, String::new()),
+            Err(_) => Values::HANDLE(0, String::new()),
+        };
+    });
+}
+
+// `DLSYM`, gives a null handle if the library doesn't have the symbol
+pub fn find_symbol(lib: &Value, name: &Value, heap: &Heap) -> Values {
+    let handle = match lib.val {
+        Values::HANDLE(handle, _) if handle != 0 => handle,
+        _ => panic!("tried to look up a symbol in `{}`, which isn't a library loaded with `LOADLIB`", lib.val),
+    };
+
+    let name = get_name_arg(name, heap);
+
+    return LIBRARIES.with_borrow(|libraries| {
+        let lib = match libraries.values().find(|(_, h)| *h == handle) {
+            Some((lib, _)) => lib,
+            None => panic!("tried to look up `{}` in `{}`, which isn't a library loaded with `LOADLIB`", name, Values::HANDLE(handle, String::new())),
+        };
+
+        return match unsafe { lib.get::<*mut c_void>(name.as_bytes()) } {
+            Ok(symbol) => Values::HANDLE(*symbol as usize, String::new()),
+            Err(_) => Values::HANDLE(0, String::new()),
+        };
+    });
+}
+
+// library and symbol names can be a bytecode string or a pointer to a C string
+fn get_name_arg(name: &Value, heap: &Heap) -> String {
+    return match name.val {
+        Values::NAME(ref name) => name.clone(),
+        Values::POINTER(addr, _) => String::from_utf8_lossy(&heap.read_c_str(addr)).to_string(),
+        _ => panic!("expected a name or a string for a library or symbol name, got `{}`", name.val),
+    };
+}
+
+// opens every extern's library and looks up its symbol, so a typo is found before the program starts instead of when it's called
+pub fn check_externs(scope: &Scope) {
+    let mut errors: Vec<String> = Vec::new();
+    find_unresolved(scope, &mut errors);
+
+    if !errors.is_empty() {
+        panic!("failed to resolve externs:\n{}", errors.join("\n"));
+    }
+}
+
+fn find_unresolved(scope: &Scope, errors: &mut Vec<String>) {
+    for (_, _extern) in &scope.externs {
+        if let Err(error) = get_symbol(&_extern.dll, &_extern.name) {
+            let error = format!("extern `{}` from `{}`: {}", _extern.name, _extern.dll, error);
+            if !errors.contains(&error) {
+                errors.push(error);
+            }
+        }
+    }
+
+    for (_, func) in &scope.functions {
+        find_unresolved(&func.scope, errors);
+    }
+
+    for (_, module) in &scope.modules {
+        find_unresolved(&module.scope, errors);
+    }
+
+    for block in &scope.blocks {
+        if let Block::SCOPE(scope) = block {
+            find_unresolved(scope, errors);
+        }
+    }
+}
+
+pub fn last_errno() -> i32 {
+    return ERRNO.get();
+}
+
+// saved straight after the call, the interpreter can change errno before the program gets to read it
+fn save_errno() {
+    ERRNO.set(std::io::Error::last_os_error().raw_os_error().unwrap_or(0));
+}
+
+unsafe fn call<R>(cif: &mut ffi_cif, fun: CodePtr, args: *mut *mut c_void) -> R {
+    let result = libffi::low::call::<R>(cif, fun, args);
+    save_errno();
+
+    return result;
+}
 
 pub unsafe fn type_to_type(typ: &Type) -> ffi_type {
     match typ.typ[0] {
@@ -16,27 +225,52 @@ pub unsafe fn type_to_type(typ: &Type) -> ffi_type {
         Types::U64     => types::uint64,
         Types::F32     => types::float,
         Types::F64     => types::double,
-        Types::POINTER => types::pointer,
+        Types::POINTER | Types::HANDLE(_) => types::pointer,
         _ => panic!("unsupported type `{}` for externs", typ.typ[0])
     }
 }
 
-pub unsafe fn struct_to_ffi(_struct: Struct, types: &mut Vec<ffi_type>, var_type_storage: &mut Vec<Vec<*mut ffi_type>>) -> ffi_type {
+#[allow(clippy::vec_box)]
+pub unsafe fn struct_to_ffi(_struct: Struct, types: &mut Vec<Box<ffi_type>>, var_type_storage: &mut Vec<Vec<*mut ffi_type>>, global_scope: &Scope, scope: &Scope) -> ffi_type {
     let mut var_types: Vec<*mut ffi_type> = Vec::new();
 
-    let size = get_struct_size(&_struct);
+    let mut end = 0;
+    for i in 0.._struct.var_types.len() {
+        let typ = &_struct.var_types[i];
+        let offset = *_struct.byte_offsets.get(&_struct.var_names[i]).unwrap();
+
+        let (var_type, size, align) = match &typ.typ[0] {
+            Types::STRUCT(name) => {
+                let nested = get_struct(&String::new(), name, global_scope, scope);
+                let (size, align) = (nested.size, nested.align);
+
+                (struct_to_ffi(nested, types, var_type_storage, global_scope, scope), size, align)
+            }
+            _ => (type_to_type(typ), typ.get_size(), typ.get_align()),
+        };
+
+        // libffi lays structs out itself and doesn't know about packing, so packed variables have to be where C would put them anyway
+        if !offset.is_multiple_of(align) {
+            panic!("cannot pass packed struct `{}` to or from an extern by value, its variable `{}` is not aligned (pass a pointer to it instead)", _struct.name, _struct.var_names[i]);
+        }
+
+        types.push(Box::new(var_type));
+        var_types.push(&mut **types.last_mut().unwrap() as *mut ffi_type);
 
-    for typ in _struct.var_types {
-        let mut _typ = type_to_type(&typ);
-        types.push(_typ);
-        var_types.push(types.last_mut().unwrap() as *mut ffi_type);
+        end = offset + size;
+    }
+
+    // explicitly aligned structs can be bigger than their variables, the rest is made up with padding bytes
+    while end < _struct.size {
+        var_types.push(addr_of_mut!(types::uint8));
+        end += 1;
     }
 
     var_types.push(null_mut());
 
     let typ = ffi_type {
-        size,
-        alignment: 0,
+        size: _struct.size,
+        alignment: _struct.align as u16,
         type_: FFI_TYPE_STRUCT as u16,
         elements: var_types.as_mut_ptr(),
     };
@@ -46,134 +280,523 @@ pub unsafe fn struct_to_ffi(_struct: Struct, types: &mut Vec<ffi_type>, var_type
     return typ;
 }
 
-macro_rules! push_ptr {
-    ($typ:tt, $vals:expr, $v:expr, $pp:expr, $typ2:tt) => {
-        let mut v: Vec<$typ> = Vec::new();
-        for val in $vals {
-            match val.val {
-                Values::$typ2(n) => v.push(n as $typ),
-                _ => panic!("pointers with multiple types are not supported")
+// writes a value into native memory as type `typ`, heap pointers become native addresses
+unsafe fn write_native(ptr: *mut u8, typ: &Type, val: &Values, heap: &mut Heap) {
+    match val {
+        Values::SIGNED(num) => {
+            match typ.typ[0] {
+                Types::I8  => (ptr as *mut i8).write_unaligned(*num as i8),
+                Types::I16 => (ptr as *mut i16).write_unaligned(*num as i16),
+                Types::I32 => (ptr as *mut i32).write_unaligned(*num as i32),
+                Types::I64 => (ptr as *mut i64).write_unaligned(*num),
+                _ => panic!("type mismatch, expected `{}` got a signed number", typ)
             }
         }
-        $v.push(v.clone());
-        $pp.push($v.last_mut().unwrap().as_mut_ptr() as *mut _ as *mut c_void);
-        return $pp.last_mut().unwrap() as *mut _ as *mut c_void
-    }
-}
-
-// rust has forced my hand with this one
-pub unsafe fn get_pointer(vals: &[Value], pp: &mut Vec<*mut c_void>, s8p: &mut Vec<Vec<i8>>, s16p: &mut Vec<Vec<i16>>, s32p: &mut Vec<Vec<i32>>, s64p: &mut Vec<Vec<i64>>, u8p: &mut Vec<Vec<u8>>, u16p: &mut Vec<Vec<u16>>, u32p: &mut Vec<Vec<u32>>, u64p: &mut Vec<Vec<u64>>, f32p: &mut Vec<Vec<f32>>, f64p: &mut Vec<Vec<f64>>) -> *mut c_void {
-    match vals[0].typ.typ[0] {
-        Types::I8  => { push_ptr!(i8,  vals, s8p,  pp, SIGNED);   }
-        Types::I16 => { push_ptr!(i16, vals, s16p, pp, SIGNED);   }
-        Types::I32 => { push_ptr!(i32, vals, s32p, pp, SIGNED);   }
-        Types::I64 => { push_ptr!(i64, vals, s64p, pp, SIGNED);   }
-        Types::U8  => { push_ptr!(u8,  vals, u8p,  pp, UNSIGNED); }
-        Types::U16 => { push_ptr!(u16, vals, u16p, pp, UNSIGNED); }
-        Types::U32 => { push_ptr!(u32, vals, u32p, pp, UNSIGNED); }
-        Types::U64 => { push_ptr!(u64, vals, u64p, pp, UNSIGNED); }
-        Types::F32 => { push_ptr!(f32, vals, f32p, pp, DECIMAL);  }
-        Types::F64 => { push_ptr!(f64, vals, f64p, pp, DECIMAL);  }
-        _ => panic!("unsupported type `{}` for extern pointers", vals[0]),
-    }
-}
-
-pub unsafe fn struct_from_bytes(name: &String, struct_def: &Struct, bytes: &mut Vec<u8>, frame: &mut Frame) -> Values {
-    let index = frame.stack.len();
-
-    let bytes_ptr = bytes.as_mut_ptr();
-    let mut offset = 0;
-    for typ in &struct_def.var_types {
-        let val = match typ.typ[0] {
-            Types::I8  => Values::SIGNED(*(bytes_ptr.add(offset)   as *mut i8)    as i64),
-            Types::I16 => Values::SIGNED(*(bytes_ptr.add(offset)   as *mut i16)   as i64),
-            Types::I32 => Values::SIGNED(*(bytes_ptr.add(offset)   as *mut i32)   as i64),
-            Types::I64 => Values::SIGNED(*(bytes_ptr.add(offset)   as *mut i64)),
-            Types::U8  => Values::UNSIGNED(*(bytes_ptr.add(offset) as *mut u8)    as u64),
-            Types::U16 => Values::UNSIGNED(*(bytes_ptr.add(offset) as *mut u16)   as u64),
-            Types::U32 => Values::UNSIGNED(*(bytes_ptr.add(offset) as *mut u32)   as u64),
-            Types::U64 => Values::UNSIGNED(*(bytes_ptr.add(offset) as *mut u64)),
-            Types::F32 => Values::DECIMAL(*(bytes_ptr.add(offset)  as *mut f32)   as f64),
-            Types::F64 => Values::DECIMAL(*(bytes_ptr.add(offset)  as *mut f64)),
-            _          => todo!("{} is not implemented for FFI struct returns yet", typ),
-        };
-        frame.push(Value { typ: typ.clone(), val });
-        offset += typ.get_size();
+        Values::UNSIGNED(num) => {
+            match typ.typ[0] {
+                Types::U8  => ptr.write_unaligned(*num as u8),
+                Types::U16 => (ptr as *mut u16).write_unaligned(*num as u16),
+                Types::U32 => (ptr as *mut u32).write_unaligned(*num as u32),
+                Types::U64 => (ptr as *mut u64).write_unaligned(*num),
+                _ => panic!("type mismatch, expected `{}` got an unsigned number", typ)
+            }
+        }
+        Values::DECIMAL(num) => {
+            match typ.typ[0] {
+                Types::F16 => (ptr as *mut u16).write_unaligned(f16::from_f64(*num).to_bits()),
+                Types::F32 => (ptr as *mut f32).write_unaligned(*num as f32),
+                Types::F64 => (ptr as *mut f64).write_unaligned(*num),
+                _ => panic!("type mismatch, expected `{}` got a decimal", typ)
+            }
+        }
+        Values::POINTER(p, _) => (ptr as *mut *mut c_void).write_unaligned(heap.as_ptr(*p)),
+        Values::HANDLE(h, _) => (ptr as *mut usize).write_unaligned(*h),
+        Values::REFERENCE(_, _, _) => panic!("cannot pass a reference to a stack value to an extern inside another value, put the value in heap memory instead"),
+        _ => panic!("unsupported value `{}` for externs", val),
     }
+}
 
-    return Values::STRUCT(String::new(), name.clone(), index);
+// reads a value of type `typ` from native memory, native addresses are turned back into heap pointers
+unsafe fn read_native(ptr: *const u8, typ: &Type, heap: &Heap) -> Values {
+    return match &typ.typ[0] {
+        Types::I8  => Values::SIGNED((ptr   as *const i8).read_unaligned()  as i64),
+        Types::I16 => Values::SIGNED((ptr   as *const i16).read_unaligned() as i64),
+        Types::I32 => Values::SIGNED((ptr   as *const i32).read_unaligned() as i64),
+        Types::I64 => Values::SIGNED((ptr   as *const i64).read_unaligned()),
+        Types::U8  => Values::UNSIGNED(ptr.read_unaligned() as u64),
+        Types::U16 => Values::UNSIGNED((ptr as *const u16).read_unaligned() as u64),
+        Types::U32 => Values::UNSIGNED((ptr as *const u32).read_unaligned() as u64),
+        Types::U64 => Values::UNSIGNED((ptr as *const u64).read_unaligned()),
+        Types::F16 => Values::DECIMAL(f16::from_bits((ptr as *const u16).read_unaligned()).to_f64()),
+        Types::F32 => Values::DECIMAL((ptr  as *const f32).read_unaligned() as f64),
+        Types::F64 => Values::DECIMAL((ptr  as *const f64).read_unaligned()),
+        Types::POINTER => {
+            let native = (ptr as *const *const c_void).read_unaligned();
+            match heap.addr_of(native) {
+                Some(addr) => Values::POINTER(addr, 0),
+                None => panic!("an extern gave back a pointer of type `{}` to memory outside of the heap ({:?}), which can't be used as a pointer (use a `u64` to hold it instead)", typ, native),
+            }
+        }
+        Types::HANDLE(dtor) => Values::HANDLE((ptr as *const usize).read_unaligned(), dtor.clone()),
+        _ => panic!("values of type `{}` cannot be read from native memory", typ),
+    };
 }
 
-pub fn call_ffi(_extern: &Extern, stack: &mut Vec<Frame>, cur_frame: usize, global_frame: usize, scope: &Scope, global_scope: &Scope) {
-    unsafe {
-        let lib = Library::new(&_extern.dll).unwrap();
+// copies a struct living in `frame` into native memory, laid out the way C would
+unsafe fn struct_to_native(ptr: *mut u8, name: &String, index: usize, frame: usize, stack: &mut [Frame], global_frame: usize, global_scope: &Scope, scope: &Scope) {
+    if index == usize::MAX {
+        panic!("cannot pass struct `{name}` to an extern before it has been created with `INST`");
+    }
+
+    let struct_type = get_struct(&String::new(), name, global_scope, scope);
+
+    for i in 0..struct_type.var_types.len() {
+        let field = &struct_type.var_names[i];
+        let slot = *struct_type.var_offsets.get(field).unwrap();
+        let offset = *struct_type.byte_offsets.get(field).unwrap();
+
+        let val = stack[frame].get(index + slot).val.clone();
+        match &val {
+            Values::STRUCT(_, nested, nested_index) => struct_to_native(ptr.add(offset), nested, *nested_index, frame, stack, global_frame, global_scope, scope),
+            _ => write_native(ptr.add(offset), &struct_type.var_types[i], &val, &mut stack[global_frame].heap),
+        }
+    }
+}
+
+// copies a struct from native memory into an existing struct living in `frame`
+unsafe fn struct_from_native(ptr: *const u8, name: &String, index: usize, frame: usize, stack: &mut [Frame], global_frame: usize, global_scope: &Scope, scope: &Scope) {
+    let struct_type = get_struct(&String::new(), name, global_scope, scope);
+
+    for i in 0..struct_type.var_types.len() {
+        let field = &struct_type.var_names[i];
+        let slot = *struct_type.var_offsets.get(field).unwrap();
+        let offset = *struct_type.byte_offsets.get(field).unwrap();
+
+        match &struct_type.var_types[i].typ[0] {
+            Types::STRUCT(nested) => {
+                let nested_index = match stack[frame].get(index + slot).val {
+                    Values::STRUCT(_, _, nested_index) if nested_index != usize::MAX => nested_index,
+                    _ => panic!("cannot write struct `{nested}` back from an extern before it has been created with `INST`"),
+                };
+                struct_from_native(ptr.add(offset), nested, nested_index, frame, stack, global_frame, global_scope, scope);
+            }
+            _ => {
+                let val = read_native(ptr.add(offset), &struct_type.var_types[i], &stack[global_frame].heap);
+                stack[frame].set(index + slot, &val);
+            }
+        }
+    }
+}
+
+// finds where the pointers are within a value of type `typ`
+fn pointer_offsets(typ: &Type, offset: usize, offsets: &mut Vec<usize>, global_scope: &Scope, scope: &Scope) {
+    match &typ.typ[0] {
+        Types::POINTER => offsets.push(offset),
+        Types::STRUCT(name) => {
+            let struct_type = get_struct(&String::new(), name, global_scope, scope);
+
+            for i in 0..struct_type.var_types.len() {
+                let var_offset = *struct_type.byte_offsets.get(&struct_type.var_names[i]).unwrap();
+                pointer_offsets(&struct_type.var_types[i], offset + var_offset, offsets, global_scope, scope);
+            }
+        }
+        _ => (),
+    }
+}
 
-        let func: Symbol<*mut c_void> = lib.get(_extern.name.as_bytes()).unwrap();
+// pointers stored in heap memory are heap addresses, which native code can't follow,
+// so the pointers in the allocation a pointer argument points into are swapped for native addresses for the call and swapped back after it
+// returns whether there were any pointers to swap
+fn swizzle(heap: &mut Heap, addr: usize, typ: &Type, to_native: bool, global_scope: &Scope, scope: &Scope) -> bool {
+    if addr == 0 || typ.typ.len() < 2 {
+        return false;
+    }
+
+    let elem = typ.clone().pop();
+    let mut offsets = Vec::new();
+    pointer_offsets(&elem, 0, &mut offsets, global_scope, scope);
+    if offsets.is_empty() {
+        return false;
+    }
+
+    let (start, size) = match heap.find_alloc(addr) {
+        Some(alloc) => alloc,
+        None => return false,
+    };
+
+    let stride = match &elem.typ[0] {
+        Types::STRUCT(name) => get_struct(&String::new(), name, global_scope, scope).size,
+        _ => elem.get_size(),
+    };
+    let word = size_of::<usize>();
+    let base = heap.bytes.as_ptr() as usize;
+
+    let mut elem_addr = addr;
+    while elem_addr + stride <= start + size {
+        for offset in &offsets {
+            let at = elem_addr + offset;
+            let ptr = usize::from_ne_bytes(heap.bytes[at..at+word].try_into().unwrap());
+
+            let ptr = match (ptr, to_native) {
+                (0, _) => 0,
+                (ptr, true) => {
+                    if ptr > heap.bytes.len() {
+                        panic!("cannot pass a pointer to an extern, the memory it points to holds the pointer {ptr} which points outside of the heap");
+                    }
+                    base + ptr
+                }
+                (ptr, false) => match heap.addr_of(ptr as *const c_void) {
+                    Some(addr) => addr,
+                    None => panic!("native code put a pointer to memory outside of the heap ({:#x}) into heap memory, which can't be used as a pointer (use a `u64` to hold it instead)", ptr),
+                },
+            };
+            heap.bytes[at..at+word].copy_from_slice(&ptr.to_ne_bytes());
+        }
+
+        elem_addr += stride;
+    }
+
+    return true;
+}
+
+// turns a typed pointer returned by native code into a heap pointer
+// memory outside of the heap is copied into a new allocation that the program owns
+unsafe fn pointer_from_native(ptr: *const c_void, typ: &Type, len: usize, site: &str, heap: &mut Heap, global_scope: &Scope, scope: &Scope) -> Values {
+    if let Some(addr) = heap.addr_of(ptr) {
+        return Values::POINTER(addr, 0);
+    }
+
+    let elem_type = Type { typ: typ.typ[1..].to_vec() };
+    let elem_size = get_type_size(&elem_type, scope, global_scope);
+
+    let len = match len {
+        0 => {
+            // count up to and including the terminator
+            let mut len = 1;
+            while slice::from_raw_parts((ptr as *const u8).add((len - 1) * elem_size), elem_size).iter().any(|b| *b != 0) {
+                len += 1;
+            }
+            len
+        }
+        _ => len,
+    };
+
+    let addr = heap.alloc(len * elem_size);
+    heap.write(addr, slice::from_raw_parts(ptr as *const u8, len * elem_size));
+
+    // pointers in the copy still point at native memory
+    swizzle(heap, addr, typ, false, global_scope, scope);
+
+    if gc::is_gc() && elem_type.typ.len() == 1 && !matches!(elem_type.typ[0], Types::STRUCT(_)) {
+        heap.set_no_scan(addr);
+    }
+    if heap.is_memcheck() {
+        heap.set_site(addr, format!("memory returned by {site}"));
+    }
 
-        let code_ptr = CodePtr::from_ptr(func.clone().into_raw().as_raw_ptr());
-        
+    return Values::POINTER(addr, addr);
+}
+
+// structs in modules are looked up by their full name
+fn qualified_name(module: &String, name: &String) -> String {
+    if module.is_empty() {
+        return name.clone();
+    }
+
+    return module.clone() + "." + name;
+}
+
+// a zeroed buffer for native code to use, aligned to 8 bytes
+fn native_buffer(size: usize) -> Vec<u64> {
+    return vec![0u64; size.div_ceil(8).max(1)];
+}
+
+// pushes a new struct onto `frame`, including any structs nested inside of it, and returns the index of its variables
+pub fn push_struct(name: &String, frame: usize, stack: &mut [Frame], global_scope: &Scope, scope: &Scope) -> usize {
+    let struct_type = get_struct(&String::new(), name, global_scope, scope);
+
+    let index = stack[frame].len();
+    for typ in &struct_type.var_types {
+        stack[frame].push_type(typ);
+    }
+
+    for i in 0..struct_type.var_types.len() {
+        if let Types::STRUCT(nested) = &struct_type.var_types[i].typ[0] {
+            let nested_index = push_struct(nested, frame, stack, global_scope, scope);
+
+            let slot = *struct_type.var_offsets.get(&struct_type.var_names[i]).unwrap();
+            stack[frame].stack[index + slot].val = Values::STRUCT(String::new(), nested.clone(), nested_index);
+        }
+    }
+
+    return index;
+}
+
+// turns a rainbow function into a native function pointer with the same signature
+unsafe fn get_callback(name: &String, scope: &Scope, global_scope: &Scope, module_frame: usize, global_frame: usize, module: &String) -> *mut c_void {
+    if !func_exists(name, scope, global_scope) {
+        panic!("cannot pass `{name}` to an extern as a function pointer, there is no function with that name");
+    }
+
+    let (module_name, frame, func) = get_callee(name, scope, global_scope, module_frame, global_frame, module);
+
+    return CALLBACKS.with_borrow_mut(|callbacks| {
+        let key = qualified_name(&module_name, &func.name);
+        if let Some(callback) = callbacks.get(&key) {
+            return callback.code.as_mut_ptr();
+        }
+
+        let (closure, code) = closure_alloc();
+        let mut callback = Box::new(Callback { func, module: module_name, module_frame: frame, cif: Default::default(), types: Vec::new(), arg_types: Vec::new(), var_type_storage: Vec::new(), code });
+
+        for typ in callback.func.arg_types.iter().chain([&callback.func.ret_type]) {
+            let ffi_type = match &typ.typ[0] {
+                Types::STRUCT(name) => struct_to_ffi(get_struct(&String::new(), name, global_scope, scope), &mut callback.types, &mut callback.var_type_storage, global_scope, scope),
+                _ => type_to_type(typ),
+            };
+
+            callback.types.push(Box::new(ffi_type));
+            callback.arg_types.push(&mut **callback.types.last_mut().unwrap() as *mut ffi_type);
+        }
+
+        let ret_type = callback.arg_types.pop().unwrap();
+        let arg_count = callback.arg_types.len();
+        let arg_types = callback.arg_types.as_mut_ptr();
+        prep_cif(&mut callback.cif, ffi_abi_FFI_DEFAULT_ABI, arg_count, ret_type, arg_types).unwrap();
+
+        let userdata = &mut *callback as *mut Callback;
+        prep_closure_mut(closure, &mut callback.cif, run_callback, userdata, code).unwrap();
+
+        callbacks.insert(key, callback);
+
+        return code.as_mut_ptr();
+    });
+}
+
+// native code calling a callback ends up here
+unsafe extern "C" fn run_callback(_cif: &ffi_cif, result: &mut u64, args: *const *const c_void, callback: &mut Callback) {
+    // a panic can't unwind through native code, it has already been reported by then so all that's left is to exit like it would have
+    if panic::catch_unwind(AssertUnwindSafe(|| exec_callback(result, args, callback))).is_err() {
+        process::exit(101);
+    }
+}
+
+// the function runs in a new frame on top of whatever called the extern
+unsafe fn exec_callback(result: &mut u64, args: *const *const c_void, callback: &mut Callback) {
+    let interpreter = INTERPRETER.get().unwrap_or_else(|| panic!("native code called function `{}` through a function pointer while no extern was running", callback.func.name));
+
+    let stack = &mut *interpreter.stack;
+    let scope = &*interpreter.scope;
+    let global_scope = &*interpreter.global_scope;
+    let global_frame = interpreter.global_frame;
+    let swizzled = &*interpreter.swizzled;
+
+    let top = stack.len() - 1;
+    let depth = stack[top].len();
+
+    for (i, typ) in callback.func.arg_types.iter().enumerate() {
+        let arg = *args.add(i) as *const u8;
+
+        match &typ.typ[0] {
+            Types::STRUCT(name) => {
+                let index = push_struct(name, top, stack, global_scope, scope);
+                struct_from_native(arg, name, index, top, stack, global_frame, global_scope, scope);
+
+                stack[top].push(Value { typ: typ.clone(), val: Values::STRUCT(String::new(), name.clone(), index) });
+            }
+            _ => {
+                let val = read_native(arg, typ, &stack[global_frame].heap);
+                stack[top].push(Value { typ: typ.clone(), val });
+            }
+        }
+    }
+
+    // the function sees the memory the extern was given as heap memory, and the heap stays put for the native code it returns to
+    for (addr, typ) in swizzled {
+        swizzle(&mut stack[global_frame].heap, *addr, typ, false, global_scope, scope);
+    }
+    stack[global_frame].heap.freeze();
+
+    exec_func(&callback.func, global_scope, scope, stack, callback.module_frame, global_frame, &callback.module);
+
+    stack[global_frame].heap.thaw();
+    for (addr, typ) in swizzled {
+        swizzle(&mut stack[global_frame].heap, *addr, typ, true, global_scope, scope);
+    }
+
+    let ret_type = &callback.func.ret_type;
+    if !matches!(ret_type.typ[0], Types::VOID) {
+        if stack[top].len() <= depth {
+            panic!("function `{}` was called from native code but did not return a value", callback.func.name);
+        }
+
+        let ret = stack[top].pop();
+        let result = result as *mut u64 as *mut u8;
+
+        match &ret.val {
+            Values::STRUCT(module, name, index) => struct_to_native(result, &qualified_name(module, name), *index, top, stack, global_frame, global_scope, scope),
+            _ => {
+                let mut val = Frame::get_default_val(ret_type);
+                val.set(&ret.val);
+                val.fit(ret_type);
+
+                // libffi wants integers returned as a whole register
+                match val {
+                    Values::SIGNED(n) => (result as *mut i64).write(n),
+                    Values::UNSIGNED(n) => (result as *mut u64).write(n),
+                    _ => write_native(result, ret_type, &val, &mut stack[global_frame].heap),
+                }
+            }
+        }
+    }
+
+    // clean up anything the arguments or the return value left behind
+    while stack[top].len() > depth {
+        stack[top].pop();
+    }
+}
+
+pub fn call_ffi(_extern: &Extern, stack: &mut Vec<Frame>, cur_frame: usize, module_frame: usize, global_frame: usize, scope: &Scope, global_scope: &Scope, module: &String) {
+    let func = match get_symbol(&_extern.dll, &_extern.name) {
+        Ok(func) => func,
+        Err(error) => panic!("failed to resolve extern `{}` from `{}`: {}", _extern.name, _extern.dll, error),
+    };
+
+    let code_ptr = CodePtr::from_ptr(func);
+    let site = format!("extern `{}`", _extern.name);
+
+    if !_extern.variadic {
+        let ret_len = pop_ret_len(&_extern.ret_type, &mut stack[cur_frame]);
         let args = stack[cur_frame].pop_args(_extern.arg_types.len(), global_scope, scope);
 
-        let mut types: Vec<ffi_type> = Vec::with_capacity(_extern.arg_types.len() * 4); // TODO: this is very jank
+        call_native(code_ptr, &_extern.ret_type, &_extern.arg_types, None, ret_len, &site, args, stack, cur_frame, module_frame, global_frame, scope, global_scope, module);
+        return;
+    }
+
+    // the number of variadic arguments is pushed last, so everything below it is laid out like any other call
+    let count = stack[cur_frame].pop();
+    let count = get_arg_count(&count);
+
+    let ret_len = pop_ret_len(&_extern.ret_type, &mut stack[cur_frame]);
+    let args = stack[cur_frame].pop_args(_extern.arg_types.len() + count, global_scope, scope);
+
+    let mut arg_types = _extern.arg_types.clone();
+    for arg in &args[_extern.arg_types.len()..] {
+        arg_types.push(promote(&arg[0]));
+    }
+
+    call_native(code_ptr, &_extern.ret_type, &arg_types, Some(_extern.arg_types.len()), ret_len, &site, args, stack, cur_frame, module_frame, global_frame, scope, global_scope, module);
+}
+
+// functions returning a typed pointer are given how many elements it points to after their arguments, 0 meaning up to the first zeroed element
+fn pop_ret_len(ret_type: &Type, frame: &mut Frame) -> usize {
+    if !matches!(ret_type.typ[0], Types::POINTER) || matches!(ret_type.typ[1], Types::VOID) {
+        return 0;
+    }
+
+    return get_arg_count(&frame.pop());
+}
+
+fn get_arg_count(count: &Value) -> usize {
+    return match count.val {
+        Values::SIGNED(n) if n >= 0 => n as usize,
+        Values::UNSIGNED(n) => n as usize,
+        _ => panic!("invalid argument count `{}` for a native call", count.val),
+    };
+}
+
+// the type a value is passed as when the function doesn't say, following C's default argument promotions
+fn promote(arg: &Value) -> Type {
+    return match (&arg.typ.typ[0], &arg.val) {
+        (_, Values::NAME(_)) => Type { typ: vec![Types::POINTER, Types::VOID] },
+        (Types::I8 | Types::I16 | Types::U8 | Types::U16, _) => Type { typ: vec![Types::I32] },
+        (Types::F16 | Types::F32, _) => Type { typ: vec![Types::F64] },
+        _ => arg.typ.clone(),
+    };
+}
+
+// calls the native function at the address in `addr`, the argument types are the types of the values passed to it
+pub fn call_pointer(addr: &Value, ret_type: &Type, arg_count: &Value, stack: &mut Vec<Frame>, cur_frame: usize, module_frame: usize, global_frame: usize, scope: &Scope, global_scope: &Scope, module: &String) {
+    let ptr = match addr.val {
+        Values::UNSIGNED(ptr) => ptr as usize,
+        Values::HANDLE(ptr, _) => ptr,
+        _ => panic!("cannot call `{}` of type `{}`, function pointers are held in handles or unsigned numbers", addr.val, addr.typ),
+    };
+
+    if ptr == 0 {
+        panic!("attempted to call a null function pointer");
+    }
+
+    let ret_len = pop_ret_len(ret_type, &mut stack[cur_frame]);
+    let args = stack[cur_frame].pop_args(get_arg_count(arg_count), global_scope, scope);
+
+    // functions are passed as function pointers
+    let arg_types = args.iter().map(|arg| match arg[0].val {
+        Values::NAME(_) => Type { typ: vec![Types::POINTER, Types::VOID] },
+        _ => arg[0].typ.clone(),
+    }).collect::<Vec<_>>();
+
+    call_native(CodePtr::from_ptr(ptr as *const c_void), ret_type, &arg_types, None, ret_len, "`CALLC`", args, stack, cur_frame, module_frame, global_frame, scope, global_scope, module);
+}
+
+// calls native code with `args` converted to `arg_types`, and pushes its return value
+// variadic functions are given how many of the arguments are fixed
+fn call_native(code_ptr: CodePtr, ret_type: &Type, arg_types: &[Type], fixed_args: Option<usize>, ret_len: usize, site: &str, args: Vec<Vec<Value>>, stack: &mut Vec<Frame>, cur_frame: usize, module_frame: usize, global_frame: usize, scope: &Scope, global_scope: &Scope, module: &String) {
+    unsafe {
+        // boxed so that the pointers handed to libffi stay put as more types are added
+        let mut types: Vec<Box<ffi_type>> = Vec::new();
 
         let mut var_type_storage: Vec<Vec<*mut ffi_type>> = Vec::new();
 
-        let mut arg_types: Vec<*mut ffi_type> = Vec::new();
-        let mut index = 0;
-        for typ in &_extern.arg_types {
+        let mut ffi_arg_types: Vec<*mut ffi_type> = Vec::new();
+        for (index, typ) in arg_types.iter().enumerate() {
             match typ.typ[0] {
                 Types::STRUCT(_) => {
                     match &args[index][0].val {
                         Values::STRUCT(module, name, _) => {
                             let _struct = get_struct(&module, &name, global_scope, scope);
                             
-                            let arg_type = struct_to_ffi(_struct, &mut types, &mut var_type_storage); 
-                            types.push(arg_type);
-                            arg_types.push(types.last_mut().unwrap() as *mut ffi_type);
+                            let arg_type = struct_to_ffi(_struct, &mut types, &mut var_type_storage, global_scope, scope); 
+                            types.push(Box::new(arg_type));
+                            ffi_arg_types.push(&mut **types.last_mut().unwrap() as *mut ffi_type);
                         }
                         _ => panic!("illegal type created, type is `STRUCT` value is {}", args[index][0])
                     }
                 }
                 _ => {
                     let arg_type = type_to_type(typ);
-                    types.push(arg_type);
-                    arg_types.push(types.last_mut().unwrap() as *mut ffi_type);
+                    types.push(Box::new(arg_type));
+                    ffi_arg_types.push(&mut **types.last_mut().unwrap() as *mut ffi_type);
                 }
             }
-            index += 1;
         }
 
         let mut cif: ffi_cif = Default::default();
 
-        let mut ret_type = match &_extern.ret_type.typ[0] {
+        let mut ffi_ret_type = match &ret_type.typ[0] {
             Types::STRUCT(name) => {
                 let _struct = get_struct(&String::new(), &name, global_scope, scope);
                 
-                struct_to_ffi(_struct, &mut types, &mut var_type_storage) 
+                struct_to_ffi(_struct, &mut types, &mut var_type_storage, global_scope, scope) 
             }
-            _ => type_to_type(&_extern.ret_type)
+            _ => type_to_type(ret_type)
         };
 
         let mut raw_args:      Vec<*mut c_void> = Vec::new();
 
-        let mut signed_args:   Vec<i64>         = Vec::new();
-        let mut unsigned_args: Vec<u64>         = Vec::new();
-        let mut f32_args:      Vec<f32>         = Vec::new();
-        let mut f64_args:      Vec<f64>         = Vec::new();
-
-        let mut pp:            Vec<*mut c_void> = Vec::new();
-        let mut s8p:           Vec<Vec<i8>>     = Vec::new();
-        let mut s16p:          Vec<Vec<i16>>    = Vec::new();
-        let mut s32p:          Vec<Vec<i32>>    = Vec::new();
-        let mut s64p:          Vec<Vec<i64>>    = Vec::new();
-        let mut u8p:           Vec<Vec<u8>>     = Vec::new();
-        let mut u16p:          Vec<Vec<u16>>    = Vec::new();
-        let mut u32p:          Vec<Vec<u32>>    = Vec::new();
-        let mut u64p:          Vec<Vec<u64>>    = Vec::new();
-        let mut f32p:          Vec<Vec<f32>>    = Vec::new();
-        let mut f64p:          Vec<Vec<f64>>    = Vec::new();
-
-        let mut struct_data:   Vec<Vec<u8>>     = Vec::new();
+        // raw_args points into these, so they must never reallocate
+        let mut signed_args:   Vec<i64>         = Vec::with_capacity(args.len());
+        let mut unsigned_args: Vec<u64>         = Vec::with_capacity(args.len());
+        let mut f32_args:      Vec<f32>         = Vec::with_capacity(args.len());
+        let mut f64_args:      Vec<f64>         = Vec::with_capacity(args.len());
+
+        let mut pointer_args:  Vec<*mut c_void> = Vec::with_capacity(args.len());
+
+        // the buffers themselves are on the heap, so these can grow
+        let mut struct_data:   Vec<Vec<u64>>    = Vec::new();
+        let mut write_backs:   Vec<(usize, usize, usize, usize)> = Vec::new(); // buffer, then the frame index, generation and slot of the reference
+        let mut swizzled:      Vec<(usize, Type)> = Vec::new();
+        let mut swizzled_allocs: HashSet<usize>  = HashSet::new();
 
         let mut i = 0;
         for arg in &args {
@@ -187,7 +810,7 @@ pub fn call_ffi(_extern: &Extern, stack: &mut Vec<Frame>, cur_frame: usize, glob
                     raw_args.push(unsigned_args.last_mut().unwrap() as *mut _ as *mut c_void);
                 }
                 Values::DECIMAL(n) => {
-                    match &_extern.arg_types[i].typ[0] {
+                    match &arg_types[i].typ[0] {
                         Types::F32 => {
                             f32_args.push(*n as f32);
                             raw_args.push(f32_args.last_mut().unwrap() as *mut _ as *mut c_void);
@@ -196,75 +819,94 @@ pub fn call_ffi(_extern: &Extern, stack: &mut Vec<Frame>, cur_frame: usize, glob
                             f64_args.push(*n);
                             raw_args.push(f64_args.last_mut().unwrap() as *mut _ as *mut c_void);
                         }
-                        _ => panic!("type mismatch, got {} expected {}", arg[0], _extern.arg_types[i])
+                        _ => panic!("type mismatch, got {} expected {}", arg[0], arg_types[i])
                     }
                 }
-                Values::POINTER(p, s) => {
-                    let val = &stack[global_frame].stack[*p..*p+*s];
-                    let ptr = get_pointer(val, &mut pp, &mut s8p, &mut s16p, &mut s32p, &mut s64p, &mut u8p, &mut u16p, &mut u32p, &mut u64p, &mut f32p, &mut f64p);
+                Values::POINTER(p, _) => {
+                    // heap memory is handed to native code directly, so any writes land in place
+                    // the same allocation can be passed more than once (or pointed into by more than one argument), its pointers are only swapped the first time
+                    let alloc = stack[global_frame].heap.find_alloc(*p).map(|(start, _)| start);
+                    if alloc.is_some_and(|start| !swizzled_allocs.contains(&start)) && swizzle(&mut stack[global_frame].heap, *p, &arg_types[i], true, global_scope, scope) {
+                        swizzled_allocs.insert(alloc.unwrap());
+                        swizzled.push((*p, arg_types[i].clone()));
+                    }
+                    stack[global_frame].heap.pin(*p);
 
-                    raw_args.push(ptr);
+                    pointer_args.push(stack[global_frame].heap.as_ptr(*p));
+                    raw_args.push(pointer_args.last_mut().unwrap() as *mut _ as *mut c_void);
                 }
-                Values::STRUCT(module, name, _) => {
-                    let struct_type = get_struct(module, name, global_scope, scope);
-
-                    let struct_size = get_struct_size(&struct_type);
-                    let mut struct_bytes = vec![0u8; struct_size];
-
-                    let mut offset = 0;
-                    let mut i = 0;
-                    for typ in struct_type.var_types {
-                        let val = &arg[i + 1];
-
-                        let val_ptr = struct_bytes.as_mut_ptr().add(offset);
-
-                        match val.val {
-                            Values::SIGNED(num) => {
-                                match typ.typ[0] {
-                                    Types::I8  => *(val_ptr as *mut i8)  = num as i8,
-                                    Types::I16 => *(val_ptr as *mut i16) = num as i16,
-                                    Types::I32 => *(val_ptr as *mut i32) = num as i32,
-                                    Types::I64 => *(val_ptr as *mut i64) = num as i64,
-                                    _ => panic!("type mismatch, expected `{}` got `{}`", typ, val.typ)
-                                }
-                                offset += typ.typ[0].get_size();
-                            }
-                            Values::UNSIGNED(num) => {
-                                match typ.typ[0] {
-                                    Types::U8  => *(val_ptr as *mut u8)  = num as u8,
-                                    Types::U16 => *(val_ptr as *mut u16) = num as u16,
-                                    Types::U32 => *(val_ptr as *mut u32) = num as u32,
-                                    Types::U64 => *(val_ptr as *mut u64) = num as u64,
-                                    _ => panic!("type mismatch, expected `{}` got `{}`", typ, val.typ)
-                                }
-                                offset += typ.typ[0].get_size();
-                            }
-                            Values::DECIMAL(num) => {
-                                match typ.typ[0] {
-                                    Types::F16 => todo!("f16 not supported by ffi yet"), 
-                                    Types::F32 => *(val_ptr as *mut f32) = num as f32,
-                                    Types::F64 => *(val_ptr as *mut f64) = num as f64,
-                                    _ => panic!("type mismatch, expected `{}` got `{}`", typ, val.typ)
-                                }
-                                offset += typ.typ[0].get_size();
-                            }
-                            _ => todo!("unsupported value in struct {}", val)
-                        }
+                Values::STRUCT(module, name, index) => {
+                    let name = qualified_name(module, name);
+                    let struct_type = get_struct(&String::new(), &name, global_scope, scope);
 
-                        i += 1;
-                    }
+                    let mut struct_bytes = native_buffer(struct_type.size);
+                    struct_to_native(struct_bytes.as_mut_ptr() as *mut u8, &name, *index, cur_frame, stack, global_frame, global_scope, scope);
 
                     raw_args.push(struct_bytes.as_mut_ptr() as *mut c_void);
                     struct_data.push(struct_bytes);
                 }
+                Values::REFERENCE(frame, gen, slot) => {
+                    // stack values don't live in native memory, so they are copied out for the call and copied back in after it
+                    let value = Frame::get_ref(stack, *frame, *gen, *slot, 0).clone();
+
+                    let mut bytes = match &value.val {
+                        Values::STRUCT(module, name, index) => {
+                            let name = qualified_name(module, name);
+                            let struct_type = get_struct(&String::new(), &name, global_scope, scope);
+
+                            let mut bytes = native_buffer(struct_type.size);
+                            struct_to_native(bytes.as_mut_ptr() as *mut u8, &name, *index, *frame, stack, global_frame, global_scope, scope);
+                            bytes
+                        }
+                        val => {
+                            let mut bytes = native_buffer(value.typ.get_size());
+                            write_native(bytes.as_mut_ptr() as *mut u8, &value.typ, val, &mut stack[global_frame].heap);
+                            bytes
+                        }
+                    };
+
+                    pointer_args.push(bytes.as_mut_ptr() as *mut c_void);
+                    raw_args.push(pointer_args.last_mut().unwrap() as *mut _ as *mut c_void);
+
+                    write_backs.push((struct_data.len(), *frame, *gen, *slot));
+                    struct_data.push(bytes);
+                }
+                Values::HANDLE(h, _) => {
+                    match arg_types[i].typ[0] {
+                        Types::POINTER | Types::HANDLE(_) => {
+                            pointer_args.push(*h as *mut c_void);
+                            raw_args.push(pointer_args.last_mut().unwrap() as *mut _ as *mut c_void);
+                        }
+                        // externs declared before handles existed take native pointers as a number
+                        Types::U64 | Types::I64 => {
+                            unsigned_args.push(*h as u64);
+                            raw_args.push(unsigned_args.last_mut().unwrap() as *mut _ as *mut c_void);
+                        }
+                        _ => panic!("type mismatch, got a handle expected {}", arg_types[i]),
+                    }
+                }
+                Values::NAME(name) => {
+                    if !matches!(arg_types[i].typ[0], Types::POINTER) {
+                        panic!("type mismatch, got function `{}` expected {}", name, arg_types[i]);
+                    }
+
+                    pointer_args.push(get_callback(name, scope, global_scope, module_frame, global_frame, module));
+                    raw_args.push(pointer_args.last_mut().unwrap() as *mut _ as *mut c_void);
+                }
                 _ => panic!("unsupported type `{}` for externs (value: `{}`)", arg[0].typ, arg[0].val),
             }
             i += 1;
         }
 
-        prep_cif(&mut cif, ffi_abi_FFI_DEFAULT_ABI, _extern.arg_types.len(), addr_of_mut!(ret_type), arg_types.as_mut_ptr()).unwrap();
+        match fixed_args {
+            Some(fixed_args) => prep_cif_var(&mut cif, ffi_abi_FFI_DEFAULT_ABI, fixed_args, arg_types.len(), addr_of_mut!(ffi_ret_type), ffi_arg_types.as_mut_ptr()).unwrap(),
+            None => prep_cif(&mut cif, ffi_abi_FFI_DEFAULT_ABI, arg_types.len(), addr_of_mut!(ffi_ret_type), ffi_arg_types.as_mut_ptr()).unwrap(),
+        }
+
+        // callbacks run on top of this call
+        let outer = INTERPRETER.replace(Some(Interpreter { stack: stack as *mut Vec<Frame>, scope, global_scope, global_frame, swizzled: &swizzled }));
 
-        let val = match &_extern.ret_type.typ[0] {
+        let val = match &ret_type.typ[0] {
             Types::VOID => {
                 call::<c_void>(&mut cif, code_ptr, raw_args.as_mut_ptr());
                 Values::VOID
@@ -310,80 +952,137 @@ pub fn call_ffi(_extern: &Extern, stack: &mut Vec<Frame>, cur_frame: usize, glob
                 Values::DECIMAL(result)
             },
             Types::POINTER => {
-                match _extern.ret_type.typ[1] {
+                match ret_type.typ[1] {
                     Types::VOID => {
                         let result: *const c_void = call::<*const c_void>(&mut cif, code_ptr, raw_args.as_mut_ptr());
-                        Values::UNSIGNED(result as u64)
+                        Values::HANDLE(result as usize, String::new())
+                    }
+                    _ => {
+                        let result: *const c_void = call::<*const c_void>(&mut cif, code_ptr, raw_args.as_mut_ptr());
+                        pointer_from_native(result, ret_type, ret_len, site, &mut stack[global_frame].heap, global_scope, scope)
                     }
-                    _ => panic!("unsupported return type `{}`", _extern.ret_type),
                 }
             }
             Types::STRUCT(name) => {
                 let struct_type = get_struct(&String::new(), name, global_scope, scope);
-                let struct_size = get_struct_size(&struct_type);
                 
-                let mut struct_data = vec![0u8; struct_size];
-                ffi_call(&mut cif as *mut ffi_cif, Some(*code_ptr.as_fun()), struct_data.as_mut_ptr() as *mut c_void, raw_args.as_mut_ptr());
+                let mut ret_data = native_buffer(struct_type.size);
+                ffi_call(&mut cif as *mut ffi_cif, Some(*code_ptr.as_fun()), ret_data.as_mut_ptr() as *mut c_void, raw_args.as_mut_ptr());
+                save_errno();
+
+                let index = push_struct(name, cur_frame, stack, global_scope, scope);
+                struct_from_native(ret_data.as_ptr() as *const u8, name, index, cur_frame, stack, global_frame, global_scope, scope);
 
-                struct_from_bytes(name, &struct_type, &mut struct_data, &mut stack[cur_frame])
+                Values::STRUCT(String::new(), name.clone(), index)
             }
-            _ => panic!("unsupported return type `{}`", _extern.ret_type),
+            Types::HANDLE(dtor) => {
+                let result: *const c_void = call::<*const c_void>(&mut cif, code_ptr, raw_args.as_mut_ptr());
+                Values::HANDLE(result as usize, dtor.clone())
+            }
+            _ => panic!("unsupported return type `{}`", ret_type),
         };
 
-        for i in 0..arg_types.len() {
-            let arg = raw_args[i];
-            let typ = _extern.arg_types[i].clone();
+        INTERPRETER.set(outer);
 
-            match typ.typ[0] {
-                Types::POINTER => {
-                    let index;
-                    let len;
-                    match args[i][0].val {
-                        Values::POINTER(p, s) => {
-                            index = p;
-                            len = s;
-                        }
-                        _ => panic!("type mismatch, expected POINTER got {} (value: {})", args[i][0].typ, args[i][0].val)
-                    }
+        for (addr, typ) in &swizzled {
+            swizzle(&mut stack[global_frame].heap, *addr, typ, false, global_scope, scope);
+        }
+        for arg in &args {
+            if let Values::POINTER(p, _) = arg[0].val {
+                stack[global_frame].heap.unpin(p);
+            }
+        }
 
-                    let pointer = *(arg as *mut *mut c_void);
-                    for j in 0..len {
-                        let pointer_val = match typ.typ[1] {
-                            Types::VOID      => &Values::VOID,
-                            Types::I8        => &Values::SIGNED(*(pointer.wrapping_add(j)   as *mut i8)  as i64),
-                            Types::I16       => &Values::SIGNED(*(pointer.wrapping_add(j)   as *mut i16) as i64),
-                            Types::I32       => &Values::SIGNED(*(pointer.wrapping_add(j)   as *mut i32) as i64),
-                            Types::I64       => &Values::SIGNED(*(pointer.wrapping_add(j)   as *mut i64) as i64),
-                            Types::U8        => &Values::UNSIGNED(*(pointer.wrapping_add(j) as *mut u8)  as u64),
-                            Types::U16       => &Values::UNSIGNED(*(pointer.wrapping_add(j) as *mut u16) as u64),
-                            Types::U32       => &Values::UNSIGNED(*(pointer.wrapping_add(j) as *mut u32) as u64),
-                            Types::U64       => &Values::UNSIGNED(*(pointer.wrapping_add(j) as *mut u64) as u64),
-                            Types::F16       => todo!(),
-                            Types::F32       => &Values::DECIMAL(*(pointer.wrapping_add(j)  as *mut f32) as f64),
-                            Types::F64       => &Values::DECIMAL(*(pointer.wrapping_add(j)  as *mut f64) as f64),
-                            Types::POINTER   => todo!(),
-                            Types::TYPE      => todo!(),
-                            Types::STRUCT(_) => todo!(),
-                            Types::NAME      => todo!(),
-                        };
-
-                        stack[global_frame].stack[index + j].set(pointer_val);
-                    }
+        // copy anything native code wrote through references back to the stack
+        for (buffer, frame, gen, slot) in write_backs {
+            let value = Frame::get_ref(stack, frame, gen, slot, 0).clone();
+            let ptr = struct_data[buffer].as_ptr() as *const u8;
+
+            match &value.val {
+                Values::STRUCT(module, name, index) => struct_from_native(ptr, &qualified_name(module, name), *index, frame, stack, global_frame, global_scope, scope),
+                _ => {
+                    let val = read_native(ptr, &value.typ, &stack[global_frame].heap);
+                    Frame::get_ref(stack, frame, gen, slot, 0).set(&val);
                 }
-                _ => ()
             }
         }
 
-        stack[cur_frame].push(Value { typ: _extern.ret_type.clone(), val });
+        stack[cur_frame].push(Value { typ: ret_type.clone(), val });
     }
 }
 
-fn get_struct_size(_struct: &Struct) -> usize {
-    let mut size = 0;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ptr_to_ptr() -> Type {
+        Type { typ: vec![Types::POINTER, Types::POINTER, Types::U8] }
+    }
+
+    // an allocation holding a pointer to a one byte allocation holding `byte`
+    fn pointer_array(heap: &mut Heap, byte: u8) -> usize {
+        let target = heap.alloc(1);
+        heap.write(target, &[byte]);
+
+        let array = heap.alloc(16);
+        heap.write(array, &target.to_ne_bytes());
+        heap.write(array + 8, &0usize.to_ne_bytes());
+
+        array
+    }
+
+    fn stored_ptr(heap: &Heap, addr: usize) -> usize {
+        usize::from_ne_bytes(heap.bytes[addr..addr + 8].try_into().unwrap())
+    }
+
+    #[test]
+    fn swizzle_round_trip() {
+        let scope = Scope::new();
+        let mut stack = vec![Frame::new()];
+        let heap = &mut stack[0].heap;
+        let array = pointer_array(heap, 7);
+        let target = stored_ptr(heap, array);
+
+        assert!(swizzle(heap, array, &ptr_to_ptr(), true, &scope, &scope));
+        assert_eq!(stored_ptr(heap, array), heap.bytes.as_ptr() as usize + target);
+        assert_eq!(stored_ptr(heap, array + 8), 0);
+
+        assert!(swizzle(heap, array, &ptr_to_ptr(), false, &scope, &scope));
+        assert_eq!(stored_ptr(heap, array), target);
+        assert_eq!(stored_ptr(heap, array + 8), 0);
+    }
+
+    #[test]
+    fn swizzle_skips_memory_without_pointers() {
+        let scope = Scope::new();
+        let mut stack = vec![Frame::new()];
+        let heap = &mut stack[0].heap;
+        let array = pointer_array(heap, 7);
 
-    for typ in &_struct.var_types {
-        size += typ.get_size();
+        assert!(!swizzle(heap, array, &Type { typ: vec![Types::POINTER, Types::U64] }, true, &scope, &scope));
     }
 
-    return size;
+    extern "C" fn first_bytes(a: *const *const u8, b: *const *const u8) -> u64 {
+        unsafe { (**a + **b) as u64 }
+    }
+
+    #[test]
+    fn same_allocation_passed_twice() {
+        let scope = Scope::new();
+        let mut stack = vec![Frame::new()];
+        let array = pointer_array(&mut stack[0].heap, 20);
+        let target = stored_ptr(&stack[0].heap, array);
+
+        for _ in 0..2 {
+            stack[0].push(Value { typ: ptr_to_ptr(), val: Values::POINTER(array, array) });
+        }
+
+        let func = Value { typ: Type { typ: vec![Types::U64] }, val: Values::UNSIGNED(first_bytes as *const () as u64) };
+        let count = Value { typ: Type { typ: vec![Types::U64] }, val: Values::UNSIGNED(2) };
+        call_pointer(&func, &Type { typ: vec![Types::U64] }, &count, &mut stack, 0, 0, 0, &scope, &scope, &String::new());
+
+        assert_eq!(stack[0].pop().val, Values::UNSIGNED(40));
+        assert_eq!(stored_ptr(&stack[0].heap, array), target);
+        assert!(stack[0].heap.pinned().is_empty());
+    }
 }
@@ -1,11 +1,25 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
 use indexmap::IndexMap;
 
-use crate::{_type::{Type, Types}, get_struct, value::{Value, Values}, scope::Scope};
+use crate::{_type::{Type, Types}, get_struct, heap::Heap, value::{Value, Values}, scope::Scope};
+
+// every slot gets a unique generation when it is pushed, so a reference can tell when the value it points to is gone
+// even if its frame was popped or the slot was popped and pushed again
+static NEXT_GEN: AtomicUsize = AtomicUsize::new(1);
+
+fn new_gen() -> usize {
+    return NEXT_GEN.fetch_add(1, Ordering::Relaxed);
+}
 
 #[derive(Debug, Clone)]
 pub struct Frame {
     pub vars: IndexMap<String, usize>,
     pub stack: Vec<Value>,
+    gens: Vec<usize>, // the generation of each slot of `stack`
+
+    // values made by `REF` on an immediate, they live beside the stack so pushes and pops don't touch them
+    temps: Vec<(usize, Value)>,
 
     // TODO: the current allocs system will not work if multiple pointers point to one place,
     //       as the new pointer will try to say it owns the space
@@ -15,15 +29,73 @@ pub struct Frame {
     //        if someone uses FREE with the right size
     //        the pointer will point to nothing but still exist)
     pub allocs: Vec<String>,
+
+    // only the global frame's heap is used, pointers are byte addresses into it
+    pub heap: Heap,
 }
 
 impl Frame {
+    pub fn new() -> Frame {
+        return Frame { vars: IndexMap::new(), stack: Vec::new(), gens: Vec::new(), temps: Vec::new(), allocs: Vec::new(), heap: Heap::new() };
+    }
+
+    // the generation a reference to `slot` has to carry
+    pub fn slot_gen(&self, slot: usize) -> usize {
+        return self.gens[slot];
+    }
+
+    // stores a temporary for a reference, gives its generation and index
+    pub fn push_temp(&mut self, val: Value) -> (usize, usize) {
+        let gen = new_gen();
+        self.temps.push((gen, val));
+
+        return (gen, self.temps.len() - 1);
+    }
+
+    pub fn temps_len(&self) -> usize {
+        return self.temps.len();
+    }
+
+    pub fn truncate_temps(&mut self, len: usize) {
+        self.temps.truncate(len);
+    }
+
+    pub fn temps(&self) -> impl Iterator<Item = &Value> {
+        return self.temps.iter().map(|(_, val)| val);
+    }
+
+    // checks that a reference still points at a live value, and returns the value `offset` slots after it
+    pub fn get_ref(stack: &mut [Frame], frame: usize, gen: usize, slot: usize, offset: usize) -> &mut Value {
+        if frame < stack.len() {
+            let f = &stack[frame];
+
+            if f.gens.get(slot) == Some(&gen) {
+                if slot + offset >= f.stack.len() {
+                    panic!("reference offset {offset} is past the end of the stack");
+                }
+
+                return &mut stack[frame].stack[slot + offset];
+            }
+
+            if f.temps.get(slot).is_some_and(|(temp_gen, _)| *temp_gen == gen) {
+                if offset != 0 {
+                    panic!("reference offset {offset} is past the end of the value");
+                }
+
+                return &mut stack[frame].temps[slot].1;
+            }
+        }
+
+        panic!("dangling reference: the value it points to has been popped");
+    }
+
     pub fn len(&self) -> usize {
         return self.stack.len();
     }
 
     pub fn push(&mut self, val: Value) {
         self.stack.push(val);
+        self.gens.push(new_gen());
         self.allocs.push(String::new());
     }
     
@@ -40,6 +112,7 @@ impl Frame {
             }
         }
 
+        self.gens.pop();
         return self.stack.pop().expect("attempted to pop empty stack");
     }
 
@@ -47,6 +120,7 @@ impl Frame {
         let mut res: Vec<Value> = Vec::new();
 
         let val = self.stack.remove(index);
+        self.gens.remove(index);
         match &val.val {
             Values::STRUCT(module, _struct, index) => {
                 let index = *index; // rust
@@ -106,23 +180,21 @@ impl Frame {
         return &self.stack[index];
     }
 
-    pub fn push_alloc(self: &mut Frame, typ: &Type, alloc: String) {
-        let value = Self::get_default_val(typ);
-
-        self.stack.push(Value { typ: typ.clone(), val: value });
-        self.allocs.push(alloc);
-    }
-
     pub fn push_type(self: &mut Frame, typ: &Type) {
         let value = Self::get_default_val(typ);
         
         self.stack.push(Value { typ: typ.clone(), val: value });
+        self.gens.push(new_gen());
         self.allocs.push(String::new());
     }
 
     pub fn push_var(&mut self, name: &String, typ: Type, value: Values) {
+        let mut val = Value { val: Self::get_default_val(&typ), typ };
+        val.set(&value);
+
         let index = self.stack.len();
-        self.stack.push(Value { typ, val: value });
+        self.stack.push(val);
+        self.gens.push(new_gen());
         self.vars.insert(name.clone(), index);
         self.allocs.push(name.clone());
     }
@@ -141,10 +213,11 @@ impl Frame {
             Types::F16 => Values::DECIMAL(0f64),
             Types::F32 => Values::DECIMAL(0f64),
             Types::F64 => Values::DECIMAL(0f64),
-            Types::POINTER => Values::POINTER(usize::MAX, 0),
+            Types::POINTER => Values::POINTER(0, 0), // null
             Types::TYPE => Values::TYPE(Type { typ: vec![Types::VOID] }),
             Types::STRUCT(t) => Values::STRUCT(String::new(), t.clone(), usize::MAX),
             Types::NAME => Values::NAME("".to_string()),
+            Types::HANDLE(d) => Values::HANDLE(0, d.clone()), // null
         }
     }
 
@@ -154,6 +227,7 @@ impl Frame {
 
         let index = self.stack.len();
         self.stack.push(value);
+        self.gens.push(new_gen());
         self.vars.insert(name.clone(), index);
         self.allocs.push(name.clone());
     }
@@ -162,17 +236,9 @@ impl Frame {
         self.allocs.append(&mut other.allocs);
         
         let len = self.stack.len();
-        for val in other.stack {
-            match val.val {
-                Values::POINTER(p, s) => {
-                    self.stack.push(Value { typ: val.typ, val: Values::POINTER(p + len, s) });
-                }
-                Values::STRUCT(module, name, index) => {
-                    self.stack.push(Value { typ: val.typ, val: Values::STRUCT(module, name, index) });
-                }
-                _ => self.stack.push(val),
-            }
-        }
+        self.stack.append(&mut other.stack);
+        self.gens.append(&mut other.gens);
+        self.temps.append(&mut other.temps);
 
         for (var, index) in other.vars {
             self.vars.insert(var, index + len);
@@ -181,7 +247,82 @@ impl Frame {
 
     pub fn clear(&mut self) {
         self.stack.clear();
+        self.gens.clear();
+        self.temps.clear();
         self.allocs.clear();
         self.vars.clear();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn num(n: i64) -> Value {
+        Value { typ: Type { typ: vec![Types::I64] }, val: Values::SIGNED(n) }
+    }
+
+    #[test]
+    fn reference_to_live_slot() {
+        let mut stack = vec![Frame::new()];
+        stack[0].push(num(1));
+        let gen = stack[0].slot_gen(0);
+
+        Frame::get_ref(&mut stack, 0, gen, 0, 0).set(&Values::SIGNED(2));
+        assert_eq!(stack[0].get(0).val, Values::SIGNED(2));
+    }
+
+    #[test]
+    #[should_panic(expected = "dangling reference")]
+    fn reference_to_refilled_slot() {
+        let mut stack = vec![Frame::new()];
+        stack[0].push(num(1));
+        let gen = stack[0].slot_gen(0);
+
+        stack[0].pop();
+        stack[0].push(num(2));
+
+        Frame::get_ref(&mut stack, 0, gen, 0, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "dangling reference")]
+    fn reference_into_replaced_frame() {
+        let mut stack = vec![Frame::new(), Frame::new()];
+        stack[1].push(num(1));
+        let gen = stack[1].slot_gen(0);
+
+        stack.pop();
+        stack.push(Frame::new());
+        stack[1].push(num(2));
+
+        Frame::get_ref(&mut stack, 1, gen, 0, 0);
+    }
+
+    #[test]
+    fn temporary_survives_pushes_and_pops() {
+        let mut stack = vec![Frame::new()];
+        stack[0].push(num(5));
+        let (gen, slot) = stack[0].push_temp(num(7));
+
+        assert_eq!(stack[0].pop().val, Values::SIGNED(5));
+        stack[0].push(num(9));
+        stack[0].push(num(10));
+        stack[0].pop();
+
+        assert_eq!(Frame::get_ref(&mut stack, 0, gen, slot, 0).val, Values::SIGNED(7));
+    }
+
+    #[test]
+    #[should_panic(expected = "dangling reference")]
+    fn temporary_removed_with_scope() {
+        let mut stack = vec![Frame::new()];
+        let start = stack[0].temps_len();
+        let (gen, slot) = stack[0].push_temp(num(7));
+
+        stack[0].truncate_temps(start);
+        stack[0].push_temp(num(8));
+
+        Frame::get_ref(&mut stack, 0, gen, slot, 0);
+    }
+}
@@ -18,6 +18,7 @@ pub struct Extern {
     pub ret_type: Type,
 
     pub arg_types: Vec<Type>,
+    pub variadic: bool, // more arguments can be passed after `arg_types`
 
     pub dll: String,
 }
\ No newline at end of file
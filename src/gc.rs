@@ -0,0 +1,198 @@
+use std::{collections::BTreeSet, sync::atomic::{AtomicBool, Ordering}};
+
+use crate::{frame::Frame, heap::Heap, value::Values};
+
+// when set, allocations that can no longer be reached are freed automatically
+static GC: AtomicBool = AtomicBool::new(false);
+
+pub fn set_gc(gc: bool) {
+    GC.store(gc, Ordering::Relaxed);
+}
+
+pub fn is_gc() -> bool {
+    GC.load(Ordering::Relaxed)
+}
+
+// collects garbage if enough has been allocated since the last collection
+pub fn maybe_collect(stack: &mut [Frame], global_frame: usize) {
+    if is_gc() && stack[global_frame].heap.gc_due() {
+        collect(stack, global_frame);
+    }
+}
+
+// frees every allocation that can't be reached from a pointer on the stack, returns how many were freed
+pub fn collect(stack: &mut [Frame], global_frame: usize) -> usize {
+    let heap = &stack[global_frame].heap;
+
+    let mut marked = BTreeSet::new();
+    let mut work = Vec::new();
+
+    // every variable, struct field, argument and pushed value of every frame lives in a frame's stack
+    for frame in stack.iter() {
+        for value in frame.stack.iter().chain(frame.temps()) {
+            if let Values::POINTER(addr, _) = value.val {
+                mark(heap, addr, &mut marked, &mut work);
+            }
+        }
+    }
+
+    // the data section is never freed, and anything it points to is reachable
+    for addr in heap.static_allocs() {
+        mark(heap, addr, &mut marked, &mut work);
+    }
+
+    // pointers that were passed to an extern aren't on the stack anymore, but native code can still use them during a callback
+    for addr in heap.pinned() {
+        mark(heap, *addr, &mut marked, &mut work);
+    }
+
+    // memory is untyped, so every aligned word of a reachable allocation that points into an allocation is treated as a pointer
+    let word = size_of::<usize>();
+    while let Some(addr) = work.pop() {
+        if heap.is_no_scan(addr) {
+            continue;
+        }
+
+        let size = heap.alloc_size(addr).unwrap();
+        let mut offset = 0;
+        while offset + word <= size {
+            let ptr = usize::from_ne_bytes(heap.bytes[addr+offset..addr+offset+word].try_into().unwrap());
+            mark(heap, ptr, &mut marked, &mut work);
+
+            offset += word;
+        }
+    }
+
+    let garbage = heap.allocs.keys().filter(|addr| !marked.contains(*addr)).copied().collect::<Vec<_>>();
+
+    let heap = &mut stack[global_frame].heap;
+    for addr in &garbage {
+        heap.free(*addr);
+    }
+    heap.finish_collection(garbage.len());
+
+    garbage.len()
+}
+
+// marks the allocation `addr` points into, pointers into the middle of an allocation keep all of it alive
+fn mark(heap: &Heap, addr: usize, marked: &mut BTreeSet<usize>, work: &mut Vec<usize>) {
+    if let Some((start, size)) = heap.find_alloc(addr) {
+        if addr < start + size.max(1) && marked.insert(start) {
+            work.push(start);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{_type::{Type, Types}, value::Value};
+
+    fn pointer(addr: usize) -> Value {
+        Value { typ: Type { typ: vec![Types::POINTER, Types::U8] }, val: Values::POINTER(addr, addr) }
+    }
+
+    // the global frame and a function's frame above it
+    fn stack() -> Vec<Frame> {
+        vec![Frame::new(), Frame::new()]
+    }
+
+    fn is_live(stack: &[Frame], addr: usize) -> bool {
+        stack[0].heap.alloc_size(addr).is_some()
+    }
+
+    #[test]
+    fn free_unreachable() {
+        let mut stack = stack();
+        let a = stack[0].heap.alloc(8);
+        let b = stack[0].heap.alloc(8);
+
+        // pointing at each other doesn't keep them alive
+        stack[0].heap.write(a, &b.to_ne_bytes());
+        stack[0].heap.write(b, &a.to_ne_bytes());
+
+        assert_eq!(collect(&mut stack, 0), 2);
+        assert!(stack[0].heap.allocs.is_empty());
+        assert_eq!(stack[0].heap.stats.collections, 1);
+        assert_eq!(stack[0].heap.stats.collected, 2);
+    }
+
+    #[test]
+    fn keep_stack_slots_and_temps() {
+        let mut stack = stack();
+        let a = stack[0].heap.alloc(8);
+        let b = stack[0].heap.alloc(8);
+        let c = stack[0].heap.alloc(8);
+
+        stack[0].push(pointer(a));
+        stack[1].push(pointer(b));
+        stack[1].push_temp(pointer(c));
+
+        assert_eq!(collect(&mut stack, 0), 0);
+        assert!(is_live(&stack, a) && is_live(&stack, b) && is_live(&stack, c));
+    }
+
+    #[test]
+    fn keep_interior_pointers() {
+        let mut stack = stack();
+        let a = stack[0].heap.alloc(16);
+        stack[1].push(pointer(a + 12));
+
+        assert_eq!(collect(&mut stack, 0), 0);
+        assert!(is_live(&stack, a));
+    }
+
+    #[test]
+    fn keep_what_reachable_memory_points_to() {
+        let mut stack = stack();
+        let a = stack[0].heap.alloc(16);
+        let b = stack[0].heap.alloc(8);
+        let c = stack[0].heap.alloc(8);
+        let garbage = stack[0].heap.alloc(8);
+
+        // a -> b (into the middle of it) -> c
+        stack[0].heap.write(a, &0usize.to_ne_bytes());
+        stack[0].heap.write(a + 8, &(b + 4).to_ne_bytes());
+        stack[0].heap.write(b, &c.to_ne_bytes());
+        stack[1].push(pointer(a));
+
+        assert_eq!(collect(&mut stack, 0), 1);
+        assert!(is_live(&stack, a) && is_live(&stack, b) && is_live(&stack, c));
+        assert!(!is_live(&stack, garbage));
+    }
+
+    #[test]
+    fn keep_static_and_pinned() {
+        let mut stack = stack();
+        let data = stack[0].heap.alloc(8);
+        let pinned = stack[0].heap.alloc(8);
+        let target = stack[0].heap.alloc(8);
+
+        stack[0].heap.set_static(data);
+        stack[0].heap.write(data, &target.to_ne_bytes());
+        stack[0].heap.pin(pinned);
+
+        assert_eq!(collect(&mut stack, 0), 0);
+        assert!(is_live(&stack, data) && is_live(&stack, pinned) && is_live(&stack, target));
+
+        stack[0].heap.unpin(pinned);
+        assert_eq!(collect(&mut stack, 0), 1);
+        assert!(!is_live(&stack, pinned));
+    }
+
+    #[test]
+    fn skip_no_scan() {
+        let mut stack = stack();
+        let numbers = stack[0].heap.alloc(8);
+        let target = stack[0].heap.alloc(8);
+
+        // a number that happens to look like an address
+        stack[0].heap.write(numbers, &target.to_ne_bytes());
+        stack[0].heap.set_no_scan(numbers);
+        stack[1].push(pointer(numbers));
+
+        assert_eq!(collect(&mut stack, 0), 1);
+        assert!(is_live(&stack, numbers));
+        assert!(!is_live(&stack, target));
+    }
+}
@@ -0,0 +1,759 @@
+use std::{collections::{BTreeMap, BTreeSet, VecDeque}, ffi::c_void, ptr::null_mut, sync::atomic::{AtomicBool, Ordering}};
+
+use half::f16;
+
+use crate::{_type::{Type, Types}, frame::Frame, value::{Value, Values}};
+
+// the first bytes of the heap are never handed out so that address 0 can be used as null
+pub const NULL_SIZE: usize = 8;
+
+// every allocation is aligned to this, which is enough for any scalar type
+pub const ALIGNMENT: usize = 8;
+
+// with memcheck on, every allocation is followed by this many unused bytes so overruns don't land in the next allocation
+pub const RED_ZONE: usize = 16;
+
+// with gc on, the heap is collected once this many bytes have been allocated since the last collection (or as many as are live, if that's more)
+pub const GC_THRESHOLD: usize = 1 << 16;
+
+// with memcheck on, freed memory isn't reused until this many bytes have been freed after it
+pub const QUARANTINE_SIZE: usize = 1 << 20;
+
+// when set, heaps are created with memcheck on
+static MEMCHECK: AtomicBool = AtomicBool::new(false);
+
+pub fn set_memcheck(memcheck: bool) {
+    MEMCHECK.store(memcheck, Ordering::Relaxed);
+}
+
+pub fn is_memcheck() -> bool {
+    return MEMCHECK.load(Ordering::Relaxed);
+}
+
+#[derive(Debug, Clone)]
+pub struct Heap {
+    pub bytes: Vec<u8>,
+
+    // start address -> requested size in bytes of every live allocation
+    pub allocs: BTreeMap<usize, usize>,
+
+    // free blocks below the top of the heap, indexed by address for coalescing and by size for reuse
+    free_blocks: BTreeMap<usize, usize>,
+    free_sizes: BTreeSet<(usize, usize)>,
+
+    pub stats: HeapStats,
+
+    // gc state, allocations that can't hold pointers and how much has been allocated since the last collection
+    no_scan: BTreeSet<usize>,
+    since_gc: usize,
+
+    // memcheck state, none of this is used unless memcheck is on
+    memcheck: bool, // whether every memory access is checked against the allocation it falls in, fixed when the heap is created
+    init: Vec<bool>, // whether each byte has been written since it was allocated
+    freed: BTreeMap<usize, usize>, // allocations that have been freed but are still in quarantine
+    quarantine: VecDeque<usize>,
+    quarantine_bytes: usize,
+    sites: BTreeMap<usize, String>, // the instruction that made each allocation
+    provenance: BTreeMap<usize, usize>, // the allocation each pointer stored in memory was made from
+    statics: BTreeSet<usize>, // allocations that live for the whole program, like the data section
+    pinned: Vec<usize>,       // pointers native code is holding on to while it runs
+    frozen: usize,            // callbacks running on top of native code, the heap can't move until they return
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct HeapStats {
+    pub allocs: usize,
+    pub frees: usize,
+    pub reused: usize,
+    pub live_bytes: usize,
+    pub peak_size: usize,
+    pub peak_live: usize,
+    pub collections: usize,
+    pub collected: usize,
+}
+
+impl Heap {
+    pub fn new() -> Heap {
+        return Heap::with_memcheck(is_memcheck());
+    }
+
+    pub fn with_memcheck(memcheck: bool) -> Heap {
+        // frames are created for every function call, so nothing is allocated until the heap is used
+        return Heap {
+            bytes: Vec::new(), allocs: BTreeMap::new(), free_blocks: BTreeMap::new(), free_sizes: BTreeSet::new(), stats: HeapStats::default(), no_scan: BTreeSet::new(), since_gc: 0,
+            memcheck, init: Vec::new(), freed: BTreeMap::new(), quarantine: VecDeque::new(), quarantine_bytes: 0, sites: BTreeMap::new(), provenance: BTreeMap::new(), statics: BTreeSet::new(), pinned: Vec::new(), frozen: 0,
+        };
+    }
+
+    pub fn is_memcheck(&self) -> bool {
+        return self.memcheck;
+    }
+
+    // allocations are made in whole blocks so that every allocation stays aligned
+    fn block_size(&self, size: usize) -> usize {
+        if self.memcheck {
+            return (size.max(1) + RED_ZONE).next_multiple_of(ALIGNMENT);
+        }
+
+        return size.max(1).next_multiple_of(ALIGNMENT);
+    }
+
+    // live allocations are never moved, so addresses stay valid until they are freed
+    pub fn alloc(&mut self, size: usize) -> usize {
+        if self.bytes.len() < NULL_SIZE {
+            self.grow(NULL_SIZE);
+        }
+
+        let block = self.block_size(size);
+
+        // take the smallest free block that fits, splitting off whatever is left over
+        let addr = match self.free_sizes.range((block, 0)..).next().copied() {
+            Some((free_size, addr)) => {
+                self.remove_free(addr, free_size);
+                if free_size > block {
+                    self.insert_free(addr + block, free_size - block);
+                }
+
+                self.bytes[addr..addr+block].fill(0);
+                self.stats.reused += 1;
+
+                addr
+            }
+            None => {
+                let addr = self.bytes.len();
+                self.grow(addr + block);
+
+                addr
+            }
+        };
+
+        self.allocs.insert(addr, size);
+
+        if self.memcheck {
+            self.init.resize(self.bytes.len(), false);
+            self.init[addr..addr+block].fill(false);
+        }
+
+        self.since_gc += size;
+
+        self.stats.allocs += 1;
+        self.stats.live_bytes += size;
+        self.stats.peak_size = self.stats.peak_size.max(self.bytes.len());
+        self.stats.peak_live = self.stats.peak_live.max(self.stats.live_bytes);
+
+        return addr;
+    }
+
+    // records which instruction made an allocation, for memcheck errors and the leak report
+    pub fn set_site(&mut self, addr: usize, site: String) {
+        self.sites.insert(addr, site);
+    }
+
+    // marks an allocation as living for the whole program, so it isn't reported as a leak
+    pub fn set_static(&mut self, addr: usize) {
+        self.statics.insert(addr);
+    }
+
+    pub fn static_allocs(&self) -> Vec<usize> {
+        return self.statics.iter().copied().collect();
+    }
+
+    // keeps whatever `addr` points into alive while native code might call back into the interpreter
+    pub fn pin(&mut self, addr: usize) {
+        self.pinned.push(addr);
+    }
+
+    pub fn unpin(&mut self, addr: usize) {
+        if let Some(i) = self.pinned.iter().rposition(|a| *a == addr) {
+            self.pinned.remove(i);
+        }
+    }
+
+    pub fn pinned(&self) -> &[usize] {
+        return &self.pinned;
+    }
+
+    // native code holds pointers into the heap while it calls back into the interpreter, so the heap is kept where it is until it returns
+    pub fn freeze(&mut self) {
+        self.frozen += 1;
+    }
+
+    pub fn thaw(&mut self) {
+        self.frozen -= 1;
+    }
+
+    fn grow(&mut self, len: usize) {
+        if self.frozen > 0 && len > self.bytes.capacity() {
+            panic!("cannot allocate more memory while being called from native code, the heap would have to move and native code may still be using it (allocate what the callback needs before calling the extern)");
+        }
+
+        self.bytes.resize(len, 0);
+    }
+
+    // marks an allocation as only holding numbers, so the collector doesn't look for pointers in it
+    pub fn set_no_scan(&mut self, addr: usize) {
+        self.no_scan.insert(addr);
+    }
+
+    pub fn is_no_scan(&self, addr: usize) -> bool {
+        return self.no_scan.contains(&addr);
+    }
+
+    pub fn gc_due(&self) -> bool {
+        return self.since_gc >= GC_THRESHOLD.max(self.stats.live_bytes);
+    }
+
+    pub fn finish_collection(&mut self, collected: usize) {
+        self.since_gc = 0;
+        self.stats.collections += 1;
+        self.stats.collected += collected;
+    }
+
+    fn site(&self, addr: usize) -> String {
+        return match self.sites.get(&addr) {
+            Some(site) => site.clone(),
+            None => "an unknown instruction".to_string(),
+        };
+    }
+
+    // frees the allocation starting at `addr` and returns its size
+    pub fn free(&mut self, addr: usize) -> usize {
+        if addr == 0 {
+            panic!("attempted to free a null pointer");
+        }
+
+        let size = match self.allocs.remove(&addr) {
+            Some(size) => size,
+            None => {
+                if self.freed.contains_key(&addr) {
+                    panic!("memcheck: double free of address {addr}, allocated by {}", self.site(addr));
+                }
+
+                match self.find_alloc(addr) {
+                    Some((start, _)) => panic!("attempted to free address {addr} which is in the middle of the allocation at {start}"),
+                    None => panic!("attempted to free address {addr} which is not the start of an allocation"),
+                }
+            }
+        };
+
+        self.no_scan.remove(&addr);
+        self.statics.remove(&addr);
+
+        self.stats.frees += 1;
+        self.stats.live_bytes -= size;
+
+        if !self.memcheck {
+            self.release(addr, self.block_size(size));
+            return size;
+        }
+
+        // freed memory sits in quarantine for a while so that uses after the free can be caught
+        self.freed.insert(addr, size);
+        self.quarantine.push_back(addr);
+        self.quarantine_bytes += self.block_size(size);
+
+        while self.quarantine_bytes > QUARANTINE_SIZE {
+            let old = self.quarantine.pop_front().unwrap();
+            let old_size = self.freed.remove(&old).unwrap();
+
+            self.quarantine_bytes -= self.block_size(old_size);
+            self.sites.remove(&old);
+            self.release(old, self.block_size(old_size));
+        }
+
+        return size;
+    }
+
+    // gives a block back to the allocator
+    fn release(&mut self, addr: usize, block: usize) {
+        let mut start = addr;
+        let mut end = addr + block;
+
+        // coalesce with the free blocks on either side
+        if let Some((&prev, &prev_size)) = self.free_blocks.range(..addr).next_back() {
+            if prev + prev_size == start {
+                self.remove_free(prev, prev_size);
+                start = prev;
+            }
+        }
+        if let Some(&next_size) = self.free_blocks.get(&end) {
+            self.remove_free(end, next_size);
+            end += next_size;
+        }
+
+        // pointers stored in the block are gone
+        let stale = self.provenance.range(addr..addr+block).map(|(slot, _)| *slot).collect::<Vec<_>>();
+        for slot in stale {
+            self.provenance.remove(&slot);
+        }
+
+        // blocks at the top of the heap are given back instead of being kept around
+        if end == self.bytes.len() {
+            self.bytes.truncate(start);
+            self.init.truncate(start);
+        } else {
+            self.insert_free(start, end - start);
+        }
+    }
+
+    fn insert_free(&mut self, addr: usize, size: usize) {
+        self.free_blocks.insert(addr, size);
+        self.free_sizes.insert((size, addr));
+    }
+
+    fn remove_free(&mut self, addr: usize, size: usize) {
+        self.free_blocks.remove(&addr);
+        self.free_sizes.remove(&(size, addr));
+    }
+
+    // finds the live allocation that starts at or before `addr`
+    pub fn find_alloc(&self, addr: usize) -> Option<(usize, usize)> {
+        return self.allocs.range(..=addr).next_back().map(|(start, size)| (*start, *size));
+    }
+
+    pub fn free_bytes(&self) -> usize {
+        return self.free_blocks.values().sum();
+    }
+
+    pub fn print_stats(&self) {
+        println!("heap stats:");
+        println!("  heap size:          {} bytes (peak {} bytes)", self.bytes.len(), self.stats.peak_size);
+        println!("  live allocations:   {} ({} bytes, peak {} bytes)", self.allocs.len(), self.stats.live_bytes, self.stats.peak_live);
+        println!("  free blocks:        {} ({} bytes)", self.free_blocks.len(), self.free_bytes());
+        println!("  total allocations:  {} ({} reused a free block)", self.stats.allocs, self.stats.reused);
+        println!("  total frees:        {}", self.stats.frees);
+        if self.stats.collections > 0 {
+            println!("  gc collections:     {} ({} allocations collected)", self.stats.collections, self.stats.collected);
+        }
+    }
+
+    // returns the size of the allocation starting at `addr`, if there is one
+    pub fn alloc_size(&self, addr: usize) -> Option<usize> {
+        return self.allocs.get(&addr).copied();
+    }
+
+    fn check(&self, addr: usize, size: usize, action: &str) {
+        if addr == 0 {
+            panic!("attempted to {action} a null pointer");
+        }
+
+        if self.memcheck {
+            self.memcheck(addr, size, action);
+        }
+
+        if addr + size > self.bytes.len() {
+            panic!("attempted to {action} out of bounds memory: address {addr} with size {size} (heap size {})", self.bytes.len());
+        }
+    }
+
+    // an access has to lie entirely inside a single live allocation
+    fn memcheck(&self, addr: usize, size: usize, action: &str) {
+        if let Some((start, alloc_size)) = self.find_alloc(addr) {
+            if addr < start + alloc_size {
+                if addr + size > start + alloc_size {
+                    panic!("memcheck: attempted to {action} {size} bytes at address {addr}, which overruns the {alloc_size} byte allocation at {start} by {} bytes (allocated by {})",
+                           addr + size - (start + alloc_size), self.site(start));
+                }
+                return;
+            }
+
+            if addr < start + self.block_size(alloc_size) {
+                panic!("memcheck: attempted to {action} address {addr}, which is {} bytes past the end of the {alloc_size} byte allocation at {start} (allocated by {})",
+                       addr - (start + alloc_size), self.site(start));
+            }
+        }
+
+        if let Some((&start, &freed_size)) = self.freed.range(..=addr).next_back() {
+            if addr < start + self.block_size(freed_size) {
+                panic!("memcheck: use after free, attempted to {action} address {addr} in the {freed_size} byte allocation at {start} which has been freed (allocated by {})",
+                       self.site(start));
+            }
+        }
+
+        panic!("memcheck: attempted to {action} address {addr}, which does not point into any allocation");
+    }
+
+    // a pointer can only be used to access the allocation it was made from, even if another allocation lies at the address
+    pub fn check_provenance(&self, ptr: &Values, addr: usize, size: usize, action: &str) {
+        let base = match ptr {
+            Values::POINTER(_, base) if self.memcheck && *base != 0 => *base,
+            _ => return,
+        };
+
+        if let Some(&alloc_size) = self.allocs.get(&base) {
+            if addr < base || addr + size > base + alloc_size {
+                panic!("memcheck: a pointer into the {alloc_size} byte allocation at {base} (allocated by {}) was used to {action} {size} bytes at address {addr}, outside of that allocation",
+                       self.site(base));
+            }
+        } else if self.freed.contains_key(&base) {
+            panic!("memcheck: use after free, a pointer into the allocation at {base} (allocated by {}) was used to {action} address {addr} after it was freed",
+                   self.site(base));
+        }
+    }
+
+    pub fn read(&self, addr: usize, size: usize) -> &[u8] {
+        self.check(addr, size, "read from");
+
+        if self.memcheck {
+            if let Some(offset) = self.init[addr..addr+size].iter().position(|init| !init) {
+                let (start, _) = self.find_alloc(addr).unwrap();
+                panic!("memcheck: read of uninitialized memory at address {} in the allocation at {start} (allocated by {})", addr + offset, self.site(start));
+            }
+        }
+
+        return &self.bytes[addr..addr+size];
+    }
+
+    // reads a C string, without the 0 at the end
+    pub fn read_c_str(&self, addr: usize) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        while self.read(addr + bytes.len(), 1)[0] != 0 {
+            bytes.push(self.read(addr + bytes.len(), 1)[0]);
+        }
+
+        return bytes;
+    }
+
+    // checks that `size` bytes at `addr` can be written, for when the bytes come from somewhere that can't be undone (like a file)
+    pub fn check_write(&self, addr: usize, size: usize) {
+        self.check(addr, size, "write to");
+    }
+
+    pub fn write(&mut self, addr: usize, bytes: &[u8]) {
+        self.check(addr, bytes.len(), "write to");
+
+        if self.memcheck {
+            self.init[addr..addr+bytes.len()].fill(true);
+        }
+
+        self.bytes[addr..addr+bytes.len()].copy_from_slice(bytes);
+    }
+
+    // reports every allocation that is still live, returns how many there were
+    pub fn print_leaks(&self) -> usize {
+        let leaks = self.allocs.iter().filter(|(addr, _)| !self.statics.contains(addr)).collect::<Vec<_>>();
+
+        if leaks.is_empty() {
+            eprintln!("memcheck: no leaks");
+            return 0;
+        }
+
+        let total: usize = leaks.iter().map(|(_, size)| **size).sum();
+        eprintln!("memcheck: {} allocations ({} bytes) were never freed:", leaks.len(), total);
+        for (addr, size) in &leaks {
+            eprintln!("  {} bytes at address {}, allocated by {}", size, addr, self.site(**addr));
+        }
+
+        return leaks.len();
+    }
+
+    // loads a value of type `typ` from `addr`
+    pub fn load(&self, addr: usize, typ: &Type) -> Values {
+        let bytes = self.read(addr, typ.get_size());
+
+        return match &typ.typ[0] {
+            Types::I8      => Values::SIGNED(i8::from_ne_bytes(bytes.try_into().unwrap()) as i64),
+            Types::I16     => Values::SIGNED(i16::from_ne_bytes(bytes.try_into().unwrap()) as i64),
+            Types::I32     => Values::SIGNED(i32::from_ne_bytes(bytes.try_into().unwrap()) as i64),
+            Types::I64     => Values::SIGNED(i64::from_ne_bytes(bytes.try_into().unwrap())),
+            Types::U8      => Values::UNSIGNED(u8::from_ne_bytes(bytes.try_into().unwrap()) as u64),
+            Types::U16     => Values::UNSIGNED(u16::from_ne_bytes(bytes.try_into().unwrap()) as u64),
+            Types::U32     => Values::UNSIGNED(u32::from_ne_bytes(bytes.try_into().unwrap()) as u64),
+            Types::U64     => Values::UNSIGNED(u64::from_ne_bytes(bytes.try_into().unwrap())),
+            Types::F16     => Values::DECIMAL(f16::from_ne_bytes(bytes.try_into().unwrap()).to_f64()),
+            Types::F32     => Values::DECIMAL(f32::from_ne_bytes(bytes.try_into().unwrap()) as f64),
+            Types::F64     => Values::DECIMAL(f64::from_ne_bytes(bytes.try_into().unwrap())),
+            // only memcheck keeps track of which allocation a pointer in memory was made from
+            Types::POINTER => Values::POINTER(usize::from_ne_bytes(bytes.try_into().unwrap()), *self.provenance.get(&addr).unwrap_or(&0)),
+            Types::TYPE    => Values::TYPE(Type { typ: vec![Types::from_u8(bytes[0])] }),
+            Types::HANDLE(d) => Values::HANDLE(usize::from_ne_bytes(bytes.try_into().unwrap()), d.clone()),
+            _ => panic!("cannot load a value of type `{}` from memory", typ),
+        };
+    }
+
+    // stores `val` at `addr` as a value of type `typ`
+    pub fn store(&mut self, addr: usize, typ: &Type, val: &Values) {
+        // go through a value of the right type so stores convert and fit the same way variables do
+        let mut value = Value { typ: typ.clone(), val: Frame::get_default_val(typ) };
+        match &typ.typ[0] {
+            Types::POINTER if matches!(val, Values::REFERENCE(_, _, _)) => panic!("cannot store a reference to a stack value in memory"),
+            Types::TYPE | Types::POINTER | Types::HANDLE(_) => value.val.set(val),
+            Types::STRUCT(_) | Types::NAME | Types::VOID => panic!("cannot store a value of type `{}` in memory", typ),
+            _ => value.set(val),
+        }
+
+        match value.val {
+            Values::SIGNED(n) => {
+                match typ.typ[0] {
+                    Types::I8  => self.write(addr, &(n as i8).to_ne_bytes()),
+                    Types::I16 => self.write(addr, &(n as i16).to_ne_bytes()),
+                    Types::I32 => self.write(addr, &(n as i32).to_ne_bytes()),
+                    _          => self.write(addr, &n.to_ne_bytes()),
+                }
+            }
+            Values::UNSIGNED(n) => {
+                match typ.typ[0] {
+                    Types::U8  => self.write(addr, &(n as u8).to_ne_bytes()),
+                    Types::U16 => self.write(addr, &(n as u16).to_ne_bytes()),
+                    Types::U32 => self.write(addr, &(n as u32).to_ne_bytes()),
+                    _          => self.write(addr, &n.to_ne_bytes()),
+                }
+            }
+            Values::DECIMAL(n) => {
+                match typ.typ[0] {
+                    Types::F16 => self.write(addr, &f16::from_f64(n).to_ne_bytes()),
+                    Types::F32 => self.write(addr, &(n as f32).to_ne_bytes()),
+                    _          => self.write(addr, &n.to_ne_bytes()),
+                }
+            }
+            Values::POINTER(p, base) => {
+                self.write(addr, &p.to_ne_bytes());
+                if self.memcheck {
+                    self.provenance.insert(addr, base);
+                }
+            }
+            Values::TYPE(t) => {
+                if t.typ.len() != 1 || matches!(t.typ[0], Types::STRUCT(_)) {
+                    panic!("cannot store type `{}` in memory, only single byte types can be stored", t);
+                }
+                self.write(addr, &[t.typ[0].to_u8()]);
+            }
+            Values::HANDLE(h, _) => self.write(addr, &h.to_ne_bytes()),
+            _ => panic!("cannot store a value of type `{}` in memory", typ),
+        }
+    }
+
+    // gets a native pointer to `addr`, only valid until the heap next grows
+    pub fn as_ptr(&mut self, addr: usize) -> *mut c_void {
+        if addr == 0 {
+            return null_mut();
+        }
+
+        if self.memcheck {
+            // native code can write anywhere in the allocation, so all of it counts as initialized from here on
+            // the end of an allocation (all there is to an empty one) can be passed too, like in C
+            match self.find_alloc(addr) {
+                Some((start, size)) if addr <= start + size => self.init[start..start+size].fill(true),
+                _ => self.memcheck(addr, 0, "pass native code"),
+            }
+        }
+
+        if addr > self.bytes.len() {
+            panic!("attempted to pass out of bounds address {addr} to native code (heap size {})", self.bytes.len());
+        }
+
+        return unsafe { self.bytes.as_mut_ptr().add(addr) as *mut c_void };
+    }
+
+    // turns a native pointer back into a heap address, if it points into the heap
+    pub fn addr_of(&self, ptr: *const c_void) -> Option<usize> {
+        if ptr.is_null() {
+            return Some(0);
+        }
+
+        let start = self.bytes.as_ptr() as usize;
+        let ptr = ptr as usize;
+        if ptr < start || ptr > start + self.bytes.len() {
+            return None;
+        }
+
+        return Some(ptr - start);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aligned_blocks() {
+        let mut heap = Heap::with_memcheck(false);
+        let a = heap.alloc(3);
+        let b = heap.alloc(0);
+        let c = heap.alloc(9);
+
+        assert_eq!(a, NULL_SIZE);
+        assert_eq!(b, a + 8);
+        assert_eq!(c, b + 8);
+        assert_eq!(heap.bytes.len(), c + 16);
+
+        // memcheck leaves a red zone after every allocation
+        let mut heap = Heap::with_memcheck(true);
+        let a = heap.alloc(8);
+        let b = heap.alloc(8);
+        assert_eq!(b, a + 8 + RED_ZONE);
+    }
+
+    #[test]
+    fn reuse_smallest_free_block() {
+        let mut heap = Heap::with_memcheck(false);
+        let big = heap.alloc(32);
+        heap.alloc(8);
+        let small = heap.alloc(16);
+        heap.alloc(8);
+
+        heap.free(big);
+        heap.free(small);
+
+        assert_eq!(heap.alloc(16), small);
+        assert_eq!(heap.alloc(8), big);
+
+        // the rest of the block that was split stays free
+        assert_eq!(heap.free_bytes(), 24);
+        assert_eq!(heap.alloc(24), big + 8);
+        assert_eq!(heap.free_bytes(), 0);
+    }
+
+    #[test]
+    fn coalesce_with_neighbours() {
+        let mut heap = Heap::with_memcheck(false);
+        let a = heap.alloc(8);
+        let b = heap.alloc(8);
+        let c = heap.alloc(8);
+        let d = heap.alloc(8);
+        let e = heap.alloc(8);
+        heap.alloc(8);
+
+        // with the block before it
+        heap.free(a);
+        heap.free(b);
+        assert_eq!(heap.free_blocks.len(), 1);
+        assert_eq!(heap.free_blocks.get(&a), Some(&16));
+
+        // with the block after it
+        heap.free(d);
+        heap.free(c);
+        assert_eq!(heap.free_blocks.len(), 1);
+        assert_eq!(heap.free_blocks.get(&a), Some(&32));
+
+        // with both
+        let b = heap.alloc(8);
+        assert_eq!(b, a);
+        heap.free(e);
+        heap.free(b);
+        assert_eq!(heap.free_blocks.len(), 1);
+        assert_eq!(heap.free_blocks.get(&a), Some(&40));
+        assert_eq!(heap.alloc(40), a);
+    }
+
+    #[test]
+    fn truncate_top_of_heap() {
+        let mut heap = Heap::with_memcheck(false);
+        let a = heap.alloc(8);
+        let b = heap.alloc(16);
+        let c = heap.alloc(8);
+
+        heap.free(c);
+        assert_eq!(heap.bytes.len(), b + 16);
+        assert!(heap.free_blocks.is_empty());
+
+        // a free block that ends up at the top goes too
+        heap.free(a);
+        heap.free(b);
+        assert_eq!(heap.bytes.len(), NULL_SIZE);
+        assert!(heap.free_blocks.is_empty());
+    }
+
+    #[test]
+    fn addresses_stay_put() {
+        let mut heap = Heap::with_memcheck(false);
+        let a = heap.alloc(8);
+        heap.write(a, &[1, 2, 3, 4, 5, 6, 7, 8]);
+
+        let b = heap.alloc(64);
+        heap.free(b);
+        let c = heap.alloc(8);
+        heap.alloc(1024);
+
+        assert_eq!(c, b);
+        assert_eq!(heap.read(a, 8), &[1, 2, 3, 4, 5, 6, 7, 8]);
+
+        // reused blocks are zeroed
+        heap.write(c, &[9; 8]);
+        heap.free(c);
+        let e = heap.alloc(8);
+        assert_eq!(e, c);
+        assert_eq!(heap.read(e, 8), &[0; 8]);
+    }
+
+    #[test]
+    fn stats() {
+        let mut heap = Heap::with_memcheck(false);
+        let a = heap.alloc(8);
+        let b = heap.alloc(24);
+        heap.alloc(4);
+        heap.free(a);
+        heap.free(b);
+        heap.alloc(16);
+
+        assert_eq!(heap.stats.allocs, 4);
+        assert_eq!(heap.stats.frees, 2);
+        assert_eq!(heap.stats.reused, 1);
+        assert_eq!(heap.stats.live_bytes, 20);
+        assert_eq!(heap.stats.peak_live, 36);
+        assert_eq!(heap.stats.peak_size, NULL_SIZE + 40);
+    }
+
+    #[test]
+    fn pass_end_of_allocation() {
+        let mut heap = Heap::with_memcheck(true);
+        let addr = heap.alloc(8);
+        let empty = heap.alloc(0);
+
+        let end = heap.as_ptr(addr + 8);
+        assert_eq!(heap.addr_of(end), Some(addr + 8));
+
+        let ptr = heap.as_ptr(empty);
+        assert_eq!(heap.addr_of(ptr), Some(empty));
+    }
+
+    #[test]
+    #[should_panic(expected = "past the end")]
+    fn pass_past_end_of_allocation() {
+        let mut heap = Heap::with_memcheck(true);
+        let addr = heap.alloc(8);
+
+        heap.as_ptr(addr + 9);
+    }
+
+    #[test]
+    fn access_through_own_allocation() {
+        let mut heap = Heap::with_memcheck(true);
+        let addr = heap.alloc(8);
+        heap.alloc(8);
+
+        heap.write(addr + 4, &[1, 2, 3, 4]);
+        heap.check_provenance(&Values::POINTER(addr + 4, addr), addr + 4, 4, "read from");
+        assert_eq!(heap.read(addr + 4, 4), &[1, 2, 3, 4]);
+    }
+
+    #[test]
+    #[should_panic(expected = "a pointer into the 8 byte allocation")]
+    fn access_through_neighbouring_allocation() {
+        let mut heap = Heap::with_memcheck(true);
+        let a = heap.alloc(8);
+        let b = heap.alloc(8);
+
+        // the address is valid, but the pointer was made from `a`
+        heap.check_provenance(&Values::POINTER(b, a), b, 1, "read from");
+    }
+
+    #[test]
+    #[should_panic(expected = "use after free")]
+    fn access_after_free() {
+        let mut heap = Heap::with_memcheck(true);
+        let addr = heap.alloc(8);
+        heap.free(addr);
+
+        heap.check_provenance(&Values::POINTER(addr, addr), addr, 1, "read from");
+    }
+
+    #[test]
+    #[should_panic(expected = "uninitialized memory")]
+    fn read_uninitialized() {
+        let mut heap = Heap::with_memcheck(true);
+        let addr = heap.alloc(8);
+
+        heap.read(addr, 8);
+    }
+}
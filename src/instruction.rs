@@ -199,6 +199,63 @@ pub enum Opcode { // very large enum
     CMP_V_I_V(String, Value, String, String)  = 0x91,
     CMP_I_V_V(Value, String, String, String)  = 0x92,
     CMP_V_V_V(String, String, String, String) = 0x93,
+
+    // checked arithmetic
+    CHK_I_I_I(Value, Value, Value, String)    = 0x94,
+    CHK_V_I_I(String, Value, Value, String)   = 0x95,
+    CHK_I_V_I(Value, String, Value, String)   = 0x96,
+    CHK_V_V_I(String, String, Value, String)  = 0x97,
+    CHK_I_I_V(Value, Value, String, String)   = 0x98,
+    CHK_V_I_V(String, Value, String, String)  = 0x99,
+    CHK_I_V_V(Value, String, String, String)  = 0x9A,
+    CHK_V_V_V(String, String, String, String) = 0x9B,
+
+    // wrapping arithmetic
+    WRAP_I_I_I(Value, Value, Value, String)   = 0x9C,
+    WRAP_V_I_I(String, Value, Value, String)  = 0x9D,
+    WRAP_I_V_I(Value, String, Value, String)  = 0x9E,
+    WRAP_V_V_I(String, String, Value, String) = 0x9F,
+    WRAP_I_I_V(Value, Value, String, String)  = 0xA0,
+    WRAP_V_I_V(String, Value, String, String) = 0xA1,
+    WRAP_I_V_V(Value, String, String, String) = 0xA2,
+    WRAP_V_V_V(String, String, String, String)= 0xA3,
+
+    // saturating arithmetic
+    SAT_I_I_I(Value, Value, Value, String)    = 0xA4,
+    SAT_V_I_I(String, Value, Value, String)   = 0xA5,
+    SAT_I_V_I(Value, String, Value, String)   = 0xA6,
+    SAT_V_V_I(String, String, Value, String)  = 0xA7,
+    SAT_I_I_V(Value, Value, String, String)   = 0xA8,
+    SAT_V_I_V(String, Value, String, String)  = 0xA9,
+    SAT_I_V_V(Value, String, String, String)  = 0xAA,
+    SAT_V_V_V(String, String, String, String) = 0xAB,
+
+    // arithmetic with overflow flag
+    OVF_I_I_I(Value, Value, Value, String, String)= 0xAC,
+    OVF_V_I_I(String, Value, Value, String, String)= 0xAD,
+    OVF_I_V_I(Value, String, Value, String, String)= 0xAE,
+    OVF_V_V_I(String, String, Value, String, String)= 0xAF,
+    OVF_I_I_V(Value, Value, String, String, String)= 0xB0,
+    OVF_V_I_V(String, Value, String, String, String)= 0xB1,
+    OVF_I_V_V(Value, String, String, String, String)= 0xB2,
+    OVF_V_V_V(String, String, String, String, String)= 0xB3,
+
+    // cast instruction
+    CAST_I_I_T(Value, Value, Type, String)    = 0xB4,
+    CAST_V_I_T(String, Value, Type, String)   = 0xB5,
+    CAST_I_V_T(Value, String, Type, String)   = 0xB6,
+    CAST_V_V_T(String, String, Type, String)  = 0xB7,
+    CAST_I_I_V(Value, Value, String, String)  = 0xB8,
+    CAST_V_I_V(String, Value, String, String) = 0xB9,
+    CAST_I_V_V(Value, String, String, String) = 0xBA,
+    CAST_V_V_V(String, String, String, String) = 0xBB,
+
+    // runtime library loading
+    LOADLIB_IMM(Value, String)                = 0xBC,
+    LOADLIB_VAR(String, String)               = 0xBD,
+
+    DLSYM_V_I(String, Value, String)          = 0xBE,
+    DLSYM_V_V(String, String, String)         = 0xBF,
 }
 
 impl Opcode {
@@ -352,6 +409,50 @@ impl Opcode {
             Opcode::CMP_V_I_V(_, _, _, _)   => 0x91,
             Opcode::CMP_I_V_V(_, _, _, _)   => 0x92,
             Opcode::CMP_V_V_V(_, _, _, _)   => 0x93,
+            Opcode::CHK_I_I_I(_, _, _, _)   => 0x94,
+            Opcode::CHK_V_I_I(_, _, _, _)   => 0x95,
+            Opcode::CHK_I_V_I(_, _, _, _)   => 0x96,
+            Opcode::CHK_V_V_I(_, _, _, _)   => 0x97,
+            Opcode::CHK_I_I_V(_, _, _, _)   => 0x98,
+            Opcode::CHK_V_I_V(_, _, _, _)   => 0x99,
+            Opcode::CHK_I_V_V(_, _, _, _)   => 0x9A,
+            Opcode::CHK_V_V_V(_, _, _, _)   => 0x9B,
+            Opcode::WRAP_I_I_I(_, _, _, _)  => 0x9C,
+            Opcode::WRAP_V_I_I(_, _, _, _)  => 0x9D,
+            Opcode::WRAP_I_V_I(_, _, _, _)  => 0x9E,
+            Opcode::WRAP_V_V_I(_, _, _, _)  => 0x9F,
+            Opcode::WRAP_I_I_V(_, _, _, _)  => 0xA0,
+            Opcode::WRAP_V_I_V(_, _, _, _)  => 0xA1,
+            Opcode::WRAP_I_V_V(_, _, _, _)  => 0xA2,
+            Opcode::WRAP_V_V_V(_, _, _, _)  => 0xA3,
+            Opcode::SAT_I_I_I(_, _, _, _)   => 0xA4,
+            Opcode::SAT_V_I_I(_, _, _, _)   => 0xA5,
+            Opcode::SAT_I_V_I(_, _, _, _)   => 0xA6,
+            Opcode::SAT_V_V_I(_, _, _, _)   => 0xA7,
+            Opcode::SAT_I_I_V(_, _, _, _)   => 0xA8,
+            Opcode::SAT_V_I_V(_, _, _, _)   => 0xA9,
+            Opcode::SAT_I_V_V(_, _, _, _)   => 0xAA,
+            Opcode::SAT_V_V_V(_, _, _, _)   => 0xAB,
+            Opcode::OVF_I_I_I(_, _, _, _, _)=> 0xAC,
+            Opcode::OVF_V_I_I(_, _, _, _, _)=> 0xAD,
+            Opcode::OVF_I_V_I(_, _, _, _, _)=> 0xAE,
+            Opcode::OVF_V_V_I(_, _, _, _, _)=> 0xAF,
+            Opcode::OVF_I_I_V(_, _, _, _, _)=> 0xB0,
+            Opcode::OVF_V_I_V(_, _, _, _, _)=> 0xB1,
+            Opcode::OVF_I_V_V(_, _, _, _, _)=> 0xB2,
+            Opcode::OVF_V_V_V(_, _, _, _, _)=> 0xB3,
+            Opcode::CAST_I_I_T(_, _, _, _)  => 0xB4,
+            Opcode::CAST_V_I_T(_, _, _, _)  => 0xB5,
+            Opcode::CAST_I_V_T(_, _, _, _)  => 0xB6,
+            Opcode::CAST_V_V_T(_, _, _, _)  => 0xB7,
+            Opcode::CAST_I_I_V(_, _, _, _)  => 0xB8,
+            Opcode::CAST_V_I_V(_, _, _, _)  => 0xB9,
+            Opcode::CAST_I_V_V(_, _, _, _)  => 0xBA,
+            Opcode::CAST_V_V_V(_, _, _, _)  => 0xBB,
+            Opcode::LOADLIB_IMM(_, _)       => 0xBC,
+            Opcode::LOADLIB_VAR(_, _)       => 0xBD,
+            Opcode::DLSYM_V_I(_, _, _)      => 0xBE,
+            Opcode::DLSYM_V_V(_, _, _)      => 0xBF,
         }
     }
 }
@@ -514,6 +615,50 @@ impl Instruction {
             Opcode::CMP_V_I_V(a, b, c, d) => "CMP_V_I_V(".to_string() + &format!("{a}, {b}, {c}, {d})"),
             Opcode::CMP_I_V_V(a, b, c, d) => "CMP_I_V_V(".to_string() + &to_cond(a) + ", " + &format!("{b}, {c}, {d})"),
             Opcode::CMP_V_V_V(a, b, c, d) => "CMP_V_V_V(".to_string() + &format!("{a}, {b}, {c}, {d})"),
+            Opcode::CHK_I_I_I(a, b, c, d) => "CHK_I_I_I(".to_string() + &to_op(a) + ", " + &format!("{b}, {c}, {d})"),
+            Opcode::CHK_V_I_I(a, b, c, d) => "CHK_V_I_I(".to_string() + &format!("{a}, {b}, {c}, {d})"),
+            Opcode::CHK_I_V_I(a, b, c, d) => "CHK_I_V_I(".to_string() + &to_op(a) + ", " + &format!("{b}, {c}, {d})"),
+            Opcode::CHK_V_V_I(a, b, c, d) => "CHK_V_V_I(".to_string() + &format!("{a}, {b}, {c}, {d})"),
+            Opcode::CHK_I_I_V(a, b, c, d) => "CHK_I_I_V(".to_string() + &to_op(a) + ", " + &format!("{b}, {c}, {d})"),
+            Opcode::CHK_V_I_V(a, b, c, d) => "CHK_V_I_V(".to_string() + &format!("{a}, {b}, {c}, {d})"),
+            Opcode::CHK_I_V_V(a, b, c, d) => "CHK_I_V_V(".to_string() + &to_op(a) + ", " + &format!("{b}, {c}, {d})"),
+            Opcode::CHK_V_V_V(a, b, c, d) => "CHK_V_V_V(".to_string() + &format!("{a}, {b}, {c}, {d})"),
+            Opcode::WRAP_I_I_I(a, b, c, d) => "WRAP_I_I_I(".to_string() + &to_op(a) + ", " + &format!("{b}, {c}, {d})"),
+            Opcode::WRAP_V_I_I(a, b, c, d) => "WRAP_V_I_I(".to_string() + &format!("{a}, {b}, {c}, {d})"),
+            Opcode::WRAP_I_V_I(a, b, c, d) => "WRAP_I_V_I(".to_string() + &to_op(a) + ", " + &format!("{b}, {c}, {d})"),
+            Opcode::WRAP_V_V_I(a, b, c, d) => "WRAP_V_V_I(".to_string() + &format!("{a}, {b}, {c}, {d})"),
+            Opcode::WRAP_I_I_V(a, b, c, d) => "WRAP_I_I_V(".to_string() + &to_op(a) + ", " + &format!("{b}, {c}, {d})"),
+            Opcode::WRAP_V_I_V(a, b, c, d) => "WRAP_V_I_V(".to_string() + &format!("{a}, {b}, {c}, {d})"),
+            Opcode::WRAP_I_V_V(a, b, c, d) => "WRAP_I_V_V(".to_string() + &to_op(a) + ", " + &format!("{b}, {c}, {d})"),
+            Opcode::WRAP_V_V_V(a, b, c, d) => "WRAP_V_V_V(".to_string() + &format!("{a}, {b}, {c}, {d})"),
+            Opcode::SAT_I_I_I(a, b, c, d) => "SAT_I_I_I(".to_string() + &to_op(a) + ", " + &format!("{b}, {c}, {d})"),
+            Opcode::SAT_V_I_I(a, b, c, d) => "SAT_V_I_I(".to_string() + &format!("{a}, {b}, {c}, {d})"),
+            Opcode::SAT_I_V_I(a, b, c, d) => "SAT_I_V_I(".to_string() + &to_op(a) + ", " + &format!("{b}, {c}, {d})"),
+            Opcode::SAT_V_V_I(a, b, c, d) => "SAT_V_V_I(".to_string() + &format!("{a}, {b}, {c}, {d})"),
+            Opcode::SAT_I_I_V(a, b, c, d) => "SAT_I_I_V(".to_string() + &to_op(a) + ", " + &format!("{b}, {c}, {d})"),
+            Opcode::SAT_V_I_V(a, b, c, d) => "SAT_V_I_V(".to_string() + &format!("{a}, {b}, {c}, {d})"),
+            Opcode::SAT_I_V_V(a, b, c, d) => "SAT_I_V_V(".to_string() + &to_op(a) + ", " + &format!("{b}, {c}, {d})"),
+            Opcode::SAT_V_V_V(a, b, c, d) => "SAT_V_V_V(".to_string() + &format!("{a}, {b}, {c}, {d})"),
+            Opcode::OVF_I_I_I(a, b, c, d, e) => "OVF_I_I_I(".to_string() + &to_op(a) + ", " + &format!("{b}, {c}, {d}, {e})"),
+            Opcode::OVF_V_I_I(a, b, c, d, e) => "OVF_V_I_I(".to_string() + &format!("{a}, {b}, {c}, {d}, {e})"),
+            Opcode::OVF_I_V_I(a, b, c, d, e) => "OVF_I_V_I(".to_string() + &to_op(a) + ", " + &format!("{b}, {c}, {d}, {e})"),
+            Opcode::OVF_V_V_I(a, b, c, d, e) => "OVF_V_V_I(".to_string() + &format!("{a}, {b}, {c}, {d}, {e})"),
+            Opcode::OVF_I_I_V(a, b, c, d, e) => "OVF_I_I_V(".to_string() + &to_op(a) + ", " + &format!("{b}, {c}, {d}, {e})"),
+            Opcode::OVF_V_I_V(a, b, c, d, e) => "OVF_V_I_V(".to_string() + &format!("{a}, {b}, {c}, {d}, {e})"),
+            Opcode::OVF_I_V_V(a, b, c, d, e) => "OVF_I_V_V(".to_string() + &to_op(a) + ", " + &format!("{b}, {c}, {d}, {e})"),
+            Opcode::OVF_V_V_V(a, b, c, d, e) => "OVF_V_V_V(".to_string() + &format!("{a}, {b}, {c}, {d}, {e})"),
+            Opcode::CAST_I_I_T(a, b, c, d) => "CAST_I_I_T".to_string() + &format!("({a}, {b}, {c}, {d})"),
+            Opcode::CAST_V_I_T(a, b, c, d) => "CAST_V_I_T".to_string() + &format!("({a}, {b}, {c}, {d})"),
+            Opcode::CAST_I_V_T(a, b, c, d) => "CAST_I_V_T".to_string() + &format!("({a}, {b}, {c}, {d})"),
+            Opcode::CAST_V_V_T(a, b, c, d) => "CAST_V_V_T".to_string() + &format!("({a}, {b}, {c}, {d})"),
+            Opcode::CAST_I_I_V(a, b, c, d) => "CAST_I_I_V".to_string() + &format!("({a}, {b}, {c}, {d})"),
+            Opcode::CAST_V_I_V(a, b, c, d) => "CAST_V_I_V".to_string() + &format!("({a}, {b}, {c}, {d})"),
+            Opcode::CAST_I_V_V(a, b, c, d) => "CAST_I_V_V".to_string() + &format!("({a}, {b}, {c}, {d})"),
+            Opcode::CAST_V_V_V(a, b, c, d) => "CAST_V_V_V".to_string() + &format!("({a}, {b}, {c}, {d})"),
+            Opcode::LOADLIB_IMM(a, b) => "LOADLIB_IMM".to_string() + &format!("({a}, {b})"),
+            Opcode::LOADLIB_VAR(a, b) => "LOADLIB_VAR".to_string() + &format!("({a}, {b})"),
+            Opcode::DLSYM_V_I(a, b, c) => "DLSYM_V_I".to_string() + &format!("({a}, {b}, {c})"),
+            Opcode::DLSYM_V_V(a, b, c) => "DLSYM_V_V".to_string() + &format!("({a}, {b}, {c})"),
         }
     }
 }
@@ -566,4 +711,22 @@ fn to_cond(val: &Value) -> String {
         }
         _ => panic!("invalid condition `{}` passed to `to_cond`", val)
     }
-}
\ No newline at end of file
+}
+fn to_op(val: &Value) -> String {
+    let n = match val.val {
+        Values::SIGNED(n) => n as u64,
+        Values::UNSIGNED(n) => n,
+        Values::DECIMAL(n) => n as u64,
+        Values::POINTER(n, _) => n as u64,
+        _ => panic!("invalid operation `{}` passed to `to_op`", val)
+    };
+
+    match n {
+        0x00 => "ADD".to_string(),
+        0x01 => "SUB".to_string(),
+        0x02 => "MUL".to_string(),
+        0x03 => "DIV".to_string(),
+        0x04 => "LSH".to_string(),
+        _ => panic!("invalid operation `{n}` passed to `to_op`")
+    }
+}
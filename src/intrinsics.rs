@@ -0,0 +1,362 @@
+use std::{cell::Cell, io::{self, BufRead, Write}};
+
+use indexmap::IndexMap;
+
+use crate::{_struct::Struct, _type::{Type, Types}, ffi::{last_errno, push_struct}, frame::Frame, gc, scope::Scope, std_fs, std_net, std_os, std_time, value::{Value, Values}};
+
+// arguments are popped off the caller's frame and the return value (if any) is pushed onto it
+pub type Intrinsic = fn(&mut [Frame], usize, usize, &Scope, &Scope);
+
+// functions built into the runtime, they live in the `std` namespace and are called with `CALL` like any other function
+pub fn get_intrinsic(name: &str) -> Option<Intrinsic> {
+    return match name {
+        "std.errno"        => Some(errno),
+
+        "std.print"        => Some(print),
+        "std.println"      => Some(println),
+        "std.eprint"       => Some(eprint),
+        "std.eprintln"     => Some(eprintln),
+        "std.read_line"    => Some(read_line),
+
+        "std.print_num"    => Some(print_num),
+        "std.ntos"         => Some(ntos),
+        "std.format_float" => Some(format_float),
+
+        "std.fs.open"       => Some(std_fs::open),
+        "std.fs.read"       => Some(std_fs::read),
+        "std.fs.write"      => Some(std_fs::write),
+        "std.fs.seek"       => Some(std_fs::seek),
+        "std.fs.close"      => Some(std_fs::close),
+        "std.fs.read_file"  => Some(std_fs::read_file),
+        "std.fs.write_file" => Some(std_fs::write_file),
+        "std.fs.metadata"   => Some(std_fs::metadata),
+        "std.fs.open_dir"   => Some(std_fs::open_dir),
+        "std.fs.next_entry" => Some(std_fs::next_entry),
+        "std.fs.create_dir" => Some(std_fs::create_dir),
+        "std.fs.remove"     => Some(std_fs::remove),
+        "std.fs.rename"     => Some(std_fs::rename),
+        "std.fs.error"      => Some(std_fs::error),
+
+        "std.net.listen"          => Some(std_net::listen),
+        "std.net.accept"          => Some(std_net::accept),
+        "std.net.connect"         => Some(std_net::connect),
+        "std.net.connect_timeout" => Some(std_net::connect_timeout),
+        "std.net.read"            => Some(std_net::read),
+        "std.net.write"           => Some(std_net::write),
+        "std.net.udp_bind"        => Some(std_net::udp_bind),
+        "std.net.send_to"         => Some(std_net::send_to),
+        "std.net.recv_from"       => Some(std_net::recv_from),
+        "std.net.set_timeout"     => Some(std_net::set_timeout),
+        "std.net.local_addr"      => Some(std_net::local_addr),
+        "std.net.peer_addr"       => Some(std_net::peer_addr),
+        "std.net.resolve"         => Some(std_net::resolve),
+        "std.net.close"           => Some(std_net::close),
+        "std.net.error"           => Some(std_net::error),
+
+        "std.os.arg_count"   => Some(std_os::arg_count),
+        "std.os.arg"         => Some(std_os::arg),
+        "std.os.env"         => Some(std_os::env),
+        "std.os.set_env"     => Some(std_os::set_env),
+        "std.os.unset_env"   => Some(std_os::unset_env),
+        "std.os.exit"        => Some(std_os::exit),
+        "std.os.spawn"       => Some(std_os::spawn),
+        "std.os.write_stdin" => Some(std_os::write_stdin),
+        "std.os.close_stdin" => Some(std_os::close_stdin),
+        "std.os.read_stdout" => Some(std_os::read_stdout),
+        "std.os.read_stderr" => Some(std_os::read_stderr),
+        "std.os.wait"        => Some(std_os::wait),
+        "std.os.kill"        => Some(std_os::kill),
+        "std.os.close"       => Some(std_os::close),
+        "std.os.cwd"         => Some(std_os::cwd),
+        "std.os.set_cwd"     => Some(std_os::set_cwd),
+        "std.os.error"       => Some(std_os::error),
+
+        "std.time.monotonic"  => Some(std_time::monotonic),
+        "std.time.unix"       => Some(std_time::unix),
+        "std.time.unix_nanos" => Some(std_time::unix_nanos),
+        "std.time.utc"        => Some(std_time::utc),
+        "std.time.sleep"      => Some(std_time::sleep),
+        _ => None,
+    };
+}
+
+// structs the `std` functions take and give back, added to the program's global scope
+pub fn add_std_structs(scope: &mut Scope) {
+    let u8_ptr = Type { typ: vec![Types::POINTER, Types::U8] };
+    let u64 = Type { typ: vec![Types::U64] };
+
+    add_struct(scope, "std.string", &[("text", u8_ptr.clone()), ("length", u64.clone())]);
+    add_struct(scope, "std.fs.metadata", &[("size", u64.clone()), ("modified", Type { typ: vec![Types::I64] }), ("is_dir", Type { typ: vec![Types::U8] }), ("error", Type { typ: vec![Types::I32] })]);
+    add_struct(scope, "std.net.packet", &[("length", Type { typ: vec![Types::I64] }), ("from", u8_ptr), ("from_length", u64)]);
+
+    let i32 = Type { typ: vec![Types::I32] };
+    add_struct(scope, "std.os.status", &[("code", i32.clone()), ("signal", i32.clone()), ("error", i32.clone())]);
+
+    let u8 = Type { typ: vec![Types::U8] };
+    add_struct(scope, "std.time.datetime", &[
+        ("year", i32), ("month", u8.clone()), ("day", u8.clone()),
+        ("hour", u8.clone()), ("minute", u8.clone()), ("second", u8.clone()),
+        ("weekday", u8), ("yearday", Type { typ: vec![Types::U16] }),
+    ]);
+}
+
+thread_local! {
+    // handles given out by the `std` functions are numbered instead of being native addresses
+    static NEXT_HANDLE: Cell<usize> = const { Cell::new(1) };
+}
+
+pub fn new_handle() -> usize {
+    let handle = NEXT_HANDLE.get();
+    NEXT_HANDLE.set(handle + 1);
+
+    return handle;
+}
+
+fn add_struct(scope: &mut Scope, name: &str, vars: &[(&str, Type)]) {
+    let mut strct = Struct { name: name.to_string(), size: 0, align: 1, packed: false, min_align: 1, var_names: Vec::new(), var_types: Vec::new(), var_offsets: IndexMap::new(), byte_offsets: IndexMap::new() };
+
+    for (i, (name, typ)) in vars.iter().enumerate() {
+        strct.var_names.push(name.to_string());
+        strct.var_types.push(typ.clone());
+        strct.var_offsets.insert(name.to_string(), i);
+    }
+
+    let fields = strct.var_types.iter().map(|typ| (typ.get_size(), typ.get_align())).collect::<Vec<_>>();
+    strct.layout(&fields);
+
+    scope.structs.insert(strct.name.clone(), strct);
+}
+
+pub fn pop_usize(frame: &mut Frame, what: &str) -> usize {
+    let val = frame.pop();
+    return match val.val {
+        Values::SIGNED(n) if n >= 0 => n as usize,
+        Values::UNSIGNED(n) => n as usize,
+        _ => panic!("expected a {what}, got `{}`", val.val),
+    };
+}
+
+// pops a text pointer and its length, pushed in that order
+pub fn pop_text(stack: &mut [Frame], cur_frame: usize, global_frame: usize) -> Vec<u8> {
+    let len = pop_usize(&mut stack[cur_frame], "length");
+    let text = stack[cur_frame].pop();
+
+    return match text.val {
+        Values::POINTER(_, _) if len == 0 => Vec::new(),
+        Values::POINTER(addr, _) => stack[global_frame].heap.read(addr, len).to_vec(),
+        _ => panic!("expected a pointer to text, got `{}`", text.val),
+    };
+}
+
+// copies `text` into a new heap allocation, with a 0 after it so it can be used as a C string
+pub fn alloc_string(text: &[u8], site: &str, stack: &mut [Frame], global_frame: usize) -> usize {
+    gc::maybe_collect(stack, global_frame);
+
+    let heap = &mut stack[global_frame].heap;
+    let addr = heap.alloc(text.len() + 1);
+    heap.write(addr, text);
+    heap.write(addr + text.len(), &[0]);
+
+    if gc::is_gc() {
+        heap.set_no_scan(addr);
+    }
+    if heap.is_memcheck() {
+        heap.set_site(addr, format!("memory returned by `{site}`"));
+    }
+
+    return addr;
+}
+
+// pushes a `std.string` for a copy of `text`
+pub fn push_string(text: &[u8], site: &str, stack: &mut [Frame], cur_frame: usize, global_frame: usize, scope: &Scope, global_scope: &Scope) {
+    let addr = alloc_string(text, site, stack, global_frame);
+    push_std_string(Values::POINTER(addr, addr), text.len(), stack, cur_frame, scope, global_scope);
+}
+
+pub fn push_std_string(text: Values, len: usize, stack: &mut [Frame], cur_frame: usize, scope: &Scope, global_scope: &Scope) {
+    push_std_struct("std.string", &[text, Values::UNSIGNED(len as u64)], stack, cur_frame, scope, global_scope);
+}
+
+// pushes one of the structs from `add_std_structs`, given the values of its variables in order
+pub fn push_std_struct(name: &str, vals: &[Values], stack: &mut [Frame], cur_frame: usize, scope: &Scope, global_scope: &Scope) {
+    let name = name.to_string();
+
+    let index = push_struct(&name, cur_frame, stack, global_scope, scope);
+    for (i, val) in vals.iter().enumerate() {
+        stack[cur_frame].set(index + i, val);
+    }
+
+    stack[cur_frame].push(Value { typ: Type { typ: vec![Types::STRUCT(name.clone())] }, val: Values::STRUCT(String::new(), name, index) });
+}
+
+fn format_num(val: &Values) -> String {
+    return match val {
+        Values::SIGNED(n) => n.to_string(),
+        Values::UNSIGNED(n) => n.to_string(),
+        Values::DECIMAL(n) => n.to_string(),
+        _ => panic!("expected a number to format, got `{}`", val),
+    };
+}
+
+// output is flushed straight away so it stays in order with anything native code writes
+fn write_out(text: &[u8], newline: bool) {
+    let mut out = io::stdout().lock();
+    out.write_all(text).expect("failed to write to stdout");
+    if newline {
+        out.write_all(b"\n").expect("failed to write to stdout");
+    }
+    out.flush().expect("failed to write to stdout");
+}
+
+fn write_err(text: &[u8], newline: bool) {
+    let mut out = io::stderr().lock();
+    out.write_all(text).expect("failed to write to stderr");
+    if newline {
+        out.write_all(b"\n").expect("failed to write to stderr");
+    }
+}
+
+// errors that don't come from the OS, like a path or address that isn't valid or a timeout, get the code the OS would use for them
+#[cfg(unix)]
+const INVALID: i32 = 22; // EINVAL
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const TIMED_OUT: i32 = 110; // ETIMEDOUT
+#[cfg(all(unix, not(any(target_os = "linux", target_os = "android"))))]
+const TIMED_OUT: i32 = 60; // ETIMEDOUT on macos and the bsds
+
+#[cfg(windows)]
+const INVALID: i32 = 87; // ERROR_INVALID_PARAMETER
+#[cfg(windows)]
+const TIMED_OUT: i32 = 10060; // WSAETIMEDOUT
+
+pub fn error_code(error: &io::Error) -> i32 {
+    return match error.raw_os_error() {
+        Some(code) => code,
+        None if error.kind() == io::ErrorKind::TimedOut => TIMED_OUT,
+        None => INVALID,
+    };
+}
+
+// functions that give back a number give back the error code negated when they fail
+// the error code (or 0) is also kept in `last_error`, for the module's `error` function
+pub fn status(result: io::Result<i64>, last_error: &Cell<i32>) -> Values {
+    return match result {
+        Ok(n) => {
+            last_error.set(0);
+            Values::SIGNED(n)
+        }
+        Err(error) => {
+            last_error.set(error_code(&error));
+            Values::SIGNED(-(error_code(&error) as i64))
+        }
+    };
+}
+
+// functions that give back a handle or text give back a null one when they fail
+pub fn opened<T>(result: io::Result<T>, last_error: &Cell<i32>) -> Option<T> {
+    return match result {
+        Ok(val) => {
+            last_error.set(0);
+            Some(val)
+        }
+        Err(error) => {
+            last_error.set(error_code(&error));
+            None
+        }
+    };
+}
+
+pub fn push_num(val: Values, typ: Types, stack: &mut [Frame], cur_frame: usize) {
+    stack[cur_frame].push(Value { typ: Type { typ: vec![typ] }, val });
+}
+
+// pops a pointer to a buffer to read into
+pub fn pop_buffer(frame: &mut Frame) -> usize {
+    let buf = frame.pop();
+    return match buf.val {
+        Values::POINTER(addr, _) => addr,
+        _ => panic!("expected a pointer to read into, got `{}`", buf.val),
+    };
+}
+
+pub fn pop_handle(frame: &mut Frame) -> usize {
+    let val = frame.pop();
+    return match val.val {
+        Values::HANDLE(handle, _) => handle,
+        _ => panic!("expected a handle, got `{}`", val.val),
+    };
+}
+
+// i32 std.errno()
+fn errno(stack: &mut [Frame], cur_frame: usize, _global_frame: usize, _scope: &Scope, _global_scope: &Scope) {
+    push_num(Values::SIGNED(last_errno() as i64), Types::I32, stack, cur_frame);
+}
+
+// void std.print(u8* text, u64 length)
+fn print(stack: &mut [Frame], cur_frame: usize, global_frame: usize, _scope: &Scope, _global_scope: &Scope) {
+    write_out(&pop_text(stack, cur_frame, global_frame), false);
+}
+
+// void std.println(u8* text, u64 length)
+fn println(stack: &mut [Frame], cur_frame: usize, global_frame: usize, _scope: &Scope, _global_scope: &Scope) {
+    write_out(&pop_text(stack, cur_frame, global_frame), true);
+}
+
+// void std.eprint(u8* text, u64 length)
+fn eprint(stack: &mut [Frame], cur_frame: usize, global_frame: usize, _scope: &Scope, _global_scope: &Scope) {
+    write_err(&pop_text(stack, cur_frame, global_frame), false);
+}
+
+// void std.eprintln(u8* text, u64 length)
+fn eprintln(stack: &mut [Frame], cur_frame: usize, global_frame: usize, _scope: &Scope, _global_scope: &Scope) {
+    write_err(&pop_text(stack, cur_frame, global_frame), true);
+}
+
+// struct std.string std.read_line()
+// the line is given without its line ending, at the end of input the text is null
+fn read_line(stack: &mut [Frame], cur_frame: usize, global_frame: usize, scope: &Scope, global_scope: &Scope) {
+    let mut line = Vec::new();
+    let read = io::stdin().lock().read_until(b'\n', &mut line).expect("failed to read from stdin");
+
+    if read == 0 {
+        push_std_string(Values::POINTER(0, 0), 0, stack, cur_frame, scope, global_scope);
+        return;
+    }
+
+    if line.ends_with(b"\n") {
+        line.pop();
+        if line.ends_with(b"\r") {
+            line.pop();
+        }
+    }
+
+    push_string(&line, "std.read_line", stack, cur_frame, global_frame, scope, global_scope);
+}
+
+// void std.print_num(number n)
+fn print_num(stack: &mut [Frame], cur_frame: usize, _global_frame: usize, _scope: &Scope, _global_scope: &Scope) {
+    let val = stack[cur_frame].pop();
+    write_out(format_num(&val.val).as_bytes(), false);
+}
+
+// struct std.string std.ntos(number n)
+fn ntos(stack: &mut [Frame], cur_frame: usize, global_frame: usize, scope: &Scope, global_scope: &Scope) {
+    let val = stack[cur_frame].pop();
+    push_string(format_num(&val.val).as_bytes(), "std.ntos", stack, cur_frame, global_frame, scope, global_scope);
+}
+
+// struct std.string std.format_float(f64 n, u64 decimals)
+// rounds to the given number of digits after the decimal point
+fn format_float(stack: &mut [Frame], cur_frame: usize, global_frame: usize, scope: &Scope, global_scope: &Scope) {
+    let decimals = pop_usize(&mut stack[cur_frame], "number of decimals");
+    let val = stack[cur_frame].pop();
+
+    let n = match val.val {
+        Values::SIGNED(n) => n as f64,
+        Values::UNSIGNED(n) => n as f64,
+        Values::DECIMAL(n) => n,
+        _ => panic!("expected a number to format, got `{}`", val.val),
+    };
+
+    push_string(format!("{n:.decimals$}").as_bytes(), "std.format_float", stack, cur_frame, global_frame, scope, global_scope);
+}
@@ -3,13 +3,15 @@ use std::{env, fs, path::Path, process, collections::HashSet};
 use indexmap::IndexMap;
 
 use _struct::Struct;
-use _type::Types;
+use _type::{Type, Types};
 use frame::Frame;
+use heap::Heap;
 use function::{Extern, Function};
 use module::Module;
 use scope::Scope;
+use block::Block;
 use parse_scope::{parse_bytecode_string, parse_dyn_number, parse_scope, parse_type};
-use exec_scope::exec_scope;
+use exec_scope::{exec_func, exec_scope, is_main_entered};
 use value::{Value, Values};
 use half::f16;
 
@@ -25,11 +27,17 @@ mod ffi;
 mod _struct;
 mod block;
 mod module;
+mod heap;
+mod gc;
+mod intrinsics;
+mod std_fs;
+mod std_net;
+mod std_os;
+mod std_time;
 
 // TODO: better error handling
 // TODO: result type
 // TODO: actual type checking
-// TODO: pointers to stack
 fn main() {
     let args: Vec<String> = env::args().collect();
 
@@ -43,6 +51,7 @@ fn main() {
 
     let mut timing = false;
     let mut debug = false;
+    let mut heap_stats = false;
 
     let mut i = 1;
 
@@ -62,11 +71,41 @@ fn main() {
             "--debug" | "-d" => {
                 debug = true;
             }
+            "--checked" => {
+                value::set_checked(true);
+            }
+            "--heap-stats" => {
+                heap_stats = true;
+            }
+            "--memcheck" => {
+                heap::set_memcheck(true);
+            }
+            "--gc" => {
+                gc::set_gc(true);
+            }
+            "--lib-alias" => {
+                if args.len() <= i + 1 {
+                    println!("library alias expected");
+                    process::exit(1);
+                }
+
+                i += 1;
+                match args[i].split_once("=") {
+                    Some((name, file)) => ffi::add_lib_alias(name.to_string(), file.to_string()),
+                    None => {
+                        println!("library alias should be given as `name=file`");
+                        process::exit(1);
+                    }
+                }
+            }
+            "--lazy-externs" => {
+                ffi::set_lazy_externs(true);
+            }
             "help" => {
                 usage();
                 process::exit(0);
             }
-            "run" | "r" => {
+            "run" | "r" | "--" => {
                 if args.len() <= i + 1 {
                     println!(".rbb file expected");
                     process::exit(1);
@@ -74,14 +113,23 @@ fn main() {
 
                 i += 1;
                 program = args[i].clone();
+                break;
             }
             _ => {
                 program = args[i].clone();
+                break;
             }
         }
         i += 1;
     }
 
+    // everything after the program file is passed to the program, a `--` after it only separates them
+    i += 1;
+    if args.get(i).is_some_and(|arg| arg == "--") {
+        i += 1;
+    }
+    let program_args = args[i.min(args.len())..].to_vec();
+
     if program.is_empty() {
         usage();
         println!("no program provided");
@@ -98,6 +146,10 @@ fn main() {
         process::exit(1);
     }
 
+    let mut program_args = program_args;
+    program_args.insert(0, program.clone());
+    std_os::set_args(program_args.clone());
+
     let index = program.replace("\\", "/").rfind("/");
     if index.is_some() {
         add_link_path(program.split_at(index.unwrap()).0.to_string(), &mut linker_paths);
@@ -106,7 +158,7 @@ fn main() {
     let program = fs::read(program).expect("failed to read program");
 
     let start = std::time::Instant::now();
-    let retval = run_program(&program, linker_paths, debug, timing);
+    let retval = run_program(&program, &program_args, linker_paths, debug, timing, heap_stats);
     if timing {
         println!("program parsing and execution took {:.6}s ({:.4}ms)", start.elapsed().as_secs_f32(), start.elapsed().as_secs_f32() * 1000f32);
     }
@@ -116,43 +168,115 @@ fn main() {
     }
 }
 
-pub fn run_program(program: &Vec<u8>, linker_paths: HashSet<String>, debug: bool, timing: bool) -> i32 {
+pub fn run_program(program: &Vec<u8>, args: &[String], linker_paths: HashSet<String>, debug: bool, timing: bool, heap_stats: bool) -> i32 {
     let mut consts: IndexMap<String, i32> = IndexMap::new();
 
     init_consts(&mut consts);
 
     let mut stack: Vec<Frame> = Vec::new();
 
-    stack.push(Frame { vars: IndexMap::new(), stack: Vec::new(), allocs: Vec::new(), });
+    stack.push(Frame::new());
 
     let mut global_scope = Scope::new();
     
     parse_program(program, &mut stack, &mut global_scope, &linker_paths, debug, &consts, timing, "program");
+    intrinsics::add_std_structs(&mut global_scope);
+
+    let original = global_scope.clone();
+    if let Err(error) = layout_structs(&mut global_scope, &original, &original) {
+        panic!("failed to parse program:\n{error}");
+    }
+
+    if !ffi::is_lazy_externs() {
+        ffi::check_externs(&global_scope);
+    }
 
     let data_frame = stack.remove(1);
 
     let global_frame = stack.len() - 1;
     stack[global_frame].extend(data_frame);
+
+    let heap = std::mem::replace(&mut stack[0].heap, Heap::new());
+    stack[global_frame].heap = heap;
     
     let start = std::time::Instant::now();
-    let retval = exec_scope(&global_scope, &global_scope, &mut stack, global_frame, false, &mut 0, global_frame, global_frame, &String::new());
+    let mut retval = exec_scope(&global_scope, &global_scope, &mut stack, global_frame, false, &mut 0, global_frame, global_frame, &String::new());
+
+    // main functions are not required, if there is one it runs after the top level code and what it returns is the exit code
+    if retval == 0 && global_scope.functions.contains_key("main") && !is_main_entered() {
+        retval = call_main(&global_scope, &mut stack, global_frame, args);
+    }
 
     if timing {
         println!();
         println!("program execution took {:.6}s ({:.4}ms)", start.elapsed().as_secs_f32(), start.elapsed().as_secs_f32() * 1000f32);
     }
 
-    if retval != 0 {
+    // anything unreachable by now would have been collected eventually, so it isn't a leak
+    if gc::is_gc() {
+        gc::collect(&mut stack, global_frame);
+    }
+
+    if heap_stats {
+        stack[global_frame].heap.print_stats();
+    }
+
+    if stack[global_frame].heap.is_memcheck() {
+        stack[global_frame].heap.print_leaks();
+    }
+
+    ffi::close_libraries();
+
+    return retval;
+}
+
+// main can take no arguments, or `argc` and `argv` like in C
+fn call_main(global_scope: &Scope, stack: &mut Vec<Frame>, global_frame: usize, args: &[String]) -> i32 {
+    let main = global_scope.functions.get("main").unwrap();
+    let depth = stack[global_frame].len();
+
+    match main.arg_types.len() {
+        0 => (),
+        2 => {
+            let argv = push_args(args, stack, global_frame);
+
+            stack[global_frame].push(Value { typ: main.arg_types[0].clone(), val: Values::UNSIGNED(args.len() as u64) });
+            stack[global_frame].push(Value { typ: main.arg_types[1].clone(), val: Values::POINTER(argv, argv) });
+        }
+        n => panic!("`main` should take no arguments or `argc` and `argv`, it takes {n}"),
+    }
+
+    let retval = exec_func(main, global_scope, global_scope, stack, global_frame, global_frame, &String::new());
+
+    // a main that returns nothing exits with 0
+    if retval != 0 || stack[global_frame].len() == depth {
         return retval;
     }
-    
-    // if let Some(func) = global_scope.functions.get("main") { // main functions are not required
-    //     return exec_func(func, &global_scope, &mut stack, global_frame, global_frame);
-    // }
 
-    return 0;
+    let val = stack[global_frame].pop();
+    return match val.val {
+        Values::SIGNED(n) => n as i32,
+        Values::UNSIGNED(n) => n as i32,
+        _ => panic!("`main` should return an integer or nothing, it returned `{}`", val.val),
+    };
+}
+
+// argv is a null terminated array of C strings, the program file first
+// it lives for the whole program, so main doesn't have to free it
+fn push_args(args: &[String], stack: &mut Vec<Frame>, global_frame: usize) -> usize {
+    let ptr = Type { typ: vec![Types::POINTER, Types::U8] };
+
+    let argv = stack[global_frame].heap.alloc((args.len() + 1) * ptr.get_size());
+    stack[global_frame].heap.set_static(argv);
+
+    for (i, arg) in args.iter().enumerate() {
+        let text = intrinsics::alloc_string(arg.as_bytes(), "the program's arguments", stack, global_frame);
+        stack[global_frame].heap.set_static(text);
+        stack[global_frame].heap.store(argv + i * ptr.get_size(), &ptr, &Values::POINTER(text, text));
+    }
+    stack[global_frame].heap.store(argv + args.len() * ptr.get_size(), &ptr, &Values::POINTER(0, 0));
 
-    // dbg!(stack);
+    return argv;
 }
 
 fn init_consts(consts: &mut IndexMap<String, i32>) {
@@ -173,10 +297,17 @@ fn usage() {
     println!("  --time/-t                       enables execution timing");
     println!("  --link/-l  [path]               provide a linking path");
     println!("  --debug/-d                      enables debug mode");
+    println!("  --checked                       traps on integer overflow instead of wrapping");
+    println!("  --heap-stats                    prints heap usage statistics when the program exits");
+    println!("  --memcheck                      checks every memory access and reports leaks when the program exits");
+    println!("  --gc                            frees heap allocations automatically once they can't be reached");
+    println!("  --lazy-externs                  only looks externs up when they are first called");
+    println!("  --lib-alias [name=file]         lets externs use `name` as their library to load `file`");
     println!("Subcommands");
     println!("  help                            prints this subcommand list");
-    println!("  run/r      [file]               runs the given program");
-    println!("  [file]                          runs the given program");
+    println!("  run/r      [file] [args]        runs the given program, passing it everything after the file");
+    println!("  [file] [args]                   runs the given program, passing it everything after the file");
+    println!("  --         [file] [args]        runs the given program, for files starting with `-`");
 }
 
 fn parse_program(program: &Vec<u8>, stack: &mut Vec<Frame>, scope: &mut Scope, linker_paths: &HashSet<String>, debug: bool, consts: &IndexMap<String, i32>, timing: bool, what_parsing: &str) {
@@ -189,7 +320,7 @@ fn parse_program(program: &Vec<u8>, stack: &mut Vec<Frame>, scope: &mut Scope, l
     };
 
     let global_frame = stack.len();
-    stack.push(Frame { vars: IndexMap::new(), stack: Vec::new(), allocs: Vec::new() });
+    stack.push(Frame::new());
     
     match parse_data_section(&program, stack, &mut index, global_frame) {
         Ok(_) => (),
@@ -223,88 +354,40 @@ fn parse_data_section(bytes: &Vec<u8>, stack: &mut Vec<Frame>, index: &mut usize
 
         let len = parse_dyn_number(bytes, index)?;
 
-        let i = stack[global_frame].stack.len();
-        stack[global_frame].push_var(&name, typ.clone(), Values::POINTER(i + 1, len));
-
-        // TODO: macroize
+        // all data is laid out in the first frame's heap, which becomes the global heap once parsing is done
         match typ.typ[0] {
             Types::POINTER => {
-                for _ in 0..len {
-                    match typ.typ[1] {
-                        Types::I8 => {
-                            let val = i8::from_be_bytes(bytes[*index..*index+1].try_into().unwrap());
-                            *index += 1;
-
-                            stack[global_frame].push(Value { typ: typ.clone().pop(), val: Values::SIGNED(val as i64) });
-                        }
-                        Types::I16 => {
-                            let val = i16::from_be_bytes(bytes[*index..*index+2].try_into().unwrap());
-                            *index += 2;
-
-                            stack[global_frame].push(Value { typ: typ.clone().pop(), val: Values::SIGNED(val as i64) });
-                        }
-                        Types::I32 => {
-                            let val = i32::from_be_bytes(bytes[*index..*index+4].try_into().unwrap());
-                            *index += 4;
-
-                            stack[global_frame].push(Value { typ: typ.clone().pop(), val: Values::SIGNED(val as i64) });
-                        }
-                        Types::I64 => {
-                            let val = i64::from_be_bytes(bytes[*index..*index+8].try_into().unwrap());
-                            *index += 8;
-
-                            stack[global_frame].push(Value { typ: typ.clone().pop(), val: Values::SIGNED(val as i64) });
-                        }
-                        Types::U8 => {
-                            let val = u8::from_be_bytes(bytes[*index..*index+1].try_into().unwrap());
-                            *index += 1;
-
-                            stack[global_frame].push(Value { typ: typ.clone().pop(), val: Values::UNSIGNED(val as u64) });
-                        }
-                        Types::U16 => {
-                            let val = u16::from_be_bytes(bytes[*index..*index+2].try_into().unwrap());
-                            *index += 2;
-
-                            stack[global_frame].push(Value { typ: typ.clone().pop(), val: Values::UNSIGNED(val as u64) });
-                        }
-                        Types::U32 => {
-                            let val = u32::from_be_bytes(bytes[*index..*index+4].try_into().unwrap());
-                            *index += 4;
-
-                            stack[global_frame].push(Value { typ: typ.clone().pop(), val: Values::UNSIGNED(val as u64) });
-                        }
-                        Types::U64 => {
-                            let val = u64::from_be_bytes(bytes[*index..*index+8].try_into().unwrap());
-                            *index += 8;
-
-                            stack[global_frame].push(Value { typ: typ.clone().pop(), val: Values::UNSIGNED(val as u64) });
-                        }
-                        Types::F16 => {
-                            let val = f16::to_f64(f16::from_be_bytes(bytes[*index..*index+2].try_into().unwrap()));
-                            *index += 2;
-
-                            stack[global_frame].push(Value { typ: typ.clone().pop(), val: Values::DECIMAL(val as f64) });
-                        }
-                        Types::F32 => {
-                            let val = f32::from_be_bytes(bytes[*index..*index+4].try_into().unwrap());
-                            *index += 4;
-
-                            stack[global_frame].push(Value { typ: typ.clone().pop(), val: Values::DECIMAL(val as f64) });
-                        }
-                        Types::F64 => {
-                            let val = f64::from_be_bytes(bytes[*index..*index+8].try_into().unwrap());
-                            *index += 8;
-
-                            stack[global_frame].push(Value { typ: typ.clone().pop(), val: Values::DECIMAL(val as f64) });
-                        }
+                let elem = typ.clone().pop();
+                let size = elem.get_size();
+
+                let addr = stack[0].heap.alloc(len * size);
+                stack[0].heap.set_static(addr);
+                stack[global_frame].push_var(&name, typ.clone(), Values::POINTER(addr, addr));
+
+                for i in 0..len {
+                    let data = &bytes[*index..*index+size];
+                    let val = match elem.typ[0] {
+                        Types::I8  => Values::SIGNED(i8::from_be_bytes(data.try_into().unwrap()) as i64),
+                        Types::I16 => Values::SIGNED(i16::from_be_bytes(data.try_into().unwrap()) as i64),
+                        Types::I32 => Values::SIGNED(i32::from_be_bytes(data.try_into().unwrap()) as i64),
+                        Types::I64 => Values::SIGNED(i64::from_be_bytes(data.try_into().unwrap())),
+                        Types::U8  => Values::UNSIGNED(u8::from_be_bytes(data.try_into().unwrap()) as u64),
+                        Types::U16 => Values::UNSIGNED(u16::from_be_bytes(data.try_into().unwrap()) as u64),
+                        Types::U32 => Values::UNSIGNED(u32::from_be_bytes(data.try_into().unwrap()) as u64),
+                        Types::U64 => Values::UNSIGNED(u64::from_be_bytes(data.try_into().unwrap())),
+                        Types::F16 => Values::DECIMAL(f16::from_be_bytes(data.try_into().unwrap()).to_f64()),
+                        Types::F32 => Values::DECIMAL(f32::from_be_bytes(data.try_into().unwrap()) as f64),
+                        Types::F64 => Values::DECIMAL(f64::from_be_bytes(data.try_into().unwrap())),
                         _ => panic!("unsupported data section type `{:?}`", typ.typ),
-                    }
+                    };
+                    *index += size;
+
+                    stack[0].heap.store(addr + i * size, &elem, &val);
                 }
             }
             _ => panic!("unsupported data section type `{:?}`", typ.typ),
         }
     }
-
     Ok(())
 }
 
@@ -332,6 +415,19 @@ fn get_func<'a>(name: &String, scope: &'a Scope, global_scope: &'a Scope, module
     }
 }
 
+// looks up a function to call from code running in `module`, and finds the name of the module it will run in
+// this function expects the function to exist
+fn get_callee(name: &String, scope: &Scope, global_scope: &Scope, module_frame: usize, global_frame: usize, module: &String) -> (String, usize, Function) {
+    let (func_module, frame, func) = get_func(name, scope, global_scope, module_frame, global_frame, module.clone());
+
+    // functions in nested modules run in the module's full name
+    if frame != module_frame && !module.is_empty() && module_exists(&(module.clone() + "." + &func_module), scope, global_scope) {
+        return (module.clone() + "." + &func_module, frame, func);
+    }
+
+    return (func_module, frame, func);
+}
+
 // this function expects the extern to exist
 // if it doesnt, it will crash
 fn get_extern<'a>(name: &String, scope: &'a Scope, global_scope: &'a Scope) -> &'a Extern {
@@ -408,6 +504,48 @@ fn get_var<'a>(name: &String, scope: &'a Scope, global_scope: &'a Scope, stack:
     }
 }
 
+// finds the frame and slot a variable lives in, following the same lookup rules as get_var
+fn get_var_slot(name: &String, scope: &Scope, global_scope: &Scope, stack: &mut [Frame], cur_frame: usize, module_frame: usize, global_frame: usize) -> (usize, usize) {
+    if stack[cur_frame].vars.contains_key(name) {
+        return (cur_frame, *stack[cur_frame].vars.get(name).unwrap());
+    }
+
+    if name.contains(".") {
+        let split = name.split(".").collect::<Vec<&str>>();
+        let struct_name = &split[0].to_string();
+
+        if var_exists(struct_name, stack, cur_frame, global_frame, module_frame) {
+            let (parent_name, var_name) = name.rsplit_once(".").unwrap();
+            let parent_struct = get_var(&parent_name.to_string(), scope, global_scope, stack, cur_frame, module_frame, global_frame).clone();
+
+            let (module, struct_name, index) = match &parent_struct.val {
+                Values::STRUCT(module, name, index) => (module, name, index),
+                _ => panic!("cannot get a variable in a value that is not a struct"),
+            };
+            let _struct = get_struct(module, struct_name, scope, global_scope);
+
+            let var_offset = _struct.var_offsets.get(var_name).
+                                    expect(format!("attempted to get non-existant variable `{var_name}` in struct `{}`", _struct.name).as_str());
+
+            // struct variables live in the current frame, see get_struct_var
+            return (cur_frame, index + var_offset);
+        } else if module_exists(struct_name, &scope, &global_scope) {
+            let module = get_module(struct_name, scope, global_scope);
+            return get_var_slot(&split[1].to_string(), &module.scope, global_scope, stack, cur_frame, module.frame, global_frame);
+        } else {
+            panic!("tried to get undefined variable `{name}`");
+        }
+    }
+
+    if stack[module_frame].vars.contains_key(name) {
+        return (module_frame, *stack[module_frame].vars.get(name).unwrap());
+    } else if stack[global_frame].vars.contains_key(name) {
+        return (global_frame, *stack[global_frame].vars.get(name).unwrap());
+    } else {
+        panic!("attempted to create a reference to undefined variable `{name}`");
+    }
+}
+
 fn set_var(name: &String, value: &Values, scope: &Scope, global_scope: &Scope, stack: &mut [Frame], cur_frame: usize, module_frame: usize, global_frame: usize) {
     if name == "_" {
         return;
@@ -464,6 +602,86 @@ fn get_struct<'a>(module: &String, name: &String, global_scope: &'a Scope, scope
     }
 }
 
+// structs containing other structs can only be laid out once every struct is known, so this is done after the program is parsed
+// every copy of a scope has its own copies of the structs, `original` is an untouched copy of `scope` to look them up in
+fn layout_structs(scope: &mut Scope, original: &Scope, global_scope: &Scope) -> Result<(), String> {
+    for (name, strct) in &mut scope.structs {
+        *strct = layout_struct(name, global_scope, original, &mut Vec::new())?;
+    }
+
+    for (block, original) in scope.blocks.iter_mut().zip(&original.blocks) {
+        if let (Block::SCOPE(block), Block::SCOPE(original)) = (block, original) {
+            layout_structs(block, original, global_scope)?;
+        }
+    }
+
+    for (func, original) in scope.functions.values_mut().zip(original.functions.values()) {
+        layout_structs(&mut func.scope, &original.scope, global_scope)?;
+    }
+
+    for (module, original) in scope.modules.values_mut().zip(original.modules.values()) {
+        layout_structs(&mut module.scope, &original.scope, global_scope)?;
+    }
+
+    if let (Some(parent), Some(original)) = (&mut scope.parent_scope, &original.parent_scope) {
+        layout_structs(parent, original, global_scope)?;
+    }
+
+    return Ok(());
+}
+
+// looks a struct up the same way get_struct does and lays it out, `outer` holds the structs it is nested in
+fn layout_struct(name: &String, global_scope: &Scope, scope: &Scope, outer: &mut Vec<(usize, usize, String)>) -> Result<Struct, String> {
+    let (mut strct, global_scope, scope) = if scope.struct_exists(name, false) {
+        (scope.get_struct(name), global_scope, scope)
+    } else if global_scope.struct_exists(name, false) {
+        (global_scope.get_struct(name), global_scope, global_scope)
+    } else if name.contains(".") {
+        let split = name.split(".").collect::<Vec<&str>>();
+        let module = get_module(&split[0].to_string(), scope, global_scope);
+
+        return layout_struct(&split[1..].to_vec().join("."), &module.scope, global_scope, outer);
+    } else {
+        return Err(format!("undefined struct `{name}`"));
+    };
+
+    // a struct holding itself by value would be infinitely big
+    let key = (scope as *const Scope as usize, global_scope as *const Scope as usize, name.clone());
+    if outer.contains(&key) {
+        let path = outer.iter().map(|(_, _, name)| name.as_str()).collect::<Vec<_>>().join("` -> `");
+        return Err(format!("struct `{name}` contains itself by value (`{path}` -> `{name}`), it can only hold a pointer to itself"));
+    }
+
+    if !strct.var_types.iter().any(|typ| matches!(typ.typ[0], Types::STRUCT(_))) {
+        return Ok(strct);
+    }
+
+    outer.push(key);
+    let mut fields = Vec::new();
+    for typ in &strct.var_types {
+        fields.push(match &typ.typ[0] {
+            Types::STRUCT(name) => {
+                let nested = layout_struct(name, global_scope, scope, outer)?;
+                (nested.size, nested.align)
+            }
+            _ => (typ.get_size(), typ.get_align()),
+        });
+    }
+    outer.pop();
+
+    strct.layout(&fields);
+
+    return Ok(strct);
+}
+
+// size in bytes of a value with type `typ` when stored in memory
+fn get_type_size(typ: &Type, scope: &Scope, global_scope: &Scope) -> usize {
+    match &typ.typ[0] {
+        Types::STRUCT(name) => return get_struct(&String::new(), name, global_scope, scope).size,
+        _ => return typ.get_size(),
+    }
+}
+
 fn set_struct_var(parent_struct: &Value, source_struct: &String, name: &String, value: &Values, scope: &Scope, global_scope: &Scope, stack: &mut [Frame], cur_frame: usize, module_frame: usize, global_frame: usize) {
     if name.contains(".") {
         let split = name.split(".").collect::<Vec<&str>>();
@@ -4,7 +4,7 @@ use indexmap::IndexMap;
 
 use half::f16;
 
-use crate::{_struct::Struct, _type::{Type, Types}, block::Block, frame::Frame, function::{Extern, Function}, instruction::{Instruction, Opcode}, module::Module, parse_program, scope::Scope, value::{Value, Values}};
+use crate::{_struct::Struct, _type::{Type, Types}, block::Block, ffi::get_lib_alias, frame::Frame, function::{Extern, Function}, gc, instruction::{Instruction, Opcode}, module::Module, parse_program, scope::Scope, value::{Value, Values}};
 
 // expects `index` to be at the start of the scope body
 pub fn parse_scope(bytes: &Vec<u8>, stack: &mut Vec<Frame>, index: &mut usize, linker_paths: &HashSet<String>, debug: bool, consts: &IndexMap<String, i32>, timing: bool) -> Result<Scope, String> {
@@ -54,6 +54,10 @@ pub fn parse_scope(bytes: &Vec<u8>, stack: &mut Vec<Frame>, index: &mut usize, l
                     scope.merge(s);
                 }
             }
+            0xF5 => {
+                *index += 1;
+                parse_flag(bytes, index)?;
+            }
             0xF6 => {
                 *index += 1;
                 let name = parse_bytecode_string(bytes, index)?;
@@ -63,7 +67,7 @@ pub fn parse_scope(bytes: &Vec<u8>, stack: &mut Vec<Frame>, index: &mut usize, l
                 let module = Module { name: name.clone(), scope: module_scope, frame: stack.len() };
                 scope.modules.insert(name, module);
 
-                stack.push(Frame { vars: IndexMap::new(), stack: Vec::new(), allocs: Vec::new() });
+                stack.push(Frame::new());
             }
             _ => {
                 if scope.blocks.len() == 0 {
@@ -106,6 +110,19 @@ pub fn parse_scope(bytes: &Vec<u8>, stack: &mut Vec<Frame>, index: &mut usize, l
     return Ok(scope);
 }
 
+// expects `index` to be at the flag name
+// flags turn on runtime features for the whole program
+fn parse_flag(bytes: &Vec<u8>, index: &mut usize) -> Result<(), String> {
+    let flag = parse_bytecode_string(bytes, index)?;
+
+    match flag.as_str() {
+        "gc" => gc::set_gc(true),
+        _ => return Err(format!("unknown program flag `{flag}` at {:#06x}", *index - flag.len() - 1)),
+    }
+
+    return Ok(());
+}
+
 // this just skips past a scope
 // throws away everything it parses
 // not the most performant but whatever
@@ -148,6 +165,10 @@ fn skip_scope(bytes: &Vec<u8>, index: &mut usize, timing: bool) {
                 *index += 1;
                 let _ = eval_conditional(bytes, &mut stack, index, &linker_paths, debug, &consts, timing);
             }
+            0xF5 => {
+                *index += 1;
+                let _ = parse_bytecode_string(bytes, index);
+            }
             0xF6 => {
                 *index += 1;
                 let _ = parse_bytecode_string(bytes, index);
@@ -263,7 +284,25 @@ fn eval_conditional(bytes: &Vec<u8>, stack: &mut Vec<Frame>, index: &mut usize,
 fn parse_struct(bytes: &Vec<u8>, index: &mut usize) -> Result<Struct, String> {
     let name = parse_bytecode_string(bytes, index)?;
 
-    let mut strct = Struct { name, size: 0, var_names: Vec::new(), var_types: Vec::new(), var_offsets: IndexMap::new() };
+    let mut strct = Struct { name, size: 0, align: 1, packed: false, min_align: 1, var_names: Vec::new(), var_types: Vec::new(), var_offsets: IndexMap::new(), byte_offsets: IndexMap::new() };
+
+    // attributes come between the name and the body
+    while bytes[*index] != 0xFE {
+        match bytes[*index] {
+            0x01 => {
+                *index += 1;
+                strct.packed = true;
+            }
+            0x02 => {
+                *index += 1;
+                strct.min_align = parse_dyn_number(bytes, index)?;
+                if !strct.min_align.is_power_of_two() {
+                    return Err(format!("struct `{}` has alignment {}, which is not a power of two", strct.name, strct.min_align));
+                }
+            }
+            attr => return Err(format!("unknown struct attribute {attr:#04x} at {:#06x}", *index)),
+        }
+    }
 
     *index += 1;
 
@@ -271,9 +310,6 @@ fn parse_struct(bytes: &Vec<u8>, index: &mut usize) -> Result<Struct, String> {
     while bytes[*index] != 0xFD {
         let typ = parse_type(bytes, index)?;
         let name = parse_bytecode_string(bytes, index)?;
-        
-        // TODO: replace this with the size in bytes when byte-wise memory accesses are implemented
-        strct.size += 1; //typ.get_size(); // TODO: structs are of unknown size! should we even handle this?
 
         strct.var_types.push(typ);
         strct.var_names.push(name.clone());
@@ -282,6 +318,10 @@ fn parse_struct(bytes: &Vec<u8>, index: &mut usize) -> Result<Struct, String> {
         offset += 1;
     }
 
+    // nested structs might not have been parsed yet, so they are laid out again once the whole program is (see layout_structs)
+    let fields = strct.var_types.iter().map(|typ| (typ.get_size(), typ.get_align())).collect::<Vec<_>>();
+    strct.layout(&fields);
+
     *index += 1;
 
     return Ok(strct);
@@ -361,27 +401,41 @@ pub fn parse_extern(bytes: &Vec<u8>, index: &mut usize, linker_paths: &HashSet<S
     let name = parse_bytecode_string(bytes, index)?;
 
     let mut arg_types: Vec<Type> = Vec::new();
+    let mut variadic = false;
     while bytes[*index] != 0xF8 {
+        if bytes[*index] == 0xF4 {
+            *index += 1;
+            variadic = true;
+
+            if bytes[*index] != 0xF8 {
+                return Err(format!("variadic marker must come after the last argument of extern `{name}`"));
+            }
+            break;
+        }
+
         arg_types.push(parse_type(bytes, index)?);
     }
 
     *index += 1;
 
-    let dll = parse_bytecode_string(bytes, index)?;
+    let dll = get_lib_alias(&parse_bytecode_string(bytes, index)?);
     let mut dll_path = String::new();
     if Path::exists(Path::new(&dll)) {
         dll_path = dll.clone();
     }
 
-    for path in linker_paths {
-        let paths = get_paths(path).unwrap();
+    // no library means the symbol is looked up in the process itself
+    if !dll.is_empty() {
+        for path in linker_paths {
+            let paths = get_paths(path).unwrap();
 
-        for path in paths {
-            if path.ends_with(&dll) {
-                if dll_path == "" {
-                    dll_path = path;
-                } else {
-                    return Err(format!("ambiguous extern dll {dll}"));
+            for path in paths {
+                if path.ends_with(&dll) {
+                    if dll_path == "" {
+                        dll_path = path;
+                    } else {
+                        return Err(format!("ambiguous extern dll {dll}"));
+                    }
                 }
             }
         }
@@ -395,7 +449,7 @@ pub fn parse_extern(bytes: &Vec<u8>, index: &mut usize, linker_paths: &HashSet<S
 
     let access_name = parse_bytecode_string(bytes, index)?;
 
-    return Ok(Extern { name, access_name, ret_type, arg_types, dll });
+    return Ok(Extern { name, access_name, ret_type, arg_types, variadic, dll });
 }
 
 // expects `index` to be at the start of the instruction
@@ -1157,6 +1211,284 @@ pub fn parse_instruction(bytes: &Vec<u8>, index: &mut usize) -> Result<Instructi
             parse_bytecode_string(bytes, index)?)
         }
 
+        // checked arithmetic
+        0x94 => {
+            Opcode::CHK_I_I_I(parse_immediate(bytes, index)?, 
+            parse_immediate(bytes, index)?, 
+            parse_immediate(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?)
+        }
+        0x95 => {
+            Opcode::CHK_V_I_I(parse_bytecode_string(bytes, index)?, 
+            parse_immediate(bytes, index)?, 
+            parse_immediate(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?)
+        }
+        0x96 => {
+            Opcode::CHK_I_V_I(parse_immediate(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?, 
+            parse_immediate(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?)
+        }
+        0x97 => {
+            Opcode::CHK_V_V_I(parse_bytecode_string(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?, 
+            parse_immediate(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?)
+        }
+        0x98 => {
+            Opcode::CHK_I_I_V(parse_immediate(bytes, index)?, 
+            parse_immediate(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?)
+        }
+        0x99 => {
+            Opcode::CHK_V_I_V(parse_bytecode_string(bytes, index)?, 
+            parse_immediate(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?)
+        }
+        0x9A => {
+            Opcode::CHK_I_V_V(parse_immediate(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?)
+        }
+        0x9B => {
+            Opcode::CHK_V_V_V(parse_bytecode_string(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?)
+        }
+
+        // wrapping arithmetic
+        0x9C => {
+            Opcode::WRAP_I_I_I(parse_immediate(bytes, index)?, 
+            parse_immediate(bytes, index)?, 
+            parse_immediate(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?)
+        }
+        0x9D => {
+            Opcode::WRAP_V_I_I(parse_bytecode_string(bytes, index)?, 
+            parse_immediate(bytes, index)?, 
+            parse_immediate(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?)
+        }
+        0x9E => {
+            Opcode::WRAP_I_V_I(parse_immediate(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?, 
+            parse_immediate(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?)
+        }
+        0x9F => {
+            Opcode::WRAP_V_V_I(parse_bytecode_string(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?, 
+            parse_immediate(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?)
+        }
+        0xA0 => {
+            Opcode::WRAP_I_I_V(parse_immediate(bytes, index)?, 
+            parse_immediate(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?)
+        }
+        0xA1 => {
+            Opcode::WRAP_V_I_V(parse_bytecode_string(bytes, index)?, 
+            parse_immediate(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?)
+        }
+        0xA2 => {
+            Opcode::WRAP_I_V_V(parse_immediate(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?)
+        }
+        0xA3 => {
+            Opcode::WRAP_V_V_V(parse_bytecode_string(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?)
+        }
+
+        // saturating arithmetic
+        0xA4 => {
+            Opcode::SAT_I_I_I(parse_immediate(bytes, index)?, 
+            parse_immediate(bytes, index)?, 
+            parse_immediate(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?)
+        }
+        0xA5 => {
+            Opcode::SAT_V_I_I(parse_bytecode_string(bytes, index)?, 
+            parse_immediate(bytes, index)?, 
+            parse_immediate(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?)
+        }
+        0xA6 => {
+            Opcode::SAT_I_V_I(parse_immediate(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?, 
+            parse_immediate(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?)
+        }
+        0xA7 => {
+            Opcode::SAT_V_V_I(parse_bytecode_string(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?, 
+            parse_immediate(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?)
+        }
+        0xA8 => {
+            Opcode::SAT_I_I_V(parse_immediate(bytes, index)?, 
+            parse_immediate(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?)
+        }
+        0xA9 => {
+            Opcode::SAT_V_I_V(parse_bytecode_string(bytes, index)?, 
+            parse_immediate(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?)
+        }
+        0xAA => {
+            Opcode::SAT_I_V_V(parse_immediate(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?)
+        }
+        0xAB => {
+            Opcode::SAT_V_V_V(parse_bytecode_string(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?)
+        }
+
+        // arithmetic with overflow flag
+        0xAC => {
+            Opcode::OVF_I_I_I(parse_immediate(bytes, index)?, 
+            parse_immediate(bytes, index)?, 
+            parse_immediate(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?)
+        }
+        0xAD => {
+            Opcode::OVF_V_I_I(parse_bytecode_string(bytes, index)?, 
+            parse_immediate(bytes, index)?, 
+            parse_immediate(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?)
+        }
+        0xAE => {
+            Opcode::OVF_I_V_I(parse_immediate(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?, 
+            parse_immediate(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?)
+        }
+        0xAF => {
+            Opcode::OVF_V_V_I(parse_bytecode_string(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?, 
+            parse_immediate(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?)
+        }
+        0xB0 => {
+            Opcode::OVF_I_I_V(parse_immediate(bytes, index)?, 
+            parse_immediate(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?)
+        }
+        0xB1 => {
+            Opcode::OVF_V_I_V(parse_bytecode_string(bytes, index)?, 
+            parse_immediate(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?)
+        }
+        0xB2 => {
+            Opcode::OVF_I_V_V(parse_immediate(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?)
+        }
+        0xB3 => {
+            Opcode::OVF_V_V_V(parse_bytecode_string(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?)
+        }
+
+        // cast instruction
+        0xB4 => {
+            Opcode::CAST_I_I_T(parse_immediate(bytes, index)?, 
+            parse_immediate(bytes, index)?, 
+            parse_type(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?)
+        }
+        0xB5 => {
+            Opcode::CAST_V_I_T(parse_bytecode_string(bytes, index)?, 
+            parse_immediate(bytes, index)?, 
+            parse_type(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?)
+        }
+        0xB6 => {
+            Opcode::CAST_I_V_T(parse_immediate(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?, 
+            parse_type(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?)
+        }
+        0xB7 => {
+            Opcode::CAST_V_V_T(parse_bytecode_string(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?, 
+            parse_type(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?)
+        }
+        0xB8 => {
+            Opcode::CAST_I_I_V(parse_immediate(bytes, index)?, 
+            parse_immediate(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?)
+        }
+        0xB9 => {
+            Opcode::CAST_V_I_V(parse_bytecode_string(bytes, index)?, 
+            parse_immediate(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?)
+        }
+        0xBA => {
+            Opcode::CAST_I_V_V(parse_immediate(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?)
+        }
+        0xBB => {
+            Opcode::CAST_V_V_V(parse_bytecode_string(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?, 
+            parse_bytecode_string(bytes, index)?)
+        }
+
+        // runtime library loading
+        0xBC => {
+            Opcode::LOADLIB_IMM(parse_immediate(bytes, index)?,
+            parse_bytecode_string(bytes, index)?)
+        }
+        0xBD => {
+            Opcode::LOADLIB_VAR(parse_bytecode_string(bytes, index)?,
+            parse_bytecode_string(bytes, index)?)
+        }
+        0xBE => {
+            Opcode::DLSYM_V_I(parse_bytecode_string(bytes, index)?,
+            parse_immediate(bytes, index)?,
+            parse_bytecode_string(bytes, index)?)
+        }
+        0xBF => {
+            Opcode::DLSYM_V_V(parse_bytecode_string(bytes, index)?,
+            parse_bytecode_string(bytes, index)?,
+            parse_bytecode_string(bytes, index)?)
+        }
+
         _ => return Err(format!("unknown instruction {:#04x} at {:#06x}", opcode_byte, start_index))
     };
 
@@ -1200,6 +1532,9 @@ pub fn parse_type(bytes: &[u8], index: &mut usize) -> Result<Type, String> {
         Types::STRUCT(_) => {
             new_typ = Types::STRUCT(parse_bytecode_string(bytes, index)?);
         }
+        Types::HANDLE(_) => {
+            new_typ = Types::HANDLE(parse_bytecode_string(bytes, index)?);
+        }
         _ => {}
     }
 
@@ -0,0 +1,255 @@
+use std::{cell::{Cell, RefCell}, collections::HashMap, fs::{self, File, OpenOptions, ReadDir}, io::{self, Read, Seek, SeekFrom, Write}, path::PathBuf, time::UNIX_EPOCH};
+
+use crate::{_type::Types, frame::Frame, intrinsics::{self, error_code, new_handle, pop_buffer, pop_handle, pop_text, pop_usize, push_num, push_std_string, push_std_struct, push_string}, scope::Scope, value::Values};
+
+// flags for `std.fs.open`
+const READ: usize       = 0x01;
+const WRITE: usize      = 0x02;
+const APPEND: usize     = 0x04;
+const CREATE: usize     = 0x08;
+const TRUNCATE: usize   = 0x10;
+const CREATE_NEW: usize = 0x20;
+
+#[allow(clippy::upper_case_acronyms)]
+enum Entry {
+    FILE(File),
+    DIR(ReadDir),
+}
+
+thread_local! {
+    static OPEN: RefCell<HashMap<usize, Entry>> = RefCell::new(HashMap::new());
+
+    // the error from the last `std.fs` function, 0 if it succeeded
+    static ERROR: Cell<i32> = const { Cell::new(0) };
+}
+
+fn status(result: io::Result<i64>) -> Values {
+    ERROR.with(|error| intrinsics::status(result, error))
+}
+
+fn opened<T>(result: io::Result<T>) -> Option<T> {
+    ERROR.with(|error| intrinsics::opened(result, error))
+}
+
+fn pop_path(stack: &mut [Frame], cur_frame: usize, global_frame: usize) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(&pop_text(stack, cur_frame, global_frame)).to_string())
+}
+
+fn with_file<T>(handle: usize, f: impl FnOnce(&mut File) -> T) -> T {
+    OPEN.with_borrow_mut(|open| {
+        match open.get_mut(&handle) {
+            Some(Entry::FILE(file)) => f(file),
+            _ => panic!("`handle(0x{:x})` is not a file opened with `std.fs.open`", handle),
+        }
+    })
+}
+
+fn push_handle(handle: Option<Entry>, stack: &mut [Frame], cur_frame: usize) {
+    let handle = match handle {
+        Some(entry) => {
+            let handle = new_handle();
+            OPEN.with_borrow_mut(|open| open.insert(handle, entry));
+            handle
+        }
+        None => 0,
+    };
+
+    push_num(Values::HANDLE(handle, "std.fs.close".to_string()), Types::HANDLE("std.fs.close".to_string()), stack, cur_frame);
+}
+
+// handle(std.fs.close) std.fs.open(u8* path, u64 length, u32 flags)
+// gives a null handle if the file couldn't be opened
+pub fn open(stack: &mut [Frame], cur_frame: usize, global_frame: usize, _scope: &Scope, _global_scope: &Scope) {
+    let flags = pop_usize(&mut stack[cur_frame], "flags");
+    let path = pop_path(stack, cur_frame, global_frame);
+
+    let file = OpenOptions::new()
+        .read(flags & READ != 0)
+        .write(flags & WRITE != 0)
+        .append(flags & APPEND != 0)
+        .create(flags & CREATE != 0)
+        .truncate(flags & TRUNCATE != 0)
+        .create_new(flags & CREATE_NEW != 0)
+        .open(path);
+
+    push_handle(opened(file).map(Entry::FILE), stack, cur_frame);
+}
+
+// i64 std.fs.read(handle file, u8* buf, u64 length)
+// gives the number of bytes read, 0 at the end of the file
+pub fn read(stack: &mut [Frame], cur_frame: usize, global_frame: usize, _scope: &Scope, _global_scope: &Scope) {
+    let len = pop_usize(&mut stack[cur_frame], "length");
+    let addr = pop_buffer(&mut stack[cur_frame]);
+    let handle = pop_handle(&mut stack[cur_frame]);
+
+    // whatever is read is gone from the file, so the buffer has to be checked first
+    stack[global_frame].heap.check_write(addr, len);
+
+    let mut bytes = vec![0u8; len];
+    let result = with_file(handle, |file| file.read(&mut bytes));
+
+    if let Ok(read) = result {
+        stack[global_frame].heap.write(addr, &bytes[..read]);
+    }
+
+    push_num(status(result.map(|n| n as i64)), Types::I64, stack, cur_frame);
+}
+
+// i64 std.fs.write(handle file, u8* buf, u64 length)
+// gives the number of bytes written, which is all of them unless it failed
+pub fn write(stack: &mut [Frame], cur_frame: usize, global_frame: usize, _scope: &Scope, _global_scope: &Scope) {
+    let bytes = pop_text(stack, cur_frame, global_frame);
+    let handle = pop_handle(&mut stack[cur_frame]);
+
+    let result = with_file(handle, |file| file.write_all(&bytes));
+
+    push_num(status(result.map(|_| bytes.len() as i64)), Types::I64, stack, cur_frame);
+}
+
+// i64 std.fs.seek(handle file, i64 offset, u32 from)
+// `from` is 0 for the start of the file, 1 for the current position and 2 for the end, gives the new position
+pub fn seek(stack: &mut [Frame], cur_frame: usize, _global_frame: usize, _scope: &Scope, _global_scope: &Scope) {
+    let from = pop_usize(&mut stack[cur_frame], "seek position");
+    let offset = stack[cur_frame].pop();
+    let handle = pop_handle(&mut stack[cur_frame]);
+
+    let offset = match offset.val {
+        Values::SIGNED(n) => n,
+        Values::UNSIGNED(n) => n as i64,
+        _ => panic!("expected a number to seek by, got `{}`", offset.val),
+    };
+
+    let pos = match from {
+        0 => SeekFrom::Start(offset as u64),
+        1 => SeekFrom::Current(offset),
+        2 => SeekFrom::End(offset),
+        _ => panic!("invalid seek position `{from}`, expected 0, 1 or 2"),
+    };
+
+    let result = with_file(handle, |file| file.seek(pos));
+
+    push_num(status(result.map(|n| n as i64)), Types::I64, stack, cur_frame);
+}
+
+// i64 std.fs.close(handle entry)
+// closes a file or directory, this is the destructor for their handles so `FREE` can be used instead
+pub fn close(stack: &mut [Frame], cur_frame: usize, _global_frame: usize, _scope: &Scope, _global_scope: &Scope) {
+    let handle = pop_handle(&mut stack[cur_frame]);
+
+    if OPEN.with_borrow_mut(|open| open.remove(&handle)).is_none() {
+        panic!("tried to close `handle(0x{:x})`, which isn't an open file or directory", handle);
+    }
+
+    push_num(status(Ok(0)), Types::I64, stack, cur_frame);
+}
+
+// struct std.string std.fs.read_file(u8* path, u64 length)
+// the text is null if the file couldn't be read
+pub fn read_file(stack: &mut [Frame], cur_frame: usize, global_frame: usize, scope: &Scope, global_scope: &Scope) {
+    let path = pop_path(stack, cur_frame, global_frame);
+
+    match opened(fs::read(path)) {
+        Some(bytes) => push_string(&bytes, "std.fs.read_file", stack, cur_frame, global_frame, scope, global_scope),
+        None => push_std_string(Values::POINTER(0, 0), 0, stack, cur_frame, scope, global_scope),
+    }
+}
+
+// i64 std.fs.write_file(u8* path, u64 length, u8* data, u64 data_length)
+// creates the file or replaces what was in it, gives the number of bytes written
+pub fn write_file(stack: &mut [Frame], cur_frame: usize, global_frame: usize, _scope: &Scope, _global_scope: &Scope) {
+    let bytes = pop_text(stack, cur_frame, global_frame);
+    let path = pop_path(stack, cur_frame, global_frame);
+
+    let result = fs::write(path, &bytes);
+
+    push_num(status(result.map(|_| bytes.len() as i64)), Types::I64, stack, cur_frame);
+}
+
+// struct std.fs.metadata std.fs.metadata(u8* path, u64 length)
+// `modified` is in seconds since the unix epoch, `error` is set if the metadata couldn't be read
+pub fn metadata(stack: &mut [Frame], cur_frame: usize, global_frame: usize, scope: &Scope, global_scope: &Scope) {
+    let path = pop_path(stack, cur_frame, global_frame);
+
+    let vals = match fs::metadata(path) {
+        Ok(metadata) => {
+            ERROR.set(0);
+
+            let modified = match metadata.modified().map(|time| time.duration_since(UNIX_EPOCH)) {
+                Ok(Ok(since)) => since.as_secs() as i64,
+                Ok(Err(before)) => -(before.duration().as_secs() as i64),
+                Err(_) => 0,
+            };
+
+            [Values::UNSIGNED(metadata.len()), Values::SIGNED(modified), Values::UNSIGNED(metadata.is_dir() as u64), Values::SIGNED(0)]
+        }
+        Err(error) => {
+            ERROR.set(error_code(&error));
+
+            [Values::UNSIGNED(0), Values::SIGNED(0), Values::UNSIGNED(0), Values::SIGNED(error_code(&error) as i64)]
+        }
+    };
+
+    push_std_struct("std.fs.metadata", &vals, stack, cur_frame, scope, global_scope);
+}
+
+// handle(std.fs.close) std.fs.open_dir(u8* path, u64 length)
+// gives a null handle if the directory couldn't be opened
+pub fn open_dir(stack: &mut [Frame], cur_frame: usize, global_frame: usize, _scope: &Scope, _global_scope: &Scope) {
+    let path = pop_path(stack, cur_frame, global_frame);
+
+    push_handle(opened(fs::read_dir(path)).map(Entry::DIR), stack, cur_frame);
+}
+
+// struct std.string std.fs.next_entry(handle dir)
+// gives the name of the next file or directory in it, the text is null once there are no more (or if it failed)
+pub fn next_entry(stack: &mut [Frame], cur_frame: usize, global_frame: usize, scope: &Scope, global_scope: &Scope) {
+    let handle = pop_handle(&mut stack[cur_frame]);
+
+    let next = OPEN.with_borrow_mut(|open| {
+        match open.get_mut(&handle) {
+            Some(Entry::DIR(dir)) => dir.next(),
+            _ => panic!("`handle(0x{:x})` is not a directory opened with `std.fs.open_dir`", handle),
+        }
+    });
+
+    // running out of entries isn't an error
+    match opened(next.transpose()) {
+        Some(Some(entry)) => push_string(entry.file_name().to_string_lossy().as_bytes(), "std.fs.next_entry", stack, cur_frame, global_frame, scope, global_scope),
+        _ => push_std_string(Values::POINTER(0, 0), 0, stack, cur_frame, scope, global_scope),
+    }
+}
+
+// i64 std.fs.create_dir(u8* path, u64 length)
+// creates any missing parent directories too
+pub fn create_dir(stack: &mut [Frame], cur_frame: usize, global_frame: usize, _scope: &Scope, _global_scope: &Scope) {
+    let path = pop_path(stack, cur_frame, global_frame);
+
+    push_num(status(fs::create_dir_all(path).map(|_| 0)), Types::I64, stack, cur_frame);
+}
+
+// i64 std.fs.remove(u8* path, u64 length)
+// removes a file or an empty directory
+pub fn remove(stack: &mut [Frame], cur_frame: usize, global_frame: usize, _scope: &Scope, _global_scope: &Scope) {
+    let path = pop_path(stack, cur_frame, global_frame);
+
+    let result = match fs::symlink_metadata(&path) {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir(path),
+        _ => fs::remove_file(path),
+    };
+
+    push_num(status(result.map(|_| 0)), Types::I64, stack, cur_frame);
+}
+
+// i64 std.fs.rename(u8* from, u64 from_length, u8* to, u64 to_length)
+pub fn rename(stack: &mut [Frame], cur_frame: usize, global_frame: usize, _scope: &Scope, _global_scope: &Scope) {
+    let to = pop_path(stack, cur_frame, global_frame);
+    let from = pop_path(stack, cur_frame, global_frame);
+
+    push_num(status(fs::rename(from, to).map(|_| 0)), Types::I64, stack, cur_frame);
+}
+
+// i32 std.fs.error()
+// the error code from the last `std.fs` function, 0 if it succeeded
+pub fn error(stack: &mut [Frame], cur_frame: usize, _global_frame: usize, _scope: &Scope, _global_scope: &Scope) {
+    push_num(Values::SIGNED(ERROR.get() as i64), Types::I32, stack, cur_frame);
+}
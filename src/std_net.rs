@@ -0,0 +1,312 @@
+use std::{cell::{Cell, RefCell}, collections::HashMap, io::{self, Read, Write}, net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs, UdpSocket}, thread, time::{Duration, Instant}};
+
+use crate::{_type::Types, frame::Frame, intrinsics::{self, alloc_string, new_handle, pop_buffer, pop_handle, pop_text, pop_usize, push_num, push_std_string, push_std_struct, push_string}, scope::Scope, value::Values};
+
+#[allow(clippy::upper_case_acronyms)]
+enum Socket {
+    // std has no timeout for accepting, so the listener keeps its own
+    LISTENER(TcpListener, Option<Duration>),
+    STREAM(TcpStream),
+    UDP(UdpSocket),
+}
+
+thread_local! {
+    static OPEN: RefCell<HashMap<usize, Socket>> = RefCell::new(HashMap::new());
+
+    // the error from the last `std.net` function, 0 if it succeeded
+    static ERROR: Cell<i32> = const { Cell::new(0) };
+}
+
+// timeouts show up as would block or timed out depending on the OS and the call, they're all reported as timed out
+fn timed_out<T>(result: io::Result<T>) -> io::Result<T> {
+    result.map_err(|error| match error.kind() {
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => io::Error::from(io::ErrorKind::TimedOut),
+        _ => error,
+    })
+}
+
+fn status(result: io::Result<i64>) -> Values {
+    ERROR.with(|error| intrinsics::status(timed_out(result), error))
+}
+
+fn opened<T>(result: io::Result<T>) -> Option<T> {
+    ERROR.with(|error| intrinsics::opened(timed_out(result), error))
+}
+
+fn pop_addr(stack: &mut [Frame], cur_frame: usize, global_frame: usize) -> String {
+    String::from_utf8_lossy(&pop_text(stack, cur_frame, global_frame)).to_string()
+}
+
+// host names are looked up, the first address found is used
+fn resolve_addr(addr: &str) -> io::Result<SocketAddr> {
+    addr.to_socket_addrs()?.next().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("`{addr}` has no addresses")))
+}
+
+fn with_socket<T>(handle: usize, f: impl FnOnce(&mut Socket) -> T) -> T {
+    OPEN.with_borrow_mut(|open| {
+        match open.get_mut(&handle) {
+            Some(socket) => f(socket),
+            None => panic!("`handle(0x{:x})` is not an open socket", handle),
+        }
+    })
+}
+
+fn with_stream<T>(handle: usize, f: impl FnOnce(&mut TcpStream) -> T) -> T {
+    with_socket(handle, |socket| {
+        match socket {
+            Socket::STREAM(stream) => f(stream),
+            _ => panic!("`handle(0x{:x})` is not a tcp connection", handle),
+        }
+    })
+}
+
+fn with_udp<T>(handle: usize, f: impl FnOnce(&mut UdpSocket) -> T) -> T {
+    with_socket(handle, |socket| {
+        match socket {
+            Socket::UDP(udp) => f(udp),
+            _ => panic!("`handle(0x{:x})` is not a udp socket", handle),
+        }
+    })
+}
+
+fn push_handle(handle: Option<Socket>, stack: &mut [Frame], cur_frame: usize) {
+    let handle = match handle {
+        Some(socket) => {
+            let handle = new_handle();
+            OPEN.with_borrow_mut(|open| open.insert(handle, socket));
+            handle
+        }
+        None => 0,
+    };
+
+    push_num(Values::HANDLE(handle, "std.net.close".to_string()), Types::HANDLE("std.net.close".to_string()), stack, cur_frame);
+}
+
+fn push_addr(addr: Option<SocketAddr>, site: &str, stack: &mut [Frame], cur_frame: usize, global_frame: usize, scope: &Scope, global_scope: &Scope) {
+    match addr {
+        Some(addr) => push_string(addr.to_string().as_bytes(), site, stack, cur_frame, global_frame, scope, global_scope),
+        None => push_std_string(Values::POINTER(0, 0), 0, stack, cur_frame, scope, global_scope),
+    }
+}
+
+fn accept_timeout(listener: &TcpListener, timeout: Option<Duration>) -> io::Result<TcpStream> {
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return listener.accept().map(|(stream, _)| stream),
+    };
+
+    listener.set_nonblocking(true)?;
+
+    let start = Instant::now();
+    let result = loop {
+        match listener.accept() {
+            Err(error) if error.kind() == io::ErrorKind::WouldBlock && start.elapsed() < timeout => thread::sleep(Duration::from_millis(1)),
+            result => break result,
+        }
+    };
+
+    listener.set_nonblocking(false)?;
+
+    // on some platforms the connection inherits non-blocking from the listener
+    let (stream, _) = result?;
+    stream.set_nonblocking(false)?;
+
+    Ok(stream)
+}
+
+// handle(std.net.close) std.net.listen(u8* addr, u64 length)
+// the address is text like `127.0.0.1:8080`, port 0 picks a free port which `std.net.local_addr` gives back
+pub fn listen(stack: &mut [Frame], cur_frame: usize, global_frame: usize, _scope: &Scope, _global_scope: &Scope) {
+    let addr = pop_addr(stack, cur_frame, global_frame);
+
+    let listener = opened(TcpListener::bind(addr)).map(|listener| Socket::LISTENER(listener, None));
+    push_handle(listener, stack, cur_frame);
+}
+
+// handle(std.net.close) std.net.accept(handle listener)
+// waits for the next connection, gives a null handle if it failed or the listener's timeout ran out
+pub fn accept(stack: &mut [Frame], cur_frame: usize, _global_frame: usize, _scope: &Scope, _global_scope: &Scope) {
+    let handle = pop_handle(&mut stack[cur_frame]);
+
+    let stream = with_socket(handle, |socket| {
+        match socket {
+            Socket::LISTENER(listener, timeout) => accept_timeout(listener, *timeout),
+            _ => panic!("`handle(0x{:x})` is not a listener opened with `std.net.listen`", handle),
+        }
+    });
+
+    push_handle(opened(stream).map(Socket::STREAM), stack, cur_frame);
+}
+
+// handle(std.net.close) std.net.connect(u8* addr, u64 length)
+pub fn connect(stack: &mut [Frame], cur_frame: usize, global_frame: usize, _scope: &Scope, _global_scope: &Scope) {
+    let addr = pop_addr(stack, cur_frame, global_frame);
+
+    push_handle(opened(TcpStream::connect(addr)).map(Socket::STREAM), stack, cur_frame);
+}
+
+// handle(std.net.close) std.net.connect_timeout(u8* addr, u64 length, u64 millis)
+pub fn connect_timeout(stack: &mut [Frame], cur_frame: usize, global_frame: usize, _scope: &Scope, _global_scope: &Scope) {
+    let millis = pop_usize(&mut stack[cur_frame], "timeout");
+    let addr = pop_addr(stack, cur_frame, global_frame);
+
+    let stream = resolve_addr(&addr).and_then(|addr| TcpStream::connect_timeout(&addr, Duration::from_millis(millis as u64)));
+    push_handle(opened(stream).map(Socket::STREAM), stack, cur_frame);
+}
+
+// i64 std.net.read(handle conn, u8* buf, u64 length)
+// gives the number of bytes read, 0 once the other side has closed the connection
+pub fn read(stack: &mut [Frame], cur_frame: usize, global_frame: usize, _scope: &Scope, _global_scope: &Scope) {
+    let len = pop_usize(&mut stack[cur_frame], "length");
+    let addr = pop_buffer(&mut stack[cur_frame]);
+    let handle = pop_handle(&mut stack[cur_frame]);
+
+    // data taken off a socket can't be put back, so a bad buffer has to be caught before reading
+    stack[global_frame].heap.check_write(addr, len);
+
+    let mut bytes = vec![0u8; len];
+    let result = with_stream(handle, |stream| stream.read(&mut bytes));
+
+    if let Ok(read) = result {
+        stack[global_frame].heap.write(addr, &bytes[..read]);
+    }
+
+    push_num(status(result.map(|n| n as i64)), Types::I64, stack, cur_frame);
+}
+
+// i64 std.net.write(handle conn, u8* buf, u64 length)
+// gives the number of bytes written, which is all of them unless it failed
+pub fn write(stack: &mut [Frame], cur_frame: usize, global_frame: usize, _scope: &Scope, _global_scope: &Scope) {
+    let bytes = pop_text(stack, cur_frame, global_frame);
+    let handle = pop_handle(&mut stack[cur_frame]);
+
+    let result = with_stream(handle, |stream| stream.write_all(&bytes));
+
+    push_num(status(result.map(|_| bytes.len() as i64)), Types::I64, stack, cur_frame);
+}
+
+// handle(std.net.close) std.net.udp_bind(u8* addr, u64 length)
+pub fn udp_bind(stack: &mut [Frame], cur_frame: usize, global_frame: usize, _scope: &Scope, _global_scope: &Scope) {
+    let addr = pop_addr(stack, cur_frame, global_frame);
+
+    push_handle(opened(UdpSocket::bind(addr)).map(Socket::UDP), stack, cur_frame);
+}
+
+// i64 std.net.send_to(handle udp, u8* buf, u64 length, u8* addr, u64 addr_length)
+// gives the number of bytes sent
+pub fn send_to(stack: &mut [Frame], cur_frame: usize, global_frame: usize, _scope: &Scope, _global_scope: &Scope) {
+    let addr = pop_addr(stack, cur_frame, global_frame);
+    let bytes = pop_text(stack, cur_frame, global_frame);
+    let handle = pop_handle(&mut stack[cur_frame]);
+
+    let result = resolve_addr(&addr).and_then(|addr| with_udp(handle, |udp| udp.send_to(&bytes, addr)));
+
+    push_num(status(result.map(|n| n as i64)), Types::I64, stack, cur_frame);
+}
+
+// struct std.net.packet std.net.recv_from(handle udp, u8* buf, u64 length)
+// waits for a packet and reads up to length bytes of it into buf, the rest of the packet is dropped
+pub fn recv_from(stack: &mut [Frame], cur_frame: usize, global_frame: usize, scope: &Scope, global_scope: &Scope) {
+    let len = pop_usize(&mut stack[cur_frame], "length");
+    let addr = pop_buffer(&mut stack[cur_frame]);
+    let handle = pop_handle(&mut stack[cur_frame]);
+
+    stack[global_frame].heap.check_write(addr, len);
+
+    let mut bytes = vec![0u8; len];
+    let result = with_udp(handle, |udp| udp.recv_from(&mut bytes));
+
+    if let Ok((read, _)) = result {
+        stack[global_frame].heap.write(addr, &bytes[..read]);
+    }
+
+    // the sender's address is text like everywhere else, null if nothing was received
+    let (from, from_len) = match &result {
+        Ok((_, from)) => {
+            let from = from.to_string();
+            let addr = alloc_string(from.as_bytes(), "std.net.recv_from", stack, global_frame);
+            (Values::POINTER(addr, addr), from.len())
+        }
+        Err(_) => (Values::POINTER(0, 0), 0),
+    };
+
+    let length = status(result.map(|(n, _)| n as i64));
+    push_std_struct("std.net.packet", &[length, from, Values::UNSIGNED(from_len as u64)], stack, cur_frame, scope, global_scope);
+}
+
+// i64 std.net.set_timeout(handle socket, u64 millis)
+// how long reading, writing and accepting wait before failing, 0 waits forever
+pub fn set_timeout(stack: &mut [Frame], cur_frame: usize, _global_frame: usize, _scope: &Scope, _global_scope: &Scope) {
+    let millis = pop_usize(&mut stack[cur_frame], "timeout");
+    let handle = pop_handle(&mut stack[cur_frame]);
+
+    let timeout = match millis {
+        0 => None,
+        _ => Some(Duration::from_millis(millis as u64)),
+    };
+
+    let result = with_socket(handle, |socket| {
+        match socket {
+            Socket::LISTENER(_, listener_timeout) => {
+                *listener_timeout = timeout;
+                Ok(())
+            }
+            Socket::STREAM(stream) => stream.set_read_timeout(timeout).and_then(|_| stream.set_write_timeout(timeout)),
+            Socket::UDP(udp) => udp.set_read_timeout(timeout).and_then(|_| udp.set_write_timeout(timeout)),
+        }
+    });
+
+    push_num(status(result.map(|_| 0)), Types::I64, stack, cur_frame);
+}
+
+// struct std.string std.net.local_addr(handle socket)
+// the address the socket is bound to
+pub fn local_addr(stack: &mut [Frame], cur_frame: usize, global_frame: usize, scope: &Scope, global_scope: &Scope) {
+    let handle = pop_handle(&mut stack[cur_frame]);
+
+    let addr = with_socket(handle, |socket| {
+        match socket {
+            Socket::LISTENER(listener, _) => listener.local_addr(),
+            Socket::STREAM(stream) => stream.local_addr(),
+            Socket::UDP(udp) => udp.local_addr(),
+        }
+    });
+
+    push_addr(opened(addr), "std.net.local_addr", stack, cur_frame, global_frame, scope, global_scope);
+}
+
+// struct std.string std.net.peer_addr(handle conn)
+// the address of the other side of a connection
+pub fn peer_addr(stack: &mut [Frame], cur_frame: usize, global_frame: usize, scope: &Scope, global_scope: &Scope) {
+    let handle = pop_handle(&mut stack[cur_frame]);
+
+    let addr = with_stream(handle, |stream| stream.peer_addr());
+
+    push_addr(opened(addr), "std.net.peer_addr", stack, cur_frame, global_frame, scope, global_scope);
+}
+
+// struct std.string std.net.resolve(u8* addr, u64 length)
+// parses an address or looks up a host name, `localhost:80` gives `127.0.0.1:80` (or `[::1]:80`)
+pub fn resolve(stack: &mut [Frame], cur_frame: usize, global_frame: usize, scope: &Scope, global_scope: &Scope) {
+    let addr = pop_addr(stack, cur_frame, global_frame);
+
+    push_addr(opened(resolve_addr(&addr)), "std.net.resolve", stack, cur_frame, global_frame, scope, global_scope);
+}
+
+// i64 std.net.close(handle socket)
+// this is the destructor for socket handles so `FREE` can be used instead
+pub fn close(stack: &mut [Frame], cur_frame: usize, _global_frame: usize, _scope: &Scope, _global_scope: &Scope) {
+    let handle = pop_handle(&mut stack[cur_frame]);
+
+    if OPEN.with_borrow_mut(|open| open.remove(&handle)).is_none() {
+        panic!("tried to close `handle(0x{:x})`, which isn't an open socket", handle);
+    }
+
+    push_num(status(Ok(0)), Types::I64, stack, cur_frame);
+}
+
+// i32 std.net.error()
+// the error code from the last `std.net` function, 0 if it succeeded
+pub fn error(stack: &mut [Frame], cur_frame: usize, _global_frame: usize, _scope: &Scope, _global_scope: &Scope) {
+    push_num(Values::SIGNED(ERROR.get() as i64), Types::I32, stack, cur_frame);
+}
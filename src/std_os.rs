@@ -0,0 +1,295 @@
+use std::{cell::{Cell, RefCell}, collections::HashMap, env, io::{self, Read, Write}, process::{self, Child, Command, Stdio}};
+
+use crate::{_type::{Type, Types}, frame::Frame, intrinsics::{self, new_handle, pop_buffer, pop_handle, pop_text, pop_usize, push_num, push_std_string, push_std_struct, push_string}, scope::Scope, value::Values};
+
+// flags for `std.os.spawn`, a stream that isn't piped is shared with the runtime
+const PIPE_STDIN: usize  = 0x01;
+const PIPE_STDOUT: usize = 0x02;
+const PIPE_STDERR: usize = 0x04;
+
+thread_local! {
+    // the program's arguments, starting with the program file
+    static ARGS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+
+    static CHILDREN: RefCell<HashMap<usize, Child>> = RefCell::new(HashMap::new());
+
+    // the error from the last `std.os` function, 0 if it succeeded
+    static ERROR: Cell<i32> = const { Cell::new(0) };
+}
+
+pub fn set_args(args: Vec<String>) {
+    ARGS.set(args);
+}
+
+fn status(result: io::Result<i64>) -> Values {
+    ERROR.with(|error| intrinsics::status(result, error))
+}
+
+fn opened<T>(result: io::Result<T>) -> Option<T> {
+    ERROR.with(|error| intrinsics::opened(result, error))
+}
+
+fn pop_string(stack: &mut [Frame], cur_frame: usize, global_frame: usize) -> String {
+    String::from_utf8_lossy(&pop_text(stack, cur_frame, global_frame)).to_string()
+}
+
+fn with_child<T>(handle: usize, f: impl FnOnce(&mut Child) -> T) -> T {
+    CHILDREN.with_borrow_mut(|children| {
+        match children.get_mut(&handle) {
+            Some(child) => f(child),
+            None => panic!("`handle(0x{:x})` is not a process started with `std.os.spawn`", handle),
+        }
+    })
+}
+
+fn not_piped(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, format!("the process's {what} isn't piped"))
+}
+
+fn push_text(text: Option<String>, site: &str, stack: &mut [Frame], cur_frame: usize, global_frame: usize, scope: &Scope, global_scope: &Scope) {
+    match text {
+        Some(text) => push_string(text.as_bytes(), site, stack, cur_frame, global_frame, scope, global_scope),
+        None => push_std_string(Values::POINTER(0, 0), 0, stack, cur_frame, scope, global_scope),
+    }
+}
+
+// u64 std.os.arg_count()
+pub fn arg_count(stack: &mut [Frame], cur_frame: usize, _global_frame: usize, _scope: &Scope, _global_scope: &Scope) {
+    let count = ARGS.with_borrow(|args| args.len());
+    push_num(Values::UNSIGNED(count as u64), Types::U64, stack, cur_frame);
+}
+
+// struct std.string std.os.arg(u64 index)
+// argument 0 is the program file, the text is null past the last argument
+pub fn arg(stack: &mut [Frame], cur_frame: usize, global_frame: usize, scope: &Scope, global_scope: &Scope) {
+    let index = pop_usize(&mut stack[cur_frame], "argument index");
+
+    let arg = ARGS.with_borrow(|args| args.get(index).cloned());
+    push_text(arg, "std.os.arg", stack, cur_frame, global_frame, scope, global_scope);
+}
+
+// struct std.string std.os.env(u8* name, u64 length)
+// the text is null if the variable isn't set
+pub fn env(stack: &mut [Frame], cur_frame: usize, global_frame: usize, scope: &Scope, global_scope: &Scope) {
+    let name = pop_string(stack, cur_frame, global_frame);
+
+    let val = env::var_os(name).map(|val| val.to_string_lossy().to_string());
+    push_text(val, "std.os.env", stack, cur_frame, global_frame, scope, global_scope);
+}
+
+// void std.os.set_env(u8* name, u64 length, u8* value, u64 value_length)
+pub fn set_env(stack: &mut [Frame], cur_frame: usize, global_frame: usize, _scope: &Scope, _global_scope: &Scope) {
+    let val = pop_string(stack, cur_frame, global_frame);
+    let name = pop_string(stack, cur_frame, global_frame);
+
+    if name.is_empty() || name.contains(['=', '\0']) || val.contains('\0') {
+        panic!("invalid environment variable `{name}={val}`");
+    }
+
+    env::set_var(name, val);
+}
+
+// void std.os.unset_env(u8* name, u64 length)
+pub fn unset_env(stack: &mut [Frame], cur_frame: usize, global_frame: usize, _scope: &Scope, _global_scope: &Scope) {
+    let name = pop_string(stack, cur_frame, global_frame);
+
+    if name.is_empty() || name.contains(['=', '\0']) {
+        panic!("invalid environment variable name `{name}`");
+    }
+
+    env::remove_var(name);
+}
+
+// void std.os.exit(i32 code)
+// exits straight away, from any function
+pub fn exit(stack: &mut [Frame], cur_frame: usize, _global_frame: usize, _scope: &Scope, _global_scope: &Scope) {
+    let code = stack[cur_frame].pop();
+
+    let code = match code.val {
+        Values::SIGNED(n) => n as i32,
+        Values::UNSIGNED(n) => n as i32,
+        _ => panic!("expected an exit code, got `{}`", code.val),
+    };
+
+    let _ = io::stdout().flush();
+    process::exit(code);
+}
+
+// handle(std.os.close) std.os.spawn(u8* program, u64 length, u8** args, u64 count, u32 pipes)
+// the arguments are C strings, the program is looked up in PATH like a shell would, gives a null handle if it couldn't be started
+pub fn spawn(stack: &mut [Frame], cur_frame: usize, global_frame: usize, _scope: &Scope, _global_scope: &Scope) {
+    let pipes = pop_usize(&mut stack[cur_frame], "pipe flags");
+    let count = pop_usize(&mut stack[cur_frame], "argument count");
+    let args = stack[cur_frame].pop();
+    let program = pop_string(stack, cur_frame, global_frame);
+
+    let args = match args.val {
+        Values::POINTER(_, _) if count == 0 => Vec::new(),
+        Values::POINTER(addr, _) => {
+            let heap = &stack[global_frame].heap;
+            let ptr = Type { typ: vec![Types::POINTER, Types::U8] };
+
+            (0..count).map(|i| {
+                match heap.load(addr + i * ptr.get_size(), &ptr) {
+                    Values::POINTER(arg, _) => String::from_utf8_lossy(&heap.read_c_str(arg)).to_string(),
+                    _ => unreachable!(),
+                }
+            }).collect::<Vec<_>>()
+        }
+        _ => panic!("expected a pointer to the arguments, got `{}`", args.val),
+    };
+
+    let pipe = |flag: usize| if pipes & flag != 0 { Stdio::piped() } else { Stdio::inherit() };
+
+    // anything the program printed has to come out before anything the process prints
+    let _ = io::stdout().flush();
+
+    let child = Command::new(program)
+        .args(args)
+        .stdin(pipe(PIPE_STDIN))
+        .stdout(pipe(PIPE_STDOUT))
+        .stderr(pipe(PIPE_STDERR))
+        .spawn();
+
+    let handle = match opened(child) {
+        Some(child) => {
+            let handle = new_handle();
+            CHILDREN.with_borrow_mut(|children| children.insert(handle, child));
+            handle
+        }
+        None => 0,
+    };
+
+    push_num(Values::HANDLE(handle, "std.os.close".to_string()), Types::HANDLE("std.os.close".to_string()), stack, cur_frame);
+}
+
+// i64 std.os.write_stdin(handle process, u8* buf, u64 length)
+// gives the number of bytes written, which is all of them unless it failed
+pub fn write_stdin(stack: &mut [Frame], cur_frame: usize, global_frame: usize, _scope: &Scope, _global_scope: &Scope) {
+    let bytes = pop_text(stack, cur_frame, global_frame);
+    let handle = pop_handle(&mut stack[cur_frame]);
+
+    let result = with_child(handle, |child| {
+        match &mut child.stdin {
+            Some(stdin) => stdin.write_all(&bytes),
+            None => Err(not_piped("stdin")),
+        }
+    });
+
+    push_num(status(result.map(|_| bytes.len() as i64)), Types::I64, stack, cur_frame);
+}
+
+// i64 std.os.close_stdin(handle process)
+// lets the process see the end of its input
+pub fn close_stdin(stack: &mut [Frame], cur_frame: usize, _global_frame: usize, _scope: &Scope, _global_scope: &Scope) {
+    let handle = pop_handle(&mut stack[cur_frame]);
+
+    let result = with_child(handle, |child| {
+        match child.stdin.take() {
+            Some(_) => Ok(0),
+            None => Err(not_piped("stdin")),
+        }
+    });
+
+    push_num(status(result), Types::I64, stack, cur_frame);
+}
+
+fn read_pipe(stack: &mut [Frame], cur_frame: usize, global_frame: usize, stderr: bool) {
+    let len = pop_usize(&mut stack[cur_frame], "length");
+    let addr = pop_buffer(&mut stack[cur_frame]);
+    let handle = pop_handle(&mut stack[cur_frame]);
+
+    // the process's output is only there to be read once
+    stack[global_frame].heap.check_write(addr, len);
+
+    let mut bytes = vec![0u8; len];
+    let result = with_child(handle, |child| {
+        match (stderr, &mut child.stdout, &mut child.stderr) {
+            (false, Some(stdout), _) => stdout.read(&mut bytes),
+            (true, _, Some(stderr)) => stderr.read(&mut bytes),
+            (false, None, _) => Err(not_piped("stdout")),
+            (true, _, None) => Err(not_piped("stderr")),
+        }
+    });
+
+    if let Ok(read) = result {
+        stack[global_frame].heap.write(addr, &bytes[..read]);
+    }
+
+    push_num(status(result.map(|n| n as i64)), Types::I64, stack, cur_frame);
+}
+
+// i64 std.os.read_stdout(handle process, u8* buf, u64 length)
+// gives the number of bytes read, 0 once the process has closed its stdout
+pub fn read_stdout(stack: &mut [Frame], cur_frame: usize, global_frame: usize, _scope: &Scope, _global_scope: &Scope) {
+    read_pipe(stack, cur_frame, global_frame, false);
+}
+
+// i64 std.os.read_stderr(handle process, u8* buf, u64 length)
+pub fn read_stderr(stack: &mut [Frame], cur_frame: usize, global_frame: usize, _scope: &Scope, _global_scope: &Scope) {
+    read_pipe(stack, cur_frame, global_frame, true);
+}
+
+// struct std.os.status std.os.wait(handle process)
+// waits for the process to exit, closing its stdin first so it isn't left waiting for input
+pub fn wait(stack: &mut [Frame], cur_frame: usize, _global_frame: usize, scope: &Scope, global_scope: &Scope) {
+    let handle = pop_handle(&mut stack[cur_frame]);
+
+    let result = with_child(handle, |child| child.wait());
+
+    let vals = match opened(result) {
+        Some(exit) => {
+            #[cfg(unix)]
+            let signal = std::os::unix::process::ExitStatusExt::signal(&exit).unwrap_or(0);
+            #[cfg(not(unix))]
+            let signal = 0;
+
+            [Values::SIGNED(exit.code().unwrap_or(0) as i64), Values::SIGNED(signal as i64), Values::SIGNED(0)]
+        }
+        None => [Values::SIGNED(0), Values::SIGNED(0), Values::SIGNED(ERROR.get() as i64)],
+    };
+
+    push_std_struct("std.os.status", &vals, stack, cur_frame, scope, global_scope);
+}
+
+// i64 std.os.kill(handle process)
+// killing a process that has already exited isn't an error
+pub fn kill(stack: &mut [Frame], cur_frame: usize, _global_frame: usize, _scope: &Scope, _global_scope: &Scope) {
+    let handle = pop_handle(&mut stack[cur_frame]);
+
+    let result = with_child(handle, |child| child.kill());
+
+    push_num(status(result.map(|_| 0)), Types::I64, stack, cur_frame);
+}
+
+// i64 std.os.close(handle process)
+// closes the pipes to a process without waiting for it, this is the destructor for process handles so `FREE` can be used instead
+pub fn close(stack: &mut [Frame], cur_frame: usize, _global_frame: usize, _scope: &Scope, _global_scope: &Scope) {
+    let handle = pop_handle(&mut stack[cur_frame]);
+
+    if CHILDREN.with_borrow_mut(|children| children.remove(&handle)).is_none() {
+        panic!("tried to close `handle(0x{:x})`, which isn't a process started with `std.os.spawn`", handle);
+    }
+
+    push_num(status(Ok(0)), Types::I64, stack, cur_frame);
+}
+
+// struct std.string std.os.cwd()
+// the text is null if the current directory couldn't be read
+pub fn cwd(stack: &mut [Frame], cur_frame: usize, global_frame: usize, scope: &Scope, global_scope: &Scope) {
+    let dir = opened(env::current_dir()).map(|dir| dir.to_string_lossy().to_string());
+    push_text(dir, "std.os.cwd", stack, cur_frame, global_frame, scope, global_scope);
+}
+
+// i64 std.os.set_cwd(u8* path, u64 length)
+pub fn set_cwd(stack: &mut [Frame], cur_frame: usize, global_frame: usize, _scope: &Scope, _global_scope: &Scope) {
+    let path = pop_string(stack, cur_frame, global_frame);
+
+    push_num(status(env::set_current_dir(path).map(|_| 0)), Types::I64, stack, cur_frame);
+}
+
+// i32 std.os.error()
+// the error code from the last `std.os` function, 0 if it succeeded
+pub fn error(stack: &mut [Frame], cur_frame: usize, _global_frame: usize, _scope: &Scope, _global_scope: &Scope) {
+    push_num(Values::SIGNED(ERROR.get() as i64), Types::I32, stack, cur_frame);
+}
@@ -0,0 +1,140 @@
+use std::{thread, time::{Duration, Instant, SystemTime, UNIX_EPOCH}};
+
+use crate::{_type::Types, frame::Frame, intrinsics::{pop_usize, push_num, push_std_struct}, scope::Scope, value::Values};
+
+thread_local! {
+    // the monotonic clock counts from when it was first read
+    static START: Instant = Instant::now();
+}
+
+// nanoseconds since the unix epoch, negative before it
+fn now_nanos() -> i128 {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_nanos() as i128,
+        Err(before) => -(before.duration().as_nanos() as i128),
+    }
+}
+
+// the year, month and day of a number of days since the unix epoch, in the proleptic gregorian calendar
+// from http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: i64) -> (i64, u64, u64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+
+    (year, month as u64, day as u64)
+}
+
+fn is_leap_year(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+// u64 std.time.monotonic()
+// nanoseconds from a fixed point, it never goes backwards so it's the one to use for timeouts and benchmarks
+pub fn monotonic(stack: &mut [Frame], cur_frame: usize, _global_frame: usize, _scope: &Scope, _global_scope: &Scope) {
+    let nanos = START.with(|start| start.elapsed().as_nanos());
+    push_num(Values::UNSIGNED(nanos as u64), Types::U64, stack, cur_frame);
+}
+
+// i64 std.time.unix()
+// seconds since the unix epoch
+pub fn unix(stack: &mut [Frame], cur_frame: usize, _global_frame: usize, _scope: &Scope, _global_scope: &Scope) {
+    push_num(Values::SIGNED(now_nanos().div_euclid(1_000_000_000) as i64), Types::I64, stack, cur_frame);
+}
+
+// i64 std.time.unix_nanos()
+// nanoseconds since the unix epoch
+pub fn unix_nanos(stack: &mut [Frame], cur_frame: usize, _global_frame: usize, _scope: &Scope, _global_scope: &Scope) {
+    push_num(Values::SIGNED(now_nanos() as i64), Types::I64, stack, cur_frame);
+}
+
+// the fields of a std.time.datetime for a time in seconds since the unix epoch
+fn datetime(secs: i64) -> [Values; 8] {
+    let days = secs.div_euclid(86400);
+    let time = secs.rem_euclid(86400) as u64;
+
+    let (year, month, day) = civil_from_days(days);
+
+    // the unix epoch was a thursday
+    let weekday = (days + 4).rem_euclid(7) as u64;
+
+    const DAYS_BEFORE_MONTH: [u64; 12] = [0, 31, 59, 90, 120, 151, 181, 212, 243, 273, 304, 334];
+    let yearday = DAYS_BEFORE_MONTH[month as usize - 1] + day + (month > 2 && is_leap_year(year)) as u64;
+
+    [
+        Values::SIGNED(year),
+        Values::UNSIGNED(month),
+        Values::UNSIGNED(day),
+        Values::UNSIGNED(time / 3600),
+        Values::UNSIGNED(time / 60 % 60),
+        Values::UNSIGNED(time % 60),
+        Values::UNSIGNED(weekday),
+        Values::UNSIGNED(yearday),
+    ]
+}
+
+// struct std.time.datetime std.time.utc(i64 seconds)
+// breaks a time in seconds since the unix epoch down into its date and time in UTC
+pub fn utc(stack: &mut [Frame], cur_frame: usize, _global_frame: usize, scope: &Scope, global_scope: &Scope) {
+    let secs = stack[cur_frame].pop();
+
+    let secs = match secs.val {
+        Values::SIGNED(n) => n,
+        Values::UNSIGNED(n) => n as i64,
+        _ => panic!("expected a number of seconds, got `{}`", secs.val),
+    };
+
+    push_std_struct("std.time.datetime", &datetime(secs), stack, cur_frame, scope, global_scope);
+}
+
+// void std.time.sleep(u64 millis)
+pub fn sleep(stack: &mut [Frame], cur_frame: usize, _global_frame: usize, _scope: &Scope, _global_scope: &Scope) {
+    let millis = pop_usize(&mut stack[cur_frame], "number of milliseconds");
+
+    thread::sleep(Duration::from_millis(millis as u64));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(secs: i64) -> Vec<i64> {
+        datetime(secs).iter().map(|val| match val {
+            Values::SIGNED(n) => *n,
+            Values::UNSIGNED(n) => *n as i64,
+            _ => unreachable!(),
+        }).collect()
+    }
+
+    #[test]
+    fn days_to_dates() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+        assert_eq!(civil_from_days(11016), (2000, 2, 29));
+        assert_eq!(civil_from_days(19723), (2024, 1, 1));
+        assert_eq!(civil_from_days(-719468), (0, 3, 1));
+    }
+
+    #[test]
+    fn leap_years() {
+        assert!(is_leap_year(2000));
+        assert!(is_leap_year(2024));
+        assert!(!is_leap_year(1900));
+        assert!(!is_leap_year(2023));
+    }
+
+    #[test]
+    fn utc_fields() {
+        // year, month, day, hour, minute, second, weekday, day of the year
+        assert_eq!(fields(0), [1970, 1, 1, 0, 0, 0, 4, 1]);
+        assert_eq!(fields(-1), [1969, 12, 31, 23, 59, 59, 3, 365]);
+        assert_eq!(fields(951827696), [2000, 2, 29, 12, 34, 56, 2, 60]);
+        assert_eq!(fields(1735689599), [2024, 12, 31, 23, 59, 59, 2, 366]);
+    }
+}
@@ -1,7 +1,20 @@
 use core::fmt;
-use std::cmp::{self, Ordering};
+use std::{cmp::{self, Ordering}, sync::atomic::{AtomicBool, Ordering as AtomicOrdering}};
 
-use crate::_type::Type;
+use half::f16;
+
+use crate::_type::{Type, Types};
+
+// when set, stores that don't fit in the destination type and arithmetic overflow trap instead of wrapping
+static CHECKED: AtomicBool = AtomicBool::new(false);
+
+pub fn set_checked(checked: bool) {
+    CHECKED.store(checked, AtomicOrdering::Relaxed);
+}
+
+pub fn is_checked() -> bool {
+    return CHECKED.load(AtomicOrdering::Relaxed);
+}
 
 // TODO: enum type
 #[derive(Debug, Clone)]
@@ -11,10 +24,12 @@ pub enum Values {
     SIGNED(i64),
     UNSIGNED(u64),
     DECIMAL(f64),
-    POINTER(usize, usize), // index and size
+    POINTER(usize, usize), // address and the address of the allocation it was made from (0 if unknown)
+    REFERENCE(usize, usize, usize), // frame index, generation of the slot and slot
     STRUCT(String, String, usize), // module, name, index
     TYPE(Type),
     NAME(String),
+    HANDLE(usize, String), // native address and the destructor to call when it is freed
 }
 
 impl fmt::Display for Values {
@@ -27,6 +42,7 @@ impl fmt::Display for Values {
             Values::UNSIGNED(n) => res += &n.to_string(),
             Values::DECIMAL(n) => res += &n.to_string(),
             Values::POINTER(n, _) => res += &("*".to_string() + &format!("0x{:x}", n)),
+            Values::REFERENCE(frame, _, slot) => res += &format!("&frame({frame})[{slot}]"),
             Values::STRUCT(module, name, _) => {
                 if module.len() > 0 {
                     res += &format!("struct({module}.{name})")
@@ -40,12 +56,22 @@ impl fmt::Display for Values {
                 res += &n;
                 res += "\"";
             }
+            Values::HANDLE(n, _) => res += &format!("handle(0x{:x})", n),
         }
 
         f.write_str(&res)
     }
 }
 
+// overflow behaviour of the CHK, WRAP, SAT and OVF instructions
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Overflow {
+    CHECKED,
+    WRAPPING,
+    SATURATING,
+    FLAG,
+}
+
 #[derive(Debug, Clone)]
 pub struct Value {
     pub typ: Type,
@@ -63,31 +89,60 @@ impl fmt::Display for Value {
 }
 
 impl Value {
-    pub fn set(&mut self, other: &Values) { // yeah it's just a wrapper what about it
+    pub fn set(&mut self, other: &Values) {
+        if is_checked() && !other.fits(&self.typ) {
+            panic!("integer overflow: value `{}` does not fit in type `{}`", other, self.typ);
+        }
+
+        // an infinity or NaN has no bits to wrap, so it is clamped to the type instead
+        if let Values::DECIMAL(n) = other {
+            if !n.is_finite() && self.typ.typ.len() == 1 && self.typ.typ[0].get_int_info().is_some() {
+                self.val = other.cast(&Type { typ: vec![Types::F64] }, &self.typ, 0x02);
+                return;
+            }
+        }
+
         self.val.set(other);
+        self.val.fit(&self.typ);
     }
 }
 
+// integer arithmetic wraps at 64 bits, in checked mode it is done in i128 and traps if the result doesn't fit
+macro_rules! int_op {
+    ($a:expr, $b:expr, $t:ty, $wrapping:ident, $checked:ident, $op_name:expr) => {
+        if is_checked() {
+            ($a as i128).$checked($b as i128).and_then(|n| <$t>::try_from(n).ok())
+                .unwrap_or_else(|| panic!("integer overflow: cannot {} `{}` and `{}`", $op_name, $a, $b))
+        } else {
+            ($a as $t).$wrapping($b as $t)
+        }
+    };
+}
+
 macro_rules! math {
-    ($self:expr, $other:expr, $op:tt, $op_name:expr, $op_plural:expr) => {
+    ($self:expr, $other:expr, $op:tt, $wrapping:ident, $checked:ident, $op_name:expr, $op_plural:expr) => {
         return match($self, $other) {
             (Values::VOID, _) => Values::VOID,
             (Values::SIGNED(s), Values::VOID) => Values::SIGNED(*s),
-            (Values::SIGNED(s), Values::SIGNED(v)) => Values::SIGNED(*s $op *v),
-            (Values::SIGNED(s), Values::UNSIGNED(v)) => Values::SIGNED(*s $op *v as i64),
-            (Values::SIGNED(s), Values::DECIMAL(v)) => Values::SIGNED(*s $op *v as i64),
-            (Values::SIGNED(s), Values::POINTER(v, _)) => Values::SIGNED(*s $op *v as i64),
+            (Values::SIGNED(s), Values::SIGNED(v)) => Values::SIGNED(int_op!(*s, *v, i64, $wrapping, $checked, $op_name)),
+            (Values::SIGNED(s), Values::UNSIGNED(v)) => Values::SIGNED(int_op!(*s, *v, i64, $wrapping, $checked, $op_name)),
+            (Values::SIGNED(s), Values::DECIMAL(v)) => Values::SIGNED(int_op!(*s, *v, i64, $wrapping, $checked, $op_name)),
+            (Values::SIGNED(s), Values::POINTER(v, _)) => Values::SIGNED(int_op!(*s, *v, i64, $wrapping, $checked, $op_name)),
             (Values::SIGNED(_), Values::STRUCT(_, _, _)) => panic!("type mismatch: cannot {} values of type `struct` and `number`", $op_name),
             (Values::SIGNED(_), Values::TYPE(_)) => panic!("type mismatch: cannot {} values of type `type` and `number`", $op_name),
             (Values::SIGNED(_), Values::NAME(_)) => panic!("type mismatch: cannot {} values of type `name` and `number`", $op_name),
+            (Values::SIGNED(_), Values::REFERENCE(_, _, _)) => panic!("type mismatch: cannot {} values of type `reference` and `number`", $op_name),
+            (Values::SIGNED(_), Values::HANDLE(_, _)) => panic!("type mismatch: cannot {} values of type `handle` and `number`", $op_name),
             (Values::UNSIGNED(s), Values::VOID) => Values::UNSIGNED(*s),
-            (Values::UNSIGNED(s), Values::SIGNED(v)) => Values::UNSIGNED(*s $op *v as u64),
-            (Values::UNSIGNED(s), Values::UNSIGNED(v)) => Values::UNSIGNED(*s $op *v),
-            (Values::UNSIGNED(s), Values::DECIMAL(v)) => Values::UNSIGNED(*s $op *v as u64),
-            (Values::UNSIGNED(s), Values::POINTER(v, _)) => Values::UNSIGNED(*s $op *v as u64),
+            (Values::UNSIGNED(s), Values::SIGNED(v)) => Values::UNSIGNED(int_op!(*s, *v, u64, $wrapping, $checked, $op_name)),
+            (Values::UNSIGNED(s), Values::UNSIGNED(v)) => Values::UNSIGNED(int_op!(*s, *v, u64, $wrapping, $checked, $op_name)),
+            (Values::UNSIGNED(s), Values::DECIMAL(v)) => Values::UNSIGNED(int_op!(*s, *v, u64, $wrapping, $checked, $op_name)),
+            (Values::UNSIGNED(s), Values::POINTER(v, _)) => Values::UNSIGNED(int_op!(*s, *v, u64, $wrapping, $checked, $op_name)),
             (Values::UNSIGNED(_), Values::STRUCT(_, _, _)) => panic!("type mismatch: cannot {} values of type `struct` and `number`", $op_name),
             (Values::UNSIGNED(_), Values::TYPE(_)) => panic!("type mismatch: cannot {} values of type `type` and `number`", $op_name),
             (Values::UNSIGNED(_), Values::NAME(_)) => panic!("type mismatch: cannot {} values of type `name` and `number`", $op_name),
+            (Values::UNSIGNED(_), Values::REFERENCE(_, _, _)) => panic!("type mismatch: cannot {} values of type `reference` and `number`", $op_name),
+            (Values::UNSIGNED(_), Values::HANDLE(_, _)) => panic!("type mismatch: cannot {} values of type `handle` and `number`", $op_name),
             (Values::DECIMAL(s), Values::VOID) => Values::DECIMAL(*s),
             (Values::DECIMAL(s), Values::SIGNED(v)) => Values::DECIMAL(*s $op *v as f64),
             (Values::DECIMAL(s), Values::UNSIGNED(v)) => Values::DECIMAL(*s $op *v as f64),
@@ -96,17 +151,23 @@ macro_rules! math {
             (Values::DECIMAL(_), Values::STRUCT(_, _, _)) => panic!("type mismatch: cannot {} values of type `struct` and `number`", $op_name),
             (Values::DECIMAL(_), Values::TYPE(_)) => panic!("type mismatch: cannot {} values of type `type` and `number`", $op_name),
             (Values::DECIMAL(_), Values::NAME(_)) => panic!("type mismatch: cannot {} values of type `name` and `number`", $op_name),
+            (Values::DECIMAL(_), Values::REFERENCE(_, _, _)) => panic!("type mismatch: cannot {} values of type `reference` and `number`", $op_name),
+            (Values::DECIMAL(_), Values::HANDLE(_, _)) => panic!("type mismatch: cannot {} values of type `handle` and `number`", $op_name),
             (Values::POINTER(p, s), Values::VOID) => Values::POINTER(*p, *s),
-            (Values::POINTER(p, s), Values::SIGNED(v)) => Values::POINTER(*p $op *v as usize, *s),
-            (Values::POINTER(p, s), Values::UNSIGNED(v)) => Values::POINTER(*p $op *v as usize, *s),
-            (Values::POINTER(p, s), Values::DECIMAL(v)) => Values::POINTER(*p $op *v as usize, *s),
-            (Values::POINTER(p, s), Values::POINTER(v, _)) => Values::POINTER(*p $op *v, *s),
+            (Values::POINTER(p, s), Values::SIGNED(v)) => Values::POINTER(int_op!(*p, *v, usize, $wrapping, $checked, $op_name), *s),
+            (Values::POINTER(p, s), Values::UNSIGNED(v)) => Values::POINTER(int_op!(*p, *v, usize, $wrapping, $checked, $op_name), *s),
+            (Values::POINTER(p, s), Values::DECIMAL(v)) => Values::POINTER(int_op!(*p, *v, usize, $wrapping, $checked, $op_name), *s),
+            (Values::POINTER(p, s), Values::POINTER(v, _)) => Values::POINTER(int_op!(*p, *v, usize, $wrapping, $checked, $op_name), *s),
             (Values::POINTER(_, _), Values::STRUCT(_, _, _)) => panic!("type mismatch: cannot {} values of type `struct` and `pointer`", $op_name),
             (Values::POINTER(_, _), Values::TYPE(_)) => panic!("type mismatch: cannot {} values of type `type` and `pointer`", $op_name),
             (Values::POINTER(_, _), Values::NAME(_)) => panic!("type mismatch: cannot {} values of type `name` and `pointer`", $op_name),
+            (Values::POINTER(_, _), Values::REFERENCE(_, _, _)) => panic!("type mismatch: cannot {} values of type `reference` and `pointer`", $op_name),
+            (Values::POINTER(_, _), Values::HANDLE(_, _)) => panic!("type mismatch: cannot {} values of type `handle` and `pointer`", $op_name),
             (Values::STRUCT(_, _, _), _) => panic!("type mismatch: value of type `struct` cannot be {}", $op_plural),
             (Values::TYPE(_), _) => panic!("type mismatch: value of type `type` cannot be {}", $op_plural),
             (Values::NAME(_), _) => panic!("type mismatch: value of type `name` cannot be {}", $op_plural),
+            (Values::REFERENCE(_, _, _), _) => panic!("type mismatch: value of type `reference` cannot be {}", $op_plural),
+            (Values::HANDLE(_, _), _) => panic!("type mismatch: value of type `handle` cannot be {}", $op_plural),
         }
     };
 }
@@ -123,6 +184,8 @@ macro_rules! bitwise {
             (Values::SIGNED(_), Values::STRUCT(_, _, _)) => panic!("type mismatch: cannot {} values of type `struct` and `number`", $op_name),
             (Values::SIGNED(_), Values::TYPE(_)) => panic!("type mismatch: cannot {} values of type `type` and `number`", $op_name),
             (Values::SIGNED(_), Values::NAME(_)) => panic!("type mismatch: cannot {} values of type `name` and `number`", $op_name),
+            (Values::SIGNED(_), Values::REFERENCE(_, _, _)) => panic!("type mismatch: cannot {} values of type `reference` and `number`", $op_name),
+            (Values::SIGNED(_), Values::HANDLE(_, _)) => panic!("type mismatch: cannot {} values of type `handle` and `number`", $op_name),
             (Values::UNSIGNED(s), Values::VOID) => Values::UNSIGNED(*s),
             (Values::UNSIGNED(s), Values::SIGNED(v)) => Values::UNSIGNED(*s $op *v as u64),
             (Values::UNSIGNED(s), Values::UNSIGNED(v)) => Values::UNSIGNED(*s $op *v),
@@ -131,6 +194,8 @@ macro_rules! bitwise {
             (Values::UNSIGNED(_), Values::STRUCT(_, _, _)) => panic!("type mismatch: cannot {} values of type `struct` and `number`", $op_name),
             (Values::UNSIGNED(_), Values::TYPE(_)) => panic!("type mismatch: cannot {} values of type `type` and `number`", $op_name),
             (Values::UNSIGNED(_), Values::NAME(_)) => panic!("type mismatch: cannot {} values of type `name` and `number`", $op_name),
+            (Values::UNSIGNED(_), Values::REFERENCE(_, _, _)) => panic!("type mismatch: cannot {} values of type `reference` and `number`", $op_name),
+            (Values::UNSIGNED(_), Values::HANDLE(_, _)) => panic!("type mismatch: cannot {} values of type `handle` and `number`", $op_name),
             (Values::DECIMAL(s), Values::VOID) => Values::DECIMAL(*s),
             (Values::DECIMAL(s), Values::SIGNED(v)) => Values::DECIMAL(f64::from_bits((f64::to_bits(*s) $op *v as u64))),
             (Values::DECIMAL(s), Values::UNSIGNED(v)) => Values::DECIMAL(f64::from_bits(f64::to_bits(*s) $op *v)),
@@ -139,6 +204,8 @@ macro_rules! bitwise {
             (Values::DECIMAL(_), Values::STRUCT(_, _, _)) => panic!("type mismatch: cannot {} values of type `struct` and `number`", $op_name),
             (Values::DECIMAL(_), Values::TYPE(_)) => panic!("type mismatch: cannot {} values of type `type` and `number`", $op_name),
             (Values::DECIMAL(_), Values::NAME(_)) => panic!("type mismatch: cannot {} values of type `name` and `number`", $op_name),
+            (Values::DECIMAL(_), Values::REFERENCE(_, _, _)) => panic!("type mismatch: cannot {} values of type `reference` and `number`", $op_name),
+            (Values::DECIMAL(_), Values::HANDLE(_, _)) => panic!("type mismatch: cannot {} values of type `handle` and `number`", $op_name),
             (Values::POINTER(p, s), Values::VOID) => Values::POINTER(*p, *s),
             (Values::POINTER(p, s), Values::SIGNED(v)) => Values::POINTER(*p $op *v as usize, *s),
             (Values::POINTER(p, s), Values::UNSIGNED(v)) => Values::POINTER(*p $op *v as usize, *s),
@@ -147,13 +214,19 @@ macro_rules! bitwise {
             (Values::POINTER(_, _), Values::STRUCT(_, _, _)) => panic!("type mismatch: cannot {} values of type `struct` and `pointer`", $op_name),
             (Values::POINTER(_, _), Values::TYPE(_)) => panic!("type mismatch: cannot {} values of type `type` and `pointer`", $op_name),
             (Values::POINTER(_, _), Values::NAME(_)) => panic!("type mismatch: cannot {} values of type `name` and `pointer`", $op_name),
+            (Values::POINTER(_, _), Values::REFERENCE(_, _, _)) => panic!("type mismatch: cannot {} values of type `reference` and `pointer`", $op_name),
+            (Values::POINTER(_, _), Values::HANDLE(_, _)) => panic!("type mismatch: cannot {} values of type `handle` and `pointer`", $op_name),
             (Values::STRUCT(_, _, _), _) => panic!("type mismatch: value of type `struct` cannot be {} to", $op_plural),
             (Values::TYPE(_), _) => panic!("type mismatch: value of type `type` cannot be {} to", $op_plural),
             (Values::NAME(_), _) => panic!("type mismatch: value of type `name` cannot be {} to", $op_plural),
+            (Values::REFERENCE(_, _, _), _) => panic!("type mismatch: value of type `reference` cannot be {} to", $op_plural),
+            (Values::HANDLE(_, _), _) => panic!("type mismatch: value of type `handle` cannot be {} to", $op_plural),
         }
     };
 }
 
+// integers compare by their mathematical value, so a negative signed number is always less than any unsigned one
+// comparisons involving a decimal are done as f64, and NaN compares unequal to everything
 macro_rules! compare {
     ($self:expr, $other:expr, $op:tt) => {
         match($self, $other) {
@@ -161,20 +234,24 @@ macro_rules! compare {
             (Values::VOID, _) => false,
             (Values::SIGNED(_), Values::VOID) => false,
             (Values::SIGNED(s), Values::SIGNED(v)) => *s $op *v,
-            (Values::SIGNED(s), Values::UNSIGNED(v)) => *s $op *v as i64,
-            (Values::SIGNED(s), Values::DECIMAL(v)) => *s $op *v as i64,
-            (Values::SIGNED(s), Values::POINTER(v, _)) => *s $op *v as i64,
+            (Values::SIGNED(s), Values::UNSIGNED(v)) => (*s as i128) $op (*v as i128),
+            (Values::SIGNED(s), Values::DECIMAL(v)) => (*s as f64) $op *v,
+            (Values::SIGNED(s), Values::POINTER(v, _)) => (*s as i128) $op (*v as i128),
             (Values::SIGNED(_), Values::STRUCT(_, _, _)) => false,
             (Values::SIGNED(_), Values::TYPE(_)) => false,
             (Values::SIGNED(_), Values::NAME(_)) => false,
+            (Values::SIGNED(_), Values::REFERENCE(_, _, _)) => false,
+            (Values::SIGNED(s), Values::HANDLE(v, _)) => (*s as i128) $op (*v as i128),
             (Values::UNSIGNED(_), Values::VOID) => false,
-            (Values::UNSIGNED(s), Values::SIGNED(v)) => *s $op *v as u64,
+            (Values::UNSIGNED(s), Values::SIGNED(v)) => (*s as i128) $op (*v as i128),
             (Values::UNSIGNED(s), Values::UNSIGNED(v)) => *s $op *v,
-            (Values::UNSIGNED(s), Values::DECIMAL(v)) => *s $op *v as u64,
+            (Values::UNSIGNED(s), Values::DECIMAL(v)) => (*s as f64) $op *v,
             (Values::UNSIGNED(s), Values::POINTER(v, _)) => *s $op *v as u64,
             (Values::UNSIGNED(_), Values::STRUCT(_, _, _)) => false,
             (Values::UNSIGNED(_), Values::TYPE(_)) => false,
             (Values::UNSIGNED(_), Values::NAME(_)) => false,
+            (Values::UNSIGNED(_), Values::REFERENCE(_, _, _)) => false,
+            (Values::UNSIGNED(s), Values::HANDLE(v, _)) => *s $op *v as u64,
             (Values::DECIMAL(_), Values::VOID) => false,
             (Values::DECIMAL(s), Values::SIGNED(v)) => *s $op *v as f64,
             (Values::DECIMAL(s), Values::UNSIGNED(v)) => *s $op *v as f64,
@@ -183,17 +260,28 @@ macro_rules! compare {
             (Values::DECIMAL(_), Values::STRUCT(_, _, _)) => false,
             (Values::DECIMAL(_), Values::TYPE(_)) => false,
             (Values::DECIMAL(_), Values::NAME(_)) => false,
+            (Values::DECIMAL(_), Values::REFERENCE(_, _, _)) => false,
+            (Values::DECIMAL(_), Values::HANDLE(_, _)) => false,
             (Values::POINTER(_, _), Values::VOID) => false,
-            (Values::POINTER(s, _), Values::SIGNED(v)) => *s $op *v as usize,
+            (Values::POINTER(s, _), Values::SIGNED(v)) => (*s as i128) $op (*v as i128),
             (Values::POINTER(s, _), Values::UNSIGNED(v)) => *s $op *v as usize,
-            (Values::POINTER(s, _), Values::DECIMAL(v)) => *s $op *v as usize,
+            (Values::POINTER(s, _), Values::DECIMAL(v)) => (*s as f64) $op *v,
             (Values::POINTER(s, _), Values::POINTER(v, _)) => *s $op *v,
             (Values::POINTER(_, _), Values::STRUCT(_, _, _)) => false,
             (Values::POINTER(_, _), Values::TYPE(_)) => false,
             (Values::POINTER(_, _), Values::NAME(_)) => false,
+            (Values::POINTER(_, _), Values::REFERENCE(_, _, _)) => false,
+            (Values::POINTER(_, _), Values::HANDLE(_, _)) => false,
             (Values::STRUCT(_, _, _), _) => false,
             (Values::TYPE(_), _) => false,
             (Values::NAME(_), _) => false,
+            (Values::REFERENCE(f, i, s), Values::REFERENCE(of, oi, os)) => (*f, *i, *s) $op (*of, *oi, *os),
+            (Values::REFERENCE(_, _, _), _) => false,
+            // handles can only be compared to each other and to null
+            (Values::HANDLE(s, _), Values::SIGNED(v)) => (*s as i128) $op (*v as i128),
+            (Values::HANDLE(s, _), Values::UNSIGNED(v)) => (*s as u64) $op *v,
+            (Values::HANDLE(s, _), Values::HANDLE(v, _)) => *s $op *v,
+            (Values::HANDLE(_, _), _) => false,
         }
     }
 }
@@ -215,14 +303,26 @@ impl cmp::PartialOrd for Values {
         if greater {
             return Some(Ordering::Greater);
         }
+        if compare!(self, other, ==) {
+            return Some(Ordering::Equal);
+        }
 
-        return Some(Ordering::Equal);
+        return None;
     }
 }
 
-// TODO: actually make sure resulting numbers can fit in the types they're supposed to be
 impl Values {
     pub fn set(&mut self, other: &Values) { // basically auto-type casting
+        // pointer variables can hold either a heap address or a reference to a stack slot
+        match (&*self, other) {
+            (Values::POINTER(_, _) | Values::REFERENCE(_, _, _), Values::REFERENCE(_, _, _)) => {
+                *self = other.clone();
+                return;
+            }
+            (Values::REFERENCE(_, _, _), _) => *self = Values::POINTER(0, 0),
+            _ => (),
+        }
+
         match(self, other) {
             (Values::VOID, _) => (),
             (Values::SIGNED(s), Values::VOID) => *s = 0,
@@ -233,6 +333,8 @@ impl Values {
             (Values::SIGNED(_), Values::STRUCT(_, _, _)) => panic!("type mismatch: attempted to assign value of type `struct` to variable with type `number`"),
             (Values::SIGNED(_), Values::TYPE(_)) => panic!("type mismatch: attempted to assign value of type `type` to variable with type `number`"),
             (Values::SIGNED(_), Values::NAME(_)) => panic!("type mismatch: attempted to assign value of type `name` to variable with type `number`"),
+            (Values::SIGNED(_), Values::REFERENCE(_, _, _)) => panic!("type mismatch: attempted to assign value of type `reference` to variable with type `number`"),
+            (Values::SIGNED(s), Values::HANDLE(v, _)) => *s = *v as i64,
             (Values::UNSIGNED(s), Values::VOID) => *s = 0,
             (Values::UNSIGNED(s), Values::SIGNED(v)) => *s = *v as u64,
             (Values::UNSIGNED(s), Values::UNSIGNED(v)) => *s = *v,
@@ -241,6 +343,8 @@ impl Values {
             (Values::UNSIGNED(_), Values::STRUCT(_, _, _)) => panic!("type mismatch: attempted to assign value of type `struct` to variable with type `number`"),
             (Values::UNSIGNED(_), Values::TYPE(_)) => panic!("type mismatch: attempted to assign value of type `type` to variable with type `number`"),
             (Values::UNSIGNED(_), Values::NAME(_)) => panic!("type mismatch: attempted to assign value of type `name` to variable with type `number`"),
+            (Values::UNSIGNED(_), Values::REFERENCE(_, _, _)) => panic!("type mismatch: attempted to assign value of type `reference` to variable with type `number`"),
+            (Values::UNSIGNED(s), Values::HANDLE(v, _)) => *s = *v as u64,
             (Values::DECIMAL(s), Values::VOID) => *s = 0.0,
             (Values::DECIMAL(s), Values::SIGNED(v)) => *s = *v as f64,
             (Values::DECIMAL(s), Values::UNSIGNED(v)) => *s = *v as f64,
@@ -249,14 +353,18 @@ impl Values {
             (Values::DECIMAL(_), Values::STRUCT(_, _, _)) => panic!("type mismatch: attempted to assign value of type `struct` to variable with type `number`"),
             (Values::DECIMAL(_), Values::TYPE(_)) => panic!("type mismatch: attempted to assign value of type `type` to variable with type `number`"),
             (Values::DECIMAL(_), Values::NAME(_)) => panic!("type mismatch: attempted to assign value of type `name` to variable with type `number`"),
-            (Values::POINTER(p, _), Values::VOID) => *p = 0,
-            (Values::POINTER(p, _), Values::SIGNED(v)) => *p = *v as usize,
-            (Values::POINTER(p, _), Values::UNSIGNED(v)) => *p = *v as usize,
-            (Values::POINTER(p, _), Values::DECIMAL(v)) => *p = *v as usize,
+            (Values::DECIMAL(_), Values::REFERENCE(_, _, _)) => panic!("type mismatch: attempted to assign value of type `reference` to variable with type `number`"),
+            (Values::DECIMAL(_), Values::HANDLE(_, _)) => panic!("type mismatch: attempted to assign value of type `handle` to variable with type `number`"),
+            (Values::POINTER(p, s), Values::VOID) => { *p = 0; *s = 0; }
+            (Values::POINTER(p, s), Values::SIGNED(v)) => { *p = *v as usize; *s = 0; }
+            (Values::POINTER(p, s), Values::UNSIGNED(v)) => { *p = *v as usize; *s = 0; }
+            (Values::POINTER(p, s), Values::DECIMAL(v)) => { *p = *v as usize; *s = 0; }
             (Values::POINTER(p, s), Values::POINTER(v, o)) => { *p = *v; *s = *o; }
             (Values::POINTER(p, _), Values::STRUCT(_, _, v)) => { *p = *v; },
             (Values::POINTER(_, _), Values::TYPE(_)) => panic!("type mismatch: attempted to assign value of type `type` to variable with type `pointer`"),
             (Values::POINTER(_, _), Values::NAME(_)) => panic!("type mismatch: attempted to assign value of type `name` to variable with type `pointer`"),
+            (Values::POINTER(_, _), Values::REFERENCE(_, _, _)) => unreachable!(),
+            (Values::POINTER(_, _), Values::HANDLE(_, _)) => panic!("type mismatch: attempted to assign value of type `handle` to variable with type `pointer`"),
             (Values::STRUCT(_, _, _), Values::VOID) => todo!(),
             (Values::STRUCT(_, _, _), Values::SIGNED(_)) => panic!("type mismatch: attempted to assign value of type `number` to variable with type `struct`"),
             (Values::STRUCT(_, _, _), Values::UNSIGNED(_)) => panic!("type mismatch: attempted to assign value of type `number` to variable with type `struct`"),
@@ -265,6 +373,8 @@ impl Values {
             (Values::STRUCT(m, n, p), Values::STRUCT(om, on, op)) => { *m = om.clone(); *n = on.clone(); *p = *op; },
             (Values::STRUCT(_, _, _), Values::TYPE(_)) => panic!("type mismatch: attempted to assign value of type `type` to variable with type `struct`"),
             (Values::STRUCT(_, _, _), Values::NAME(_)) => panic!("type mismatch: attempted to assign value of type `name` to variable with type `struct`"),
+            (Values::STRUCT(_, _, _), Values::REFERENCE(_, _, _)) => panic!("type mismatch: attempted to assign value of type `reference` to variable with type `struct`"),
+            (Values::STRUCT(_, _, _), Values::HANDLE(_, _)) => panic!("type mismatch: attempted to assign value of type `handle` to variable with type `struct`"),
             (Values::TYPE(_), Values::VOID) => todo!(),
             (Values::TYPE(_), Values::SIGNED(_)) => panic!("type mismatch: attempted to assign value of type `number` to variable with type `type`"),
             (Values::TYPE(_), Values::UNSIGNED(_)) => panic!("type mismatch: attempted to assign value of type `number` to variable with type `type`"),
@@ -273,6 +383,8 @@ impl Values {
             (Values::TYPE(_), Values::STRUCT(_, _, _)) => panic!("type mismatch: attempted to assign value of type `struct` to variable with type `type`"),
             (Values::TYPE(s), Values::TYPE(v)) => *s = v.clone(),
             (Values::TYPE(_), Values::NAME(_)) => panic!("type mismatch: attempted to assign value of type `name` to variable with type `type`"),
+            (Values::TYPE(_), Values::REFERENCE(_, _, _)) => panic!("type mismatch: attempted to assign value of type `reference` to variable with type `type`"),
+            (Values::TYPE(_), Values::HANDLE(_, _)) => panic!("type mismatch: attempted to assign value of type `handle` to variable with type `type`"),
             (Values::NAME(s), Values::VOID) => *s = String::new(),
             (Values::NAME(_), Values::SIGNED(_)) => panic!("type mismatch: attempted to assign value of type `number` to variable with type `name`"),
             (Values::NAME(_), Values::UNSIGNED(_)) => panic!("type mismatch: attempted to assign value of type `number` to variable with type `name`"),
@@ -281,28 +393,325 @@ impl Values {
             (Values::NAME(_), Values::STRUCT(_, _, _)) => panic!("type mismatch: attempted to assign value of type `struct` to variable with type `name`"),
             (Values::NAME(_), Values::TYPE(_)) => panic!("type mismatch: attempted to assign value of type `type` to variable with type `name`"),
             (Values::NAME(s), Values::NAME(v)) => *s = v.clone(),
+            (Values::NAME(_), Values::REFERENCE(_, _, _)) => panic!("type mismatch: attempted to assign value of type `reference` to variable with type `name`"),
+            (Values::NAME(_), Values::HANDLE(_, _)) => panic!("type mismatch: attempted to assign value of type `handle` to variable with type `name`"),
+            (Values::HANDLE(h, _), Values::VOID) => *h = 0,
+            (Values::HANDLE(h, _), Values::SIGNED(0) | Values::UNSIGNED(0)) => *h = 0,
+            (Values::HANDLE(_, _), Values::SIGNED(_) | Values::UNSIGNED(_)) => panic!("type mismatch: attempted to assign a number to variable with type `handle` (only 0, which is null, can be)"),
+            (Values::HANDLE(_, _), Values::DECIMAL(_)) => panic!("type mismatch: attempted to assign value of type `number` to variable with type `handle`"),
+            (Values::HANDLE(_, _), Values::POINTER(_, _)) => panic!("type mismatch: attempted to assign value of type `pointer` to variable with type `handle`"),
+            (Values::HANDLE(_, _), Values::STRUCT(_, _, _)) => panic!("type mismatch: attempted to assign value of type `struct` to variable with type `handle`"),
+            (Values::HANDLE(_, _), Values::TYPE(_)) => panic!("type mismatch: attempted to assign value of type `type` to variable with type `handle`"),
+            (Values::HANDLE(_, _), Values::NAME(_)) => panic!("type mismatch: attempted to assign value of type `name` to variable with type `handle`"),
+            (Values::HANDLE(_, _), Values::REFERENCE(_, _, _)) => panic!("type mismatch: attempted to assign value of type `reference` to variable with type `handle`"),
+            (Values::HANDLE(h, d), Values::HANDLE(v, o)) => {
+                *h = *v;
+                // a variable without a destructor takes the one the handle came with
+                if d.is_empty() && !o.is_empty() {
+                    *d = o.clone();
+                }
+            }
+            (Values::REFERENCE(_, _, _), _) => unreachable!(),
         }    
     }
 
+    // wraps an integer to the width of `typ` and rounds a decimal to its precision
+    pub fn fit(&mut self, typ: &Type) {
+        if typ.typ.len() != 1 {
+            return;
+        }
+
+        match (&typ.typ[0], self) {
+            (Types::I8, Values::SIGNED(s)) => *s = *s as i8 as i64,
+            (Types::I16, Values::SIGNED(s)) => *s = *s as i16 as i64,
+            (Types::I32, Values::SIGNED(s)) => *s = *s as i32 as i64,
+            (Types::U8, Values::UNSIGNED(s)) => *s = *s as u8 as u64,
+            (Types::U16, Values::UNSIGNED(s)) => *s = *s as u16 as u64,
+            (Types::U32, Values::UNSIGNED(s)) => *s = *s as u32 as u64,
+            (Types::F16, Values::DECIMAL(s)) => *s = f16::from_f64(*s).to_f64(),
+            (Types::F32, Values::DECIMAL(s)) => *s = *s as f32 as f64,
+            _ => (),
+        }
+    }
+
+    // checks if this value can be stored in a variable of type `typ` without losing its integer part
+    pub fn fits(&self, typ: &Type) -> bool {
+        if typ.typ.len() != 1 {
+            return true;
+        }
+
+        let (min, max): (i128, i128) = match &typ.typ[0] {
+            Types::I8 => (i8::MIN as i128, i8::MAX as i128),
+            Types::I16 => (i16::MIN as i128, i16::MAX as i128),
+            Types::I32 => (i32::MIN as i128, i32::MAX as i128),
+            Types::I64 => (i64::MIN as i128, i64::MAX as i128),
+            Types::U8 => (0, u8::MAX as i128),
+            Types::U16 => (0, u16::MAX as i128),
+            Types::U32 => (0, u32::MAX as i128),
+            Types::U64 => (0, u64::MAX as i128),
+            _ => return true,
+        };
+
+        return match self {
+            Values::SIGNED(n) => *n as i128 >= min && *n as i128 <= max,
+            Values::UNSIGNED(n) => *n as i128 >= min && *n as i128 <= max,
+            Values::DECIMAL(n) => n.is_finite() && n.trunc() >= min as f64 && n.trunc() <= max as f64,
+            Values::POINTER(n, _) | Values::HANDLE(n, _) => *n as i128 >= min && *n as i128 <= max,
+            _ => true,
+        };
+    }
+
+    fn to_i128(&self, op_name: &str) -> i128 {
+        return match self {
+            Values::SIGNED(n) => *n as i128,
+            Values::UNSIGNED(n) => *n as i128,
+            Values::DECIMAL(n) => *n as i128,
+            Values::POINTER(n, _) => *n as i128,
+            _ => panic!("type mismatch: cannot {} value `{}`", op_name, self),
+        };
+    }
+
+    // performs operation `op` at the width of `typ` with the given overflow behaviour
+    // returns the result and whether the operation overflowed
+    pub fn arith(&self, other: &Values, op: u64, typ: &Type, mode: Overflow) -> (Values, bool) {
+        let op_name = match op {
+            0x00 => "add",
+            0x01 => "subtract",
+            0x02 => "multiply",
+            0x03 => "divide",
+            0x04 => "left shift",
+            _ => panic!("invalid operation `{:#04x}`", op),
+        };
+
+        let int_info = if typ.typ.len() == 1 { typ.typ[0].get_int_info() } else { Some((usize::BITS, false)) };
+        let (bits, signed) = match int_info {
+            Some(info) => info,
+            None => {
+                // decimals don't overflow, so every mode behaves the same
+                let res = match op {
+                    0x00 => self.add(other),
+                    0x01 => self.sub(other),
+                    0x02 => self.mul(other),
+                    0x03 => self.div(other),
+                    _ => panic!("type mismatch: cannot {} a value of type `{}`", op_name, typ),
+                };
+                return (res, false);
+            }
+        };
+
+        let a = self.to_i128(op_name);
+        let b = other.to_i128(op_name);
+
+        let (min, max) = if signed {
+            (-(1i128 << (bits - 1)), (1i128 << (bits - 1)) - 1)
+        } else {
+            (0, (1i128 << bits) - 1)
+        };
+
+        if op == 0x03 && b == 0 {
+            panic!("division by zero: cannot divide `{}` by zero", self);
+        }
+        if op == 0x04 && mode == Overflow::CHECKED && (b < 0 || b >= bits as i128) {
+            panic!("oversized shift: cannot shift a {}-bit value by {}", bits, b);
+        }
+
+        // the mathematically exact result, if it can be represented
+        let exact = match op {
+            0x00 => a.checked_add(b),
+            0x01 => a.checked_sub(b),
+            0x02 => a.checked_mul(b),
+            0x03 => a.checked_div(b),
+            _ => {
+                if b < 0 {
+                    None
+                } else if b >= bits as i128 {
+                    if a == 0 { Some(0) } else { None }
+                } else {
+                    a.checked_mul(1 << b)
+                }
+            }
+        };
+
+        let overflowed = match exact {
+            Some(n) => n < min || n > max,
+            None => true,
+        };
+
+        if overflowed && mode == Overflow::CHECKED {
+            panic!("integer overflow: cannot {} `{}` and `{}` as type `{}`", op_name, self, other, typ);
+        }
+
+        let res = if !overflowed {
+            exact.unwrap()
+        } else if mode == Overflow::SATURATING {
+            let positive = match (op, exact) {
+                (_, Some(n)) => n > 0,
+                (0x02, None) => (a < 0) == (b < 0),
+                (_, None) => a > 0,
+            };
+
+            if positive { max } else { min }
+        } else {
+            let wrapped = match op {
+                0x00 => a.wrapping_add(b),
+                0x01 => a.wrapping_sub(b),
+                0x02 => a.wrapping_mul(b),
+                0x03 => a.wrapping_div(b),
+                _ => a.wrapping_shl(b.rem_euclid(bits as i128) as u32),
+            };
+
+            let wrapped = wrapped & ((1i128 << bits) - 1);
+            if wrapped > max { wrapped - (1i128 << bits) } else { wrapped }
+        };
+
+        if signed {
+            return (Values::SIGNED(res as i64), overflowed);
+        } else {
+            return (Values::UNSIGNED(res as u64), overflowed);
+        }
+    }
+
+    // converts this value of type `from` to type `to`, used by the CAST instruction
+    // modes: 0x00 convert with truncation, 0x01 convert with rounding, 0x02 convert with saturation,
+    //        0x03 reinterpret bits, 0x04 convert between pointers and integers
+    pub fn cast(&self, from: &Type, to: &Type, mode: u64) -> Values {
+        let to_pointer = matches!(to.typ[0], Types::POINTER);
+        let from_pointer = matches!(from.typ[0], Types::POINTER) || matches!(self, Values::POINTER(_, _));
+
+        match mode {
+            0x00..=0x02 => {
+                if to_pointer || from_pointer {
+                    panic!("cannot convert `{}` to type `{}`, pointers can only be cast with mode 0x03 or 0x04", self, to);
+                }
+
+                match to.typ[0].get_int_info() {
+                    Some((bits, signed)) => {
+                        let n = match self {
+                            Values::SIGNED(n) => *n as i128,
+                            Values::UNSIGNED(n) => *n as i128,
+                            Values::DECIMAL(n) => {
+                                // there is nothing to truncate or round an infinity or NaN to, they can only be clamped
+                                if !n.is_finite() && mode != 0x02 {
+                                    panic!("cannot convert {} to type `{}`, only mode 0x02 converts infinities and NaN", self, to);
+                                }
+
+                                if n.is_nan() {
+                                    0
+                                } else if mode == 0x01 {
+                                    n.round_ties_even() as i128
+                                } else {
+                                    n.trunc() as i128
+                                }
+                            }
+                            _ => panic!("type mismatch: cannot convert `{}` to type `{}`", self, to),
+                        };
+
+                        let (min, max) = if signed {
+                            (-(1i128 << (bits - 1)), (1i128 << (bits - 1)) - 1)
+                        } else {
+                            (0, (1i128 << bits) - 1)
+                        };
+
+                        let res = if mode == 0x02 {
+                            n.clamp(min, max)
+                        } else {
+                            let wrapped = n & ((1i128 << bits) - 1);
+                            if wrapped > max { wrapped - (1i128 << bits) } else { wrapped }
+                        };
+
+                        if signed {
+                            return Values::SIGNED(res as i64);
+                        } else {
+                            return Values::UNSIGNED(res as u64);
+                        }
+                    }
+                    None => {
+                        let mut res = match self {
+                            Values::SIGNED(n) => Values::DECIMAL(*n as f64),
+                            Values::UNSIGNED(n) => Values::DECIMAL(*n as f64),
+                            Values::DECIMAL(n) => Values::DECIMAL(*n),
+                            _ => panic!("type mismatch: cannot convert `{}` to type `{}`", self, to),
+                        };
+
+                        match to.typ[0] {
+                            Types::F16 | Types::F32 | Types::F64 => res.fit(to),
+                            _ => panic!("type mismatch: cannot convert `{}` to type `{}`", self, to),
+                        }
+
+                        return res;
+                    }
+                }
+            }
+            0x03 => {
+                let from_size = from.get_size();
+                let to_size = to.get_size();
+                if from_size != to_size || from_size == 0 {
+                    panic!("cannot reinterpret `{}` as `{}`, the types have different sizes", from, to);
+                }
+
+                let bits = match (&from.typ[0], self) {
+                    (Types::F16, Values::DECIMAL(n)) => f16::from_f64(*n).to_bits() as u64,
+                    (Types::F32, Values::DECIMAL(n)) => (*n as f32).to_bits() as u64,
+                    (Types::F64, Values::DECIMAL(n)) => n.to_bits(),
+                    (_, Values::SIGNED(n)) => *n as u64,
+                    (_, Values::UNSIGNED(n)) => *n,
+                    (_, Values::POINTER(n, _)) => *n as u64,
+                    _ => panic!("type mismatch: cannot reinterpret `{}` as `{}`", self, to),
+                };
+
+                return match &to.typ[0] {
+                    Types::F16 => Values::DECIMAL(f16::from_bits(bits as u16).to_f64()),
+                    Types::F32 => Values::DECIMAL(f32::from_bits(bits as u32) as f64),
+                    Types::F64 => Values::DECIMAL(f64::from_bits(bits)),
+                    Types::I8 => Values::SIGNED(bits as i8 as i64),
+                    Types::I16 => Values::SIGNED(bits as i16 as i64),
+                    Types::I32 => Values::SIGNED(bits as i32 as i64),
+                    Types::I64 => Values::SIGNED(bits as i64),
+                    Types::U8 => Values::UNSIGNED(bits as u8 as u64),
+                    Types::U16 => Values::UNSIGNED(bits as u16 as u64),
+                    Types::U32 => Values::UNSIGNED(bits as u32 as u64),
+                    Types::U64 => Values::UNSIGNED(bits),
+                    Types::POINTER => Values::POINTER(bits as usize, 0),
+                    _ => panic!("type mismatch: cannot reinterpret `{}` as `{}`", self, to),
+                };
+            }
+            0x04 => {
+                return match (self, to_pointer) {
+                    (Values::POINTER(p, _), false) => {
+                        match to.typ[0].get_int_info() {
+                            Some((_, true)) => Values::SIGNED(*p as i64),
+                            Some((_, false)) => Values::UNSIGNED(*p as u64),
+                            None => panic!("cannot convert a pointer to type `{}`", to),
+                        }
+                    }
+                    (Values::SIGNED(n), true) => Values::POINTER(*n as usize, 0),
+                    (Values::UNSIGNED(n), true) => Values::POINTER(*n as usize, 0),
+                    _ => panic!("cannot convert `{}` to type `{}`, mode 0x04 converts between pointers and integers", self, to),
+                };
+            }
+            _ => panic!("invalid mode `{:#04x}` passed to `CAST` instruction", mode),
+        }
+    }
+
     // math operations
     pub fn add(&self, other: &Values) -> Values {
-        math!(self, other, +, "add", "added");
+        math!(self, other, +, wrapping_add, checked_add, "add", "added");
     }
 
     pub fn sub(&self, other: &Values) -> Values {
-        math!(self, other, -, "subtract", "subtracted");
+        math!(self, other, -, wrapping_sub, checked_sub, "subtract", "subtracted");
     }
     
     pub fn mul(&self, other: &Values) -> Values {
-        math!(self, other, *, "subtract", "subtracted");
+        math!(self, other, *, wrapping_mul, checked_mul, "multiply", "multiplied");
     }
     
     pub fn div(&self, other: &Values) -> Values {
-        math!(self, other, /, "divide", "divided");
+        math!(self, other, /, wrapping_div, checked_div, "divide", "divided");
     }
 
     pub fn modulo(&self, other: &Values) -> Values {
-        math!(self, other, %, "modulo", "modulo");
+        math!(self, other, %, wrapping_rem, checked_rem, "modulo", "modulo");
     }
 
     // bitwise operations
@@ -328,6 +737,8 @@ impl Values {
             Values::STRUCT(_, _, _) => panic!("type mismatch: cannot NOT a value with type `struct`"),
             Values::TYPE(_) => panic!("type mismatch: cannot NOT a value with type `type`"),
             Values::NAME(_) => panic!("type mismatch: cannot NOT a value with type `name`"),
+            Values::REFERENCE(_, _, _) => panic!("type mismatch: cannot NOT a value with type `reference`"),
+            Values::HANDLE(_, _) => panic!("type mismatch: cannot NOT a value with type `handle`"),
         }
     }
 
@@ -339,3 +750,226 @@ impl Values {
         bitwise!(self, other, >>, "right shift", "right shifted");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn var(typ: Types) -> Value {
+        let val = match typ {
+            Types::I8 | Types::I16 | Types::I32 | Types::I64 => Values::SIGNED(0),
+            Types::F16 | Types::F32 | Types::F64 => Values::DECIMAL(0.0),
+            _ => Values::UNSIGNED(0),
+        };
+
+        Value { typ: Type { typ: vec![typ] }, val }
+    }
+
+    #[test]
+    fn wrap_to_width() {
+        let mut val = var(Types::U8);
+        val.set(&Values::UNSIGNED(256));
+        assert!(matches!(val.val, Values::UNSIGNED(0)));
+        val.set(&Values::SIGNED(-1));
+        assert!(matches!(val.val, Values::UNSIGNED(255)));
+
+        let mut val = var(Types::I8);
+        val.set(&Values::SIGNED(200));
+        assert!(matches!(val.val, Values::SIGNED(-56)));
+        val.set(&Values::UNSIGNED(128));
+        assert!(matches!(val.val, Values::SIGNED(-128)));
+
+        let mut val = var(Types::U32);
+        val.set(&Values::DECIMAL(3.9));
+        assert!(matches!(val.val, Values::UNSIGNED(3)));
+    }
+
+    #[test]
+    fn round_to_float_width() {
+        let mut val = var(Types::F32);
+        val.set(&Values::DECIMAL(0.1));
+        assert!(matches!(val.val, Values::DECIMAL(n) if n == 0.1f32 as f64 && n != 0.1));
+
+        let mut val = var(Types::F16);
+        val.set(&Values::DECIMAL(1.0 + 1.0 / 4096.0));
+        assert!(matches!(val.val, Values::DECIMAL(1.0)));
+
+        let mut val = var(Types::F64);
+        val.set(&Values::DECIMAL(0.1));
+        assert!(matches!(val.val, Values::DECIMAL(0.1)));
+    }
+
+    #[test]
+    fn fits() {
+        let u8 = Type { typ: vec![Types::U8] };
+        let i64 = Type { typ: vec![Types::I64] };
+
+        assert!(Values::UNSIGNED(255).fits(&u8));
+        assert!(!Values::UNSIGNED(256).fits(&u8));
+        assert!(!Values::SIGNED(-1).fits(&u8));
+        assert!(Values::DECIMAL(255.9).fits(&u8));
+        assert!(!Values::DECIMAL(f64::NAN).fits(&u8));
+        assert!(!Values::UNSIGNED(u64::MAX).fits(&i64));
+    }
+
+    #[test]
+    fn compare_mixed() {
+        assert!(Values::SIGNED(-1) < Values::UNSIGNED(1));
+        assert!(Values::UNSIGNED(u64::MAX) > Values::SIGNED(i64::MAX));
+        assert!(Values::SIGNED(-1) != Values::UNSIGNED(u64::MAX));
+        assert!(Values::UNSIGNED(3) == Values::SIGNED(3));
+        assert!(Values::SIGNED(-2) < Values::DECIMAL(-1.5));
+        assert!(Values::DECIMAL(2.0) == Values::UNSIGNED(2));
+
+        assert_eq!(Values::DECIMAL(f64::NAN).partial_cmp(&Values::DECIMAL(f64::NAN)), None);
+        assert_eq!(Values::DECIMAL(f64::NAN).partial_cmp(&Values::SIGNED(0)), None);
+        assert_eq!(Values::UNSIGNED(0).partial_cmp(&Values::DECIMAL(f64::NAN)), None);
+        assert!(Values::DECIMAL(f64::NAN) != Values::DECIMAL(f64::NAN));
+    }
+
+    #[test]
+    fn handle_into_number() {
+        let mut val = Values::UNSIGNED(0);
+        val.set(&Values::HANDLE(0x1234, String::from("fclose")));
+        assert!(matches!(val, Values::UNSIGNED(0x1234)));
+
+        let mut val = Values::SIGNED(0);
+        val.set(&Values::HANDLE(0x1234, String::new()));
+        assert!(matches!(val, Values::SIGNED(0x1234)));
+    }
+
+    #[test]
+    fn handle_keeps_its_destructor() {
+        let mut val = Values::HANDLE(0, String::from("close"));
+        val.set(&Values::HANDLE(1, String::from("fclose")));
+        assert!(matches!(&val, Values::HANDLE(1, d) if d == "close"));
+
+        let mut val = Values::HANDLE(0, String::new());
+        val.set(&Values::HANDLE(1, String::from("fclose")));
+        assert!(matches!(&val, Values::HANDLE(1, d) if d == "fclose"));
+    }
+
+    #[test]
+    fn overflow_flag() {
+        let u8 = Type { typ: vec![Types::U8] };
+        let i8 = Type { typ: vec![Types::I8] };
+
+        let (res, overflowed) = Values::UNSIGNED(200).arith(&Values::UNSIGNED(100), 0x00, &u8, Overflow::FLAG);
+        assert!(matches!(res, Values::UNSIGNED(44)) && overflowed);
+
+        let (res, overflowed) = Values::UNSIGNED(200).arith(&Values::UNSIGNED(55), 0x00, &u8, Overflow::FLAG);
+        assert!(matches!(res, Values::UNSIGNED(255)) && !overflowed);
+
+        let (res, overflowed) = Values::SIGNED(-128).arith(&Values::SIGNED(1), 0x01, &i8, Overflow::FLAG);
+        assert!(matches!(res, Values::SIGNED(127)) && overflowed);
+
+        let (res, overflowed) = Values::SIGNED(-128).arith(&Values::SIGNED(-1), 0x03, &i8, Overflow::FLAG);
+        assert!(matches!(res, Values::SIGNED(-128)) && overflowed);
+
+        // the shift amount wraps like the result does, but shifting the bits out still overflows
+        let (res, overflowed) = Values::UNSIGNED(1).arith(&Values::UNSIGNED(8), 0x04, &u8, Overflow::FLAG);
+        assert!(matches!(res, Values::UNSIGNED(1)) && overflowed);
+    }
+
+    #[test]
+    fn saturating_and_wrapping() {
+        let i8 = Type { typ: vec![Types::I8] };
+
+        let (res, _) = Values::SIGNED(100).arith(&Values::SIGNED(100), 0x00, &i8, Overflow::SATURATING);
+        assert!(matches!(res, Values::SIGNED(127)));
+
+        let (res, _) = Values::SIGNED(-100).arith(&Values::SIGNED(2), 0x02, &i8, Overflow::SATURATING);
+        assert!(matches!(res, Values::SIGNED(-128)));
+
+        let (res, _) = Values::SIGNED(100).arith(&Values::SIGNED(100), 0x00, &i8, Overflow::WRAPPING);
+        assert!(matches!(res, Values::SIGNED(-56)));
+    }
+
+    #[test]
+    #[should_panic(expected = "integer overflow")]
+    fn checked_overflow() {
+        let u16 = Type { typ: vec![Types::U16] };
+        Values::UNSIGNED(0).arith(&Values::UNSIGNED(1), 0x01, &u16, Overflow::CHECKED);
+    }
+
+    #[test]
+    fn infinity_into_integer() {
+        let mut val = Value { typ: Type { typ: vec![Types::I8] }, val: Values::SIGNED(1) };
+        val.set(&Values::DECIMAL(f64::INFINITY));
+        assert!(matches!(val.val, Values::SIGNED(127)));
+        val.set(&Values::DECIMAL(f64::NEG_INFINITY));
+        assert!(matches!(val.val, Values::SIGNED(-128)));
+        val.set(&Values::DECIMAL(f64::NAN));
+        assert!(matches!(val.val, Values::SIGNED(0)));
+    }
+
+    fn typ(typ: Types) -> Type {
+        Type { typ: vec![typ] }
+    }
+
+    #[test]
+    fn cast_truncating() {
+        assert!(matches!(Values::DECIMAL(300.7).cast(&typ(Types::F64), &typ(Types::U8), 0x00), Values::UNSIGNED(44)));
+        assert!(matches!(Values::DECIMAL(-1.9).cast(&typ(Types::F64), &typ(Types::I8), 0x00), Values::SIGNED(-1)));
+        assert!(matches!(Values::SIGNED(-1).cast(&typ(Types::I64), &typ(Types::U16), 0x00), Values::UNSIGNED(65535)));
+        assert!(matches!(Values::UNSIGNED(200).cast(&typ(Types::U64), &typ(Types::I8), 0x00), Values::SIGNED(-56)));
+        assert!(matches!(Values::SIGNED(-3).cast(&typ(Types::I64), &typ(Types::F32), 0x00), Values::DECIMAL(-3.0)));
+    }
+
+    #[test]
+    fn cast_rounding() {
+        let round = |n: f64| Values::DECIMAL(n).cast(&typ(Types::F64), &typ(Types::I32), 0x01);
+
+        assert!(matches!(round(2.5), Values::SIGNED(2)));
+        assert!(matches!(round(3.5), Values::SIGNED(4)));
+        assert!(matches!(round(-2.5), Values::SIGNED(-2)));
+        assert!(matches!(round(2.6), Values::SIGNED(3)));
+
+        // rounding happens before wrapping
+        assert!(matches!(Values::DECIMAL(255.5).cast(&typ(Types::F64), &typ(Types::U8), 0x01), Values::UNSIGNED(0)));
+    }
+
+    #[test]
+    fn cast_saturating() {
+        assert!(matches!(Values::DECIMAL(300.7).cast(&typ(Types::F64), &typ(Types::U8), 0x02), Values::UNSIGNED(255)));
+        assert!(matches!(Values::SIGNED(-5).cast(&typ(Types::I64), &typ(Types::U8), 0x02), Values::UNSIGNED(0)));
+        assert!(matches!(Values::UNSIGNED(u64::MAX).cast(&typ(Types::U64), &typ(Types::I64), 0x02), Values::SIGNED(i64::MAX)));
+        assert!(matches!(Values::DECIMAL(f64::INFINITY).cast(&typ(Types::F64), &typ(Types::I16), 0x02), Values::SIGNED(32767)));
+        assert!(matches!(Values::DECIMAL(f64::NEG_INFINITY).cast(&typ(Types::F64), &typ(Types::I16), 0x02), Values::SIGNED(-32768)));
+        assert!(matches!(Values::DECIMAL(f64::NAN).cast(&typ(Types::F64), &typ(Types::U32), 0x02), Values::UNSIGNED(0)));
+    }
+
+    #[test]
+    fn cast_reinterpreting() {
+        assert!(matches!(Values::DECIMAL(1.0).cast(&typ(Types::F32), &typ(Types::U32), 0x03), Values::UNSIGNED(0x3f80_0000)));
+        assert!(matches!(Values::UNSIGNED(0x3f80_0000).cast(&typ(Types::U32), &typ(Types::F32), 0x03), Values::DECIMAL(1.0)));
+        assert!(matches!(Values::DECIMAL(-2.0).cast(&typ(Types::F16), &typ(Types::I16), 0x03), Values::SIGNED(-16384)));
+        assert!(matches!(Values::SIGNED(-16384).cast(&typ(Types::I16), &typ(Types::F16), 0x03), Values::DECIMAL(-2.0)));
+        assert!(matches!(Values::SIGNED(-1).cast(&typ(Types::I64), &typ(Types::U64), 0x03), Values::UNSIGNED(u64::MAX)));
+
+        // 0.1 has no exact f32, what comes back is the f32 nearest to it
+        let bits = Values::DECIMAL(0.1).cast(&typ(Types::F32), &typ(Types::I32), 0x03);
+        assert!(matches!(bits.cast(&typ(Types::I32), &typ(Types::F32), 0x03), Values::DECIMAL(n) if n == 0.1f32 as f64));
+    }
+
+    #[test]
+    #[should_panic(expected = "different sizes")]
+    fn cast_reinterpreting_different_sizes() {
+        Values::DECIMAL(1.0).cast(&typ(Types::F32), &typ(Types::U64), 0x03);
+    }
+
+    #[test]
+    fn cast_pointers() {
+        let ptr = Type { typ: vec![Types::POINTER, Types::U8] };
+
+        assert!(matches!(Values::POINTER(0x1234, 0x1200).cast(&ptr, &typ(Types::U64), 0x04), Values::UNSIGNED(0x1234)));
+        assert!(matches!(Values::POINTER(0x1234, 0x1200).cast(&ptr, &typ(Types::I64), 0x04), Values::SIGNED(0x1234)));
+        assert!(matches!(Values::UNSIGNED(0x1234).cast(&typ(Types::U64), &ptr, 0x04), Values::POINTER(0x1234, 0)));
+    }
+
+    #[test]
+    #[should_panic(expected = "only mode 0x02")]
+    fn cast_infinity_truncating() {
+        Values::DECIMAL(f64::INFINITY).cast(&typ(Types::F64), &typ(Types::U32), 0x00);
+    }
+}
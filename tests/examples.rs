@@ -0,0 +1,47 @@
+use std::process::{Command, Output};
+
+// runs an example the way the readme does, from the repository root with the core library linked
+fn run(example: &str, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_rainbow"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .args(["-l", "core", &format!("examples/{example}.rbb")])
+        .args(args)
+        .output()
+        .expect("failed to run rainbow")
+}
+
+fn exit_code(output: &Output) -> i32 {
+    output.status.code().expect("rainbow was killed by a signal")
+}
+
+#[test]
+fn references() {
+    let output = run("references", &[]);
+    assert_eq!(exit_code(&output), 57, "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+fn qsort() {
+    let output = run("qsort", &[]);
+    assert_eq!(exit_code(&output), 0, "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+fn net() {
+    let output = run("net", &[]);
+    assert_eq!(exit_code(&output), 0, "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+fn files() {
+    let output = run("files", &[]);
+    assert_eq!(exit_code(&output), 0, "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+// spawns `sh`
+#[cfg(unix)]
+#[test]
+fn processes() {
+    let output = run("processes", &["hello"]);
+    assert_eq!(exit_code(&output), 0, "{}", String::from_utf8_lossy(&output.stderr));
+}